@@ -3,7 +3,7 @@ mod types;
 mod validation;
 
 use candid::Principal;
-use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use ic_cdk_macros::{heartbeat, init, post_upgrade, pre_upgrade, query, update};
 
 pub use state::{State, StableState, STATE};
 pub use types::*;
@@ -16,6 +16,9 @@ pub use validation::*;
 #[init]
 fn init(controllers: Option<Vec<Principal>>) {
     let effective_controllers = controllers.unwrap_or_else(|| vec![ic_cdk::caller()]);
+    if let Some(err) = effective_controllers.iter().find_map(|p| validate_grantable_principal(p).err()) {
+        ic_cdk::trap(&err);
+    }
     STATE.with(|state| {
         let mut s = state.borrow_mut();
         s.controllers = effective_controllers.clone();
@@ -53,21 +56,68 @@ fn post_upgrade() {
         *state.borrow_mut() = restored_state;
     });
 
-    // FOS-5.6.10: Migrate ownership for records created before row-level security
-    STATE.with(|state| {
-        state.borrow_mut().migrate_ownership();
-    });
+    // Run pending data migrations (e.g. FOS-5.6.10 ownership) exactly once
+    let applied = STATE.with(|state| state.borrow_mut().run_migrations());
+    ic_cdk::println!("Applied {} data migrations", applied);
+
+    // Report deals quarantined by the restore integrity pass
+    let orphaned = STATE.with(|state| state.borrow().orphaned_deals.len());
+    if orphaned > 0 {
+        ic_cdk::println!("Warning: {} orphaned deals quarantined (missing contact)", orphaned);
+    }
 
     ic_cdk::println!("===========================================");
     ic_cdk::println!("DAO Admin Upgrade Complete");
     ic_cdk::println!("===========================================");
 }
 
+/// Periodic housekeeping; each job throttles itself so most beats are no-ops
+/// Jobs return before allocating anything when they are switched off or not yet due
+#[heartbeat]
+fn heartbeat() {
+    let now = ic_cdk::api::time();
+
+    let churned = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        // Churning writes audit entries, so also wait while the audit log is blocked on export
+        if s.auto_churn_after_secs.is_none() || s.audit_log_full() {
+            return Vec::new();
+        }
+        let churned = s.run_auto_churn(now);
+        if churned.is_empty() {
+            return churned;
+        }
+
+        let canister = ic_cdk::id();
+        let details = serde_json::json!({ "after_secs": s.auto_churn_after_secs }).to_string();
+        for id in &churned {
+            s.record_audit_log(
+                canister,
+                AuditAction::AutoChurnContact,
+                "contact",
+                &id.to_string(),
+                Some(details.clone()),
+            );
+        }
+        churned
+    });
+
+    if !churned.is_empty() {
+        ic_cdk::println!("Auto-churned {} inactive contacts", churned.len());
+    }
+
+    let problems = STATE.with(|state| state.borrow_mut().run_periodic_invariant_check(now));
+    for problem in problems.unwrap_or_default() {
+        ic_cdk::println!("Warning: state invariant violated: {}", problem);
+    }
+}
+
 // =============================================================================
 // Access Control
 // =============================================================================
 
-async fn require_controller() -> Result<(), String> {
+/// Verify caller is a controller; denials are recorded under `action`
+async fn require_controller(action: &str) -> Result<(), String> {
     let caller = ic_cdk::caller();
 
     let is_authorized = STATE.with(|state| state.borrow().is_controller(&caller));
@@ -79,32 +129,92 @@ async fn require_controller() -> Result<(), String> {
             canister_id: ic_cdk::id(),
         })
         .await
-        .map_err(|(code, msg)| format!("Failed to query canister status: {:?}: {}", code, msg))?
+        .map_err(|(code, msg)| {
+            // The caller could not be verified, so this is still a denial
+            record_access_denied(caller, action, "controller");
+            format!("Failed to query canister status: {:?}: {}", code, msg)
+        })?
         .0;
 
-        if !status.settings.controllers.contains(&caller) {
+        // The anonymous principal is never treated as a controller, even if the IC lists it
+        if caller == Principal::anonymous() || !status.settings.controllers.contains(&caller) {
+            record_access_denied(caller, action, "controller");
             return Err("Unauthorized: Only controllers can perform this action".to_string());
         }
 
         STATE.with(|state| {
-            state.borrow_mut().controllers = status.settings.controllers;
+            state.borrow_mut().controllers = status
+                .settings
+                .controllers
+                .into_iter()
+                .filter(|p| *p != Principal::anonymous())
+                .collect();
         });
     }
 
     Ok(())
 }
 
-fn require_admin() -> Result<(), String> {
+/// Synchronous controller check for query endpoints, which cannot make the
+/// management canister call that `require_controller` falls back to
+fn require_controller_query() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let is_authorized = caller != Principal::anonymous()
+        && (ic_cdk::api::is_controller(&caller) || STATE.with(|state| state.borrow().is_controller(&caller)));
+
+    if is_authorized {
+        Ok(())
+    } else {
+        Err("Unauthorized: Only controllers can perform this action".to_string())
+    }
+}
+
+/// Reject admin writes while maintenance mode is on; controllers are exempt
+/// so they can run migrations and imports during the window
+fn require_not_maintenance() -> Result<(), String> {
     let caller = ic_cdk::caller();
     STATE.with(|state| {
-        if state.borrow().is_admin(&caller) {
-            Ok(())
+        let s = state.borrow();
+        if s.maintenance_mode && !s.is_controller(&caller) {
+            Err("Canister in maintenance mode".to_string())
         } else {
-            Err("Unauthorized: Admin access required".to_string())
+            Ok(())
         }
     })
 }
 
+/// Reject audited writes while `block_on_export` holds back an audit log overflow,
+/// with a clean error instead of the trap `record_audit_log` would hit
+fn require_audit_capacity() -> Result<(), String> {
+    STATE.with(|state| state.borrow().check_audit_capacity())
+}
+
+/// Verify caller is an admin; denials are recorded under `action`
+fn require_admin(action: &str) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if STATE.with(|state| state.borrow().is_admin(&caller)) {
+        Ok(())
+    } else {
+        record_access_denied(caller, action, "admin");
+        Err("Unauthorized: Admin access required".to_string())
+    }
+}
+
+/// Record a rejected access attempt so privilege-escalation probes are visible
+/// Denials go to the bounded `access_denials` buffer, not the audit log, so
+/// unauthenticated callers cannot evict audit history or trip `block_on_export`
+/// (State changes made during query calls are discarded, so only updates leave a trail)
+fn record_access_denied(caller: Principal, action: &str, required: &str) {
+    STATE.with(|state| {
+        state.borrow_mut().record_access_denial(AccessDenial {
+            timestamp: ic_cdk::api::time(),
+            caller,
+            action: action.to_string(),
+            required: required.to_string(),
+        });
+    });
+}
+
 /// Verify caller is an authorized canister for the given role
 /// @see AC-5.6.8.3 - Inter-canister call verification
 fn require_authorized_canister(role: &str) -> Result<(), String> {
@@ -148,7 +258,8 @@ fn require_authorized_canister_or_admin(roles: &[&str]) -> Result<(), String> {
 
 #[update]
 async fn add_admin(principal: Principal) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("add_admin").await?;
+    validate_grantable_principal(&principal)?;
 
     STATE.with(|state| {
         state.borrow_mut().add_admin(principal);
@@ -160,7 +271,7 @@ async fn add_admin(principal: Principal) -> Result<(), String> {
 
 #[update]
 async fn remove_admin(principal: Principal) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("remove_admin").await?;
 
     STATE.with(|state| {
         state.borrow_mut().remove_admin(&principal);
@@ -172,7 +283,7 @@ async fn remove_admin(principal: Principal) -> Result<(), String> {
 
 #[query]
 fn get_admins() -> Result<Vec<Principal>, String> {
-    require_admin()?;
+    require_admin("get_admins")?;
     Ok(STATE.with(|state| state.borrow().admins.clone()))
 }
 
@@ -184,20 +295,43 @@ fn get_admins() -> Result<Vec<Principal>, String> {
 /// @see AC-5.6.8.3 - Inter-canister call verification
 #[update]
 async fn register_authorized_canister(role: String, canister_id: Principal) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("register_authorized_canister").await?;
+    validate_grantable_principal(&canister_id)?;
 
     STATE.with(|state| {
-        state.borrow_mut().register_authorized_canister(role.clone(), canister_id);
-    });
+        state.borrow_mut().register_authorized_canister(role.clone(), canister_id)
+    })?;
 
     ic_cdk::println!("Authorized canister registered: {} = {}", role, canister_id);
     Ok(())
 }
 
+/// Allow a new role for `register_authorized_canister` beyond the built-in set (controller only)
+#[update]
+async fn add_canister_role(role: String) -> Result<(), String> {
+    require_controller("add_canister_role").await?;
+    let caller = ic_cdk::caller();
+
+    let role = role.trim().to_string();
+    if role.is_empty() {
+        return Err("Role cannot be empty".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        if s.add_canister_role(role.clone()) {
+            s.record_audit_log(caller, AuditAction::AddCanisterRole, "canister_role", &role, None);
+        }
+    });
+
+    ic_cdk::println!("Canister role added: {}", role);
+    Ok(())
+}
+
 /// Unregister an authorized canister
 #[update]
 async fn unregister_authorized_canister(role: String) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("unregister_authorized_canister").await?;
 
     STATE.with(|state| {
         state.borrow_mut().unregister_authorized_canister(&role);
@@ -210,7 +344,7 @@ async fn unregister_authorized_canister(role: String) -> Result<(), String> {
 /// List all authorized canisters (admin only)
 #[query]
 fn list_authorized_canisters() -> Result<Vec<(String, Principal)>, String> {
-    require_admin()?;
+    require_admin("list_authorized_canisters")?;
     Ok(STATE.with(|state| state.borrow().get_authorized_canisters()))
 }
 
@@ -222,7 +356,8 @@ fn list_authorized_canisters() -> Result<Vec<(String, Principal)>, String> {
 /// @see AC-5.6.10.3 - Granular CRUD permissions
 #[update]
 async fn grant_permission(principal: Principal, permission: AdminPermission) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("grant_permission").await?;
+    validate_grantable_principal(&principal)?;
 
     STATE.with(|state| {
         state.borrow_mut().grant_permission(principal, permission.clone());
@@ -236,7 +371,7 @@ async fn grant_permission(principal: Principal, permission: AdminPermission) ->
 /// @see AC-5.6.10.3 - Granular CRUD permissions
 #[update]
 async fn revoke_permission(principal: Principal, permission: AdminPermission) -> Result<(), String> {
-    require_controller().await?;
+    require_controller("revoke_permission").await?;
 
     STATE.with(|state| {
         state.borrow_mut().revoke_permission(&principal, &permission);
@@ -269,11 +404,19 @@ fn get_permissions(principal: Option<Principal>) -> Result<Vec<AdminPermission>,
     })
 }
 
+/// List every admin with their permissions in one call (controller only)
+/// Controllers are reported with the full permission set
+#[query]
+fn list_admins_with_permissions() -> Result<Vec<(Principal, Vec<AdminPermission>)>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| state.borrow().admins_with_permissions()))
+}
+
 /// Grant default permissions to all admins (controller only)
 /// Call after upgrade to ensure all admins have basic permissions
 #[update]
 async fn grant_default_permissions_to_all_admins() -> Result<u32, String> {
-    require_controller().await?;
+    require_controller("grant_default_permissions_to_all_admins").await?;
 
     let count = STATE.with(|state| {
         let mut s = state.borrow_mut();
@@ -291,252 +434,1263 @@ async fn grant_default_permissions_to_all_admins() -> Result<u32, String> {
 }
 
 // =============================================================================
-// Audit Log API (FOS-5.6.10)
+// Configuration (controller-managed)
 // =============================================================================
 
-/// Get audit log entries
-/// @see AC-5.6.10.4, AC-5.6.10.5 - Audit logging
-#[query]
-fn get_audit_log(
-    action_filter: Option<String>,
-    target_type_filter: Option<String>,
-    actor_filter: Option<Principal>,
-    limit: Option<u64>,
-) -> Result<Vec<AuditLogEntry>, String> {
-    require_admin()?;
-
+/// Enable or disable plus-address normalization for email dedup (controller only)
+/// When on, `john+crm@gmail.com` and `john@gmail.com` index as the same contact
+/// Fails if existing contacts would then share an email
+#[update]
+async fn set_normalize_plus_addressing(enabled: bool) -> Result<(), String> {
+    require_controller("set_normalize_plus_addressing").await?;
     let caller = ic_cdk::caller();
 
     STATE.with(|state| {
-        let s = state.borrow();
-
-        // Check ViewAuditLogs permission
-        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs) && !s.is_controller(&caller) {
-            return Err("Unauthorized: ViewAuditLogs permission required".to_string());
-        }
+        let mut s = state.borrow_mut();
+        let old = s.normalize_plus_addressing;
+        s.set_normalize_plus_addressing(enabled)?;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetNormalizePlusAddressing,
+            "config",
+            "normalize_plus_addressing",
+            Some(serde_json::json!({ "old": old, "new": enabled }).to_string()),
+        );
+        Ok::<(), String>(())
+    })?;
 
-        Ok(s.get_audit_log(
-            action_filter.as_deref(),
-            target_type_filter.as_deref(),
-            actor_filter.as_ref(),
-            limit,
-        ))
-    })
+    ic_cdk::println!("Plus-address normalization set to {}", enabled);
+    Ok(())
 }
 
-// =============================================================================
-// Contact API
-// =============================================================================
-
-/// Create a new contact (admin only)
-/// @see AC-5.6.10.1 - Sets owner_id to caller for row-level security
-/// @see AC-5.6.10.4 - Audit logging
-/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
+/// Replace the contact email blocklist (controller only)
+/// Domains also block their subdomains; entries are normalized to lowercase
 #[update]
-fn create_contact(request: CreateContactRequest) -> Result<Contact, String> {
-    require_admin()?;
+async fn set_email_blocklist(domains: Vec<String>, emails: Vec<String>) -> Result<(), String> {
+    require_controller("set_email_blocklist").await?;
     let caller = ic_cdk::caller();
 
-    // FOS-5.6.11: Validate input before processing
-    validate_create_contact(&request)?;
-
-    let contact = STATE.with(|state| {
+    let (domain_count, email_count) = STATE.with(|state| {
         let mut s = state.borrow_mut();
-        let contact = s.create_contact(request.clone(), caller);
-
-        // Audit log
-        s.record_audit_log(
-            caller,
-            "create_contact",
-            "contact",
-            &contact.id.to_string(),
-            Some(serde_json::json!({
-                "email": request.email,
-                "source": format!("{:?}", request.source.unwrap_or_default()),
-            }).to_string()),
-        );
-
-        contact
+        s.set_email_blocklist(domains, emails);
+        let details = serde_json::json!({
+            "domains": s.blocked_email_domains,
+            "emails": s.blocked_emails,
+        })
+        .to_string();
+        s.record_audit_log(caller, AuditAction::SetEmailBlocklist, "config", "email_blocklist", Some(details));
+        (s.blocked_email_domains.len(), s.blocked_emails.len())
     });
 
-    ic_cdk::println!("Created contact {}", contact.id);
-    Ok(contact)
+    ic_cdk::println!("Email blocklist set: {} domains, {} emails", domain_count, email_count);
+    Ok(())
 }
 
-/// Called by user-service when a new user signs up
-/// @see AC-5.6.8.3 - Validates caller is user-service canister
-/// @see AC-5.6.10.1 - Sets owner_id to service principal (caller)
-/// @see AC-5.6.10.4 - Audit logging for CRM operations
-/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
-#[update]
-fn create_contact_from_signup(request: CreateContactRequest) -> Result<Contact, String> {
-    // Verify caller is the authorized user-service canister
-    require_authorized_canister("user-service")?;
-
-    // FOS-5.6.11: Validate input before processing
-    validate_create_contact(&request)?;
+/// Get the contact email blocklist as `(domains, emails)` (admin only)
+#[query]
+fn get_email_blocklist() -> Result<(Vec<String>, Vec<String>), String> {
+    require_admin("get_email_blocklist")?;
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        (s.blocked_email_domains.clone(), s.blocked_emails.clone())
+    }))
+}
 
+/// Require every admin-created contact to have a name (controller only)
+/// Off by default; signups from user-service are exempt so leads are never dropped
+#[update]
+async fn set_require_contact_name(required: bool) -> Result<(), String> {
+    require_controller("set_require_contact_name").await?;
     let caller = ic_cdk::caller();
 
-    let contact = STATE.with(|state| {
+    STATE.with(|state| {
         let mut s = state.borrow_mut();
-        let contact = s.create_contact(request.clone(), caller);
-
-        // Audit log for contact creation (AC-5.6.10.4)
+        s.require_contact_name = required;
         s.record_audit_log(
             caller,
-            "create_contact_from_signup",
-            "contact",
-            &contact.id.to_string(),
-            Some(serde_json::json!({
-                "source": "user_signup",
-                "user_id": request.user_id,
-            }).to_string()),
+            AuditAction::SetRequireContactName,
+            "config",
+            "require_contact_name",
+            Some(serde_json::json!({ "required": required }).to_string()),
         );
-
-        contact
     });
 
-    // Auto-create a deal for the new lead
-    let deal_request = CreateDealRequest {
-        contact_id: contact.id,
-        name: format!("New signup: Contact #{}", contact.id),
-        value: None,
-        notes: Some("Auto-created from user signup".to_string()),
-        expected_close_date: None,
-    };
+    ic_cdk::println!("Require contact name set to {}", required);
+    Ok(())
+}
 
-    let deal = STATE.with(|state| {
-        let mut s = state.borrow_mut();
-        let deal = s.create_deal(deal_request.clone(), caller)?;
+/// Set the contact and deal name length limits enforced by validation (controller only)
+/// Defaults to the compile-time limits (contact names 2-100, deal names 3-200)
+#[update]
+async fn set_name_length_limits(limits: NameLengthLimits) -> Result<(), String> {
+    require_controller("set_name_length_limits").await?;
+    let caller = ic_cdk::caller();
+
+    validate_name_length_limits(&limits)?;
 
-        // Audit log for auto-created deal (AC-5.6.10.4)
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.name_length_limits, limits.clone());
         s.record_audit_log(
             caller,
-            "create_deal_from_signup",
-            "deal",
-            &deal.id.to_string(),
-            Some(serde_json::json!({
-                "contact_id": deal_request.contact_id,
-                "source": "auto_signup",
-            }).to_string()),
+            AuditAction::SetNameLengthLimits,
+            "config",
+            "name_length_limits",
+            Some(serde_json::json!({ "old": old, "new": limits }).to_string()),
         );
-
-        Ok::<_, String>(deal)
     });
 
-    if let Err(e) = deal {
-        // Log but don't fail - contact was created successfully
-        ic_cdk::println!("Warning: Failed to auto-create deal: {}", e);
-    }
-
-    Ok(contact)
-}
-
-/// Get contact by ID (admin only)
-/// @see AC-5.6.8.5 - Query endpoints require admin authorization
-#[query]
-fn get_contact(id: ContactId) -> Result<Option<Contact>, String> {
-    require_admin()?;
-    Ok(STATE.with(|state| state.borrow().get_contact(id).cloned()))
+    ic_cdk::println!("Name length limits set to {:?}", limits);
+    Ok(())
 }
 
-/// Get contact by email (admin only)
-/// @see AC-5.6.8.5 - Query endpoints require admin authorization
-/// Note: This is especially sensitive due to email enumeration risk
+/// Current contact and deal name length limits (admin only)
 #[query]
-fn get_contact_by_email(email: String) -> Result<Option<Contact>, String> {
-    require_admin()?;
-    Ok(STATE.with(|state| state.borrow().get_contact_by_email(&email).cloned()))
+fn get_name_length_limits() -> Result<NameLengthLimits, String> {
+    require_admin("get_name_length_limits")?;
+    Ok(STATE.with(|state| state.borrow().name_length_limits.clone()))
 }
 
-/// Get contacts with row-level security filtering
-/// @see AC-5.6.10.1 - Row-level security filtering
-#[query]
-fn get_contacts(
-    filter: Option<ContactFilter>,
-    pagination: Option<PaginationParams>,
-) -> Result<PaginatedResponse<Contact>, String> {
-    require_admin()?;
+/// Set the source given to manually created contacts that omit one (controller only)
+/// Signups are always recorded as `Signup` regardless of this setting
+#[update]
+async fn set_manual_contact_default_source(source: ContactSource) -> Result<(), String> {
+    require_controller("set_manual_contact_default_source").await?;
     let caller = ic_cdk::caller();
 
-    Ok(STATE.with(|state| {
-        state.borrow().get_contacts(filter, pagination.unwrap_or_default(), &caller)
-    }))
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.manual_contact_default_source = source.clone();
+        s.record_audit_log(
+            caller,
+            AuditAction::SetManualContactDefaultSource,
+            "config",
+            "manual_contact_default_source",
+            Some(serde_json::json!({ "source": format!("{:?}", source) }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Manual contact default source set to {:?}", source);
+    Ok(())
 }
 
-/// Update a contact with permission check
-/// @see AC-5.6.10.3 - Granular CRUD permissions (EditOwnContacts/EditAllContacts)
-/// @see AC-5.6.10.4 - Audit logging
-/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
+/// Record one combined audit entry per signup instead of separate contact and deal entries (controller only)
+/// The combined entry is a `create_contact_from_signup` with the auto-deal's id in `deal_id`
 #[update]
-fn update_contact(request: UpdateContactRequest) -> Result<Contact, String> {
-    require_admin()?;
+async fn set_compact_signup_audit(enabled: bool) -> Result<(), String> {
+    require_controller("set_compact_signup_audit").await?;
     let caller = ic_cdk::caller();
 
-    // FOS-5.6.11: Validate input before processing
-    validate_update_contact(&request)?;
-
     STATE.with(|state| {
         let mut s = state.borrow_mut();
+        s.compact_signup_audit = enabled;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetCompactSignupAudit,
+            "config",
+            "compact_signup_audit",
+            Some(serde_json::json!({ "enabled": enabled }).to_string()),
+        );
+    });
 
-        // Get contact to check ownership
-        let contact = s.get_contact(request.id)
-            .ok_or("Contact not found")?
-            .clone();
+    ic_cdk::println!("Compact signup audit set to {}", enabled);
+    Ok(())
+}
 
-        // Check permissions
-        let has_edit_all = s.has_permission(&caller, &AdminPermission::EditAllContacts);
-        let has_edit_own = s.has_permission(&caller, &AdminPermission::EditOwnContacts);
-        let is_owner = contact.owner_id.as_ref() == Some(&caller);
+/// Set (or clear, with `None`) the template for signup auto-deals (controller only)
+/// The template must yield a valid deal under the current name limits, checked
+/// against the longest possible contact id
+#[update]
+async fn set_signup_deal_template(template: Option<SignupDealTemplate>) -> Result<(), String> {
+    require_controller("set_signup_deal_template").await?;
+    let caller = ic_cdk::caller();
 
-        if !has_edit_all && !(has_edit_own && is_owner) {
-            return Err("Unauthorized: Cannot edit this contact".to_string());
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        if let Some(template) = &template {
+            let sample = state::signup_deal_from_template(template, ContactId::MAX);
+            validate_create_deal(&sample, &s.name_length_limits)
+                .map_err(|e| format!("Invalid signup deal template: {}", e))?;
         }
+        let old = std::mem::replace(&mut s.signup_deal_template, template.clone());
+        s.record_audit_log(
+            caller,
+            AuditAction::SetSignupDealTemplate,
+            "config",
+            "signup_deal_template",
+            Some(serde_json::json!({ "old": old, "new": template }).to_string()),
+        );
+        Ok::<_, String>(())
+    })?;
 
-        // Capture old values for audit log
-        let old_values = serde_json::json!({
-            "name": contact.name,
-            "company": contact.company,
-            "status": format!("{:?}", contact.status),
-        }).to_string();
+    ic_cdk::println!("Signup deal template updated");
+    Ok(())
+}
 
-        // Perform update
-        let updated = s.update_contact(
-            request.id,
-            request.name,
-            request.company,
-            request.job_title,
-            request.interest_area,
-            request.notes,
-            request.status,
-        ).ok_or("Failed to update contact")?;
+/// Set (or reset, with `None`) the metrics history retention windows (controller only)
+/// Takes effect at the next compaction, i.e. the next `record_metrics` call
+#[update]
+async fn set_metrics_retention(retention: Option<MetricsRetention>) -> Result<(), String> {
+    require_controller("set_metrics_retention").await?;
+    let caller = ic_cdk::caller();
 
-        // Audit log
+    if retention.as_ref().and_then(|r| r.downsample_interval_days) == Some(0) {
+        return Err("Downsample interval must be at least one day".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.metrics_retention, retention.clone());
         s.record_audit_log(
             caller,
-            "update_contact",
-            "contact",
-            &request.id.to_string(),
-            Some(old_values),
+            AuditAction::SetMetricsRetention,
+            "config",
+            "metrics_retention",
+            Some(serde_json::json!({ "old": old, "new": retention }).to_string()),
         );
+    });
 
-        Ok(updated)
-    })
+    ic_cdk::println!("Metrics retention updated");
+    Ok(())
 }
 
-/// Delete a contact with permission check
-/// @see AC-5.6.10.3 - Granular CRUD permissions (DeleteOwnContacts/DeleteAllContacts)
-/// @see AC-5.6.10.4 - Audit logging
+/// Replace the ordered custom deal pipeline (controller only)
+/// An empty list turns the custom pipeline off. Deals whose `custom_stage` is no
+/// longer in the list have it cleared, and the audit entry lists them.
 #[update]
-fn delete_contact(id: ContactId) -> Result<Contact, String> {
-    require_admin()?;
+async fn set_pipeline_stages(stages: Vec<String>) -> Result<(), String> {
+    require_controller("set_pipeline_stages").await?;
     let caller = ic_cdk::caller();
 
+    validate_pipeline_stages(&stages)?;
+
     STATE.with(|state| {
         let mut s = state.borrow_mut();
+        let cleared = s.set_pipeline_stages(stages.clone(), ic_cdk::api::time());
+        s.record_audit_log(
+            caller,
+            AuditAction::SetPipelineStages,
+            "config",
+            "pipeline_stages",
+            Some(serde_json::json!({ "stages": stages, "cleared_deals": cleared }).to_string()),
+        );
+    });
 
-        // Get contact to check ownership
+    ic_cdk::println!("Pipeline stages set: {:?}", stages);
+    Ok(())
+}
+
+/// Set the per-minute rate limit applied to authorized canisters (controller only)
+/// Endpoints whose own limit is higher keep their own limit
+#[update]
+async fn set_authorized_canister_rate_limit(limit: u32) -> Result<(), String> {
+    require_controller("set_authorized_canister_rate_limit").await?;
+    let caller = ic_cdk::caller();
+
+    if limit == 0 {
+        return Err("Rate limit must be greater than zero".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.authorized_canister_rate_limit, limit);
+        s.record_audit_log(
+            caller,
+            AuditAction::SetAuthorizedCanisterRateLimit,
+            "config",
+            "authorized_canister_rate_limit",
+            Some(serde_json::json!({ "old": old, "new": limit }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Authorized canister rate limit set to {}", limit);
+    Ok(())
+}
+
+/// Set the per-minute cap on `create_contact_from_signup` calls per canister (controller only)
+#[update]
+async fn set_signup_rate_limit(limit: u32) -> Result<(), String> {
+    require_controller("set_signup_rate_limit").await?;
+    let caller = ic_cdk::caller();
+
+    if limit == 0 {
+        return Err("Rate limit must be greater than zero".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.signup_rate_limit, limit);
+        s.record_audit_log(
+            caller,
+            AuditAction::SetSignupRateLimit,
+            "config",
+            "signup_rate_limit",
+            Some(serde_json::json!({ "old": old, "new": limit }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Signup rate limit set to {}", limit);
+    Ok(())
+}
+
+/// Set the page size used when list endpoints get no `pagination` or `limit` (controller only)
+/// Values above `MAX_PAGE_SIZE` are clamped
+#[update]
+async fn set_default_page_size(limit: u64) -> Result<u64, String> {
+    require_controller("set_default_page_size").await?;
+    let caller = ic_cdk::caller();
+
+    if limit == 0 {
+        return Err("Page size must be greater than zero".to_string());
+    }
+    let limit = limit.min(MAX_PAGE_SIZE);
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = s.default_page_size;
+        s.default_page_size = limit;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetDefaultPageSize,
+            "config",
+            "default_page_size",
+            Some(serde_json::json!({ "old": old, "new": limit }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Default page size set to {}", limit);
+    Ok(limit)
+}
+
+/// Rejected-call counts per (caller, rate-limit bucket) (controller only)
+#[query]
+fn get_rate_limit_violations() -> Result<Vec<(Principal, String, u64)>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| {
+        state
+            .borrow()
+            .rate_limit_violations
+            .iter()
+            .map(|((caller, endpoint), count)| (*caller, endpoint.clone(), *count))
+            .collect()
+    }))
+}
+
+/// Set the cycle balance below which `health_detailed` reports `low_cycles` (controller only)
+/// Use 0 to disable the warning
+#[update]
+async fn set_low_cycles_threshold(threshold: u64) -> Result<(), String> {
+    require_controller("set_low_cycles_threshold").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.low_cycles_threshold, threshold);
+        s.record_audit_log(
+            caller,
+            AuditAction::SetLowCyclesThreshold,
+            "config",
+            "low_cycles_threshold",
+            Some(serde_json::json!({ "old": old, "new": threshold }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Low cycles threshold set to {}", threshold);
+    Ok(())
+}
+
+/// Set the maximum stored length of audit `details`, in characters (controller only)
+/// Longer details are truncated at write time; existing entries are unchanged
+#[update]
+async fn set_max_audit_detail_len(max_len: u32) -> Result<(), String> {
+    require_controller("set_max_audit_detail_len").await?;
+    let caller = ic_cdk::caller();
+
+    if max_len < state::MIN_AUDIT_DETAIL_LEN {
+        return Err(format!(
+            "Maximum audit detail length must be at least {}",
+            state::MIN_AUDIT_DETAIL_LEN
+        ));
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let old = std::mem::replace(&mut s.max_audit_detail_len, max_len);
+        s.record_audit_log(
+            caller,
+            AuditAction::SetMaxAuditDetailLen,
+            "config",
+            "max_audit_detail_len",
+            Some(serde_json::json!({ "old": old, "new": max_len }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Max audit detail length set to {}", max_len);
+    Ok(())
+}
+
+/// Auto-mark Active contacts Churned after `after_secs` without activity, or `None` to disable (controller only)
+/// Inactivity is measured from `last_activity_at`, falling back to `updated_at`
+#[update]
+async fn set_auto_churn_after_secs(after_secs: Option<u64>) -> Result<(), String> {
+    require_controller("set_auto_churn_after_secs").await?;
+    let caller = ic_cdk::caller();
+
+    if after_secs == Some(0) {
+        return Err("Auto-churn threshold must be greater than zero".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.auto_churn_after_secs = after_secs;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetAutoChurnAfterSecs,
+            "config",
+            "auto_churn_after_secs",
+            Some(serde_json::json!({ "after_secs": after_secs }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Auto-churn threshold set to {:?} seconds", after_secs);
+    Ok(())
+}
+
+/// Require a second admin's `approve_deal_close` before deals worth at least `threshold`
+/// cents can be closed won; `None` disables the check (controller only)
+#[update]
+async fn set_deal_approval_threshold(threshold: Option<u64>) -> Result<(), String> {
+    require_controller("set_deal_approval_threshold").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.deal_approval_threshold = threshold;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetDealApprovalThreshold,
+            "config",
+            "deal_approval_threshold",
+            Some(serde_json::json!({ "threshold": threshold }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Deal approval threshold set to {:?}", threshold);
+    Ok(())
+}
+
+/// Re-derive contact status from deal outcomes on stage changes (controller only)
+/// Any won deal makes a contact Active; all deals lost makes it Churned.
+/// Statuses set explicitly with `update_contact` are never overridden.
+#[update]
+async fn set_auto_status_from_deals(enabled: bool) -> Result<(), String> {
+    require_controller("set_auto_status_from_deals").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.auto_status_from_deals = enabled;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetAutoStatusFromDeals,
+            "config",
+            "auto_status_from_deals",
+            Some(serde_json::json!({ "enabled": enabled }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Contact status derivation from deals set to {}", enabled);
+    Ok(())
+}
+
+/// Turn maintenance mode on or off (controller only)
+/// While on, admin create/update/delete endpoints are rejected; queries keep working
+#[update]
+async fn set_maintenance_mode(enabled: bool) -> Result<(), String> {
+    require_controller("set_maintenance_mode").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.maintenance_mode = enabled;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetMaintenanceMode,
+            "config",
+            "maintenance_mode",
+            Some(serde_json::json!({ "enabled": enabled }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Maintenance mode set to {}", enabled);
+    Ok(())
+}
+
+/// Turn PII redaction of audit log details on or off (controller only)
+/// Applies to entries written from now on; existing entries are not rewritten
+#[update]
+async fn set_redact_pii_in_audit(enabled: bool) -> Result<(), String> {
+    require_controller("set_redact_pii_in_audit").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.redact_pii_in_audit = enabled;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetRedactPiiInAudit,
+            "config",
+            "redact_pii_in_audit",
+            Some(serde_json::json!({ "enabled": enabled }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Audit PII redaction set to {}", enabled);
+    Ok(())
+}
+
+/// Mirror contact and deal mutations into `activity_log` for analytics (controller only)
+/// Mirrored entries use the actor's principal text as `user_id`
+#[update]
+async fn set_mirror_crm_to_activity_log(enabled: bool) -> Result<(), String> {
+    require_controller("set_mirror_crm_to_activity_log").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.mirror_crm_to_activity_log = enabled;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetMirrorCrmToActivityLog,
+            "config",
+            "mirror_crm_to_activity_log",
+            Some(serde_json::json!({ "enabled": enabled }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("CRM activity mirroring set to {}", enabled);
+    Ok(())
+}
+
+// =============================================================================
+// Audit Log API (FOS-5.6.10)
+// =============================================================================
+
+/// Configure the audit export hook (controller only)
+/// `high_watermark` is the count of unexported entries that raises `audit_export_needed`;
+/// with `block_on_export`, audited writes are rejected instead of draining unexported entries
+#[update]
+async fn set_audit_export_policy(high_watermark: u64, block_on_export: bool) -> Result<(), String> {
+    require_controller("set_audit_export_policy").await?;
+    let caller = ic_cdk::caller();
+
+    if high_watermark == 0 || high_watermark > state::AUDIT_LOG_CAPACITY {
+        return Err(format!(
+            "High watermark must be between 1 and {}",
+            state::AUDIT_LOG_CAPACITY
+        ));
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.audit_log_high_watermark = high_watermark;
+        s.block_on_export = block_on_export;
+        s.audit_export_needed = s.unexported_audit_entries() >= high_watermark;
+        s.record_audit_log(
+            caller,
+            AuditAction::SetAuditExportPolicy,
+            "audit_log",
+            "export_policy",
+            Some(serde_json::json!({
+                "high_watermark": high_watermark,
+                "block_on_export": block_on_export,
+            }).to_string()),
+        );
+    });
+
+    ic_cdk::println!(
+        "Audit export policy set: watermark {}, block_on_export {}",
+        high_watermark,
+        block_on_export
+    );
+    Ok(())
+}
+
+/// Confirm an off-chain export of audit entries up to `through_id` (controller only)
+/// Clears `audit_export_needed` once the unexported remainder is under the watermark
+#[update]
+async fn acknowledge_audit_export(through_id: u64) -> Result<(), String> {
+    require_controller("acknowledge_audit_export").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.acknowledge_audit_export(through_id)?;
+        s.record_audit_log(
+            caller,
+            AuditAction::AcknowledgeAuditExport,
+            "audit_log",
+            &through_id.to_string(),
+            None,
+        );
+        Ok::<(), String>(())
+    })?;
+
+    ic_cdk::println!("Audit log exported through entry {}", through_id);
+    Ok(())
+}
+
+/// Purge audit entries older than `timestamp` (controller only)
+/// Returns the number removed; the purge itself is recorded as a new entry
+/// While `block_on_export` is set, entries not yet acknowledged as exported are kept
+/// @see AC-5.6.10.5 - Audit log retention
+#[update]
+async fn purge_audit_entries_before(timestamp: Timestamp) -> Result<u64, String> {
+    require_controller("purge_audit_entries_before").await?;
+    let caller = ic_cdk::caller();
+
+    let removed = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let removed = s.purge_audit_entries_before(timestamp);
+        s.record_audit_log(
+            caller,
+            AuditAction::PurgeAuditEntries,
+            "audit_log",
+            "retention",
+            Some(serde_json::json!({
+                "before": timestamp,
+                "removed": removed,
+            }).to_string()),
+        );
+        removed
+    });
+
+    ic_cdk::println!("Purged {} audit entries before {}", removed, timestamp);
+    Ok(removed)
+}
+
+/// Get audit log entries
+/// `target_id_contains` substring-matches `target_id` (e.g. a family of deal ids)
+/// @see AC-5.6.10.4, AC-5.6.10.5 - Audit logging
+#[query]
+fn get_audit_log(
+    action_filter: Option<String>,
+    target_type_filter: Option<String>,
+    actor_filter: Option<Principal>,
+    limit: Option<u64>,
+    target_id_contains: Option<String>,
+) -> Result<Vec<AuditLogEntry>, String> {
+    query_audit_log(action_filter, target_type_filter, actor_filter, target_id_contains, limit)
+}
+
+/// Every action name the audit log can record, for building `get_audit_log` filters (admin only)
+#[query]
+fn get_audit_actions() -> Result<Vec<String>, String> {
+    require_admin("get_audit_actions")?;
+    Ok(AuditAction::ALL.iter().map(|a| a.as_str().to_string()).collect())
+}
+
+/// Most recent rejected access attempts, newest first (ViewAuditLogs or controller)
+#[query]
+fn get_access_denials(limit: Option<u64>) -> Result<Vec<AccessDenial>, String> {
+    require_admin("get_access_denials")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs) && !s.is_controller(&caller) {
+            return Err("Unauthorized: ViewAuditLogs permission required".to_string());
+        }
+        Ok(s.get_access_denials(limit))
+    })
+}
+
+/// Get audit log entries with `details` parsed into top-level key/value pairs
+/// Same filters and permissions as `get_audit_log`
+#[query]
+fn get_audit_log_parsed(
+    action_filter: Option<String>,
+    target_type_filter: Option<String>,
+    actor_filter: Option<Principal>,
+    limit: Option<u64>,
+    target_id_contains: Option<String>,
+) -> Result<Vec<ParsedAuditLogEntry>, String> {
+    let entries = query_audit_log(action_filter, target_type_filter, actor_filter, target_id_contains, limit)?;
+    Ok(entries.into_iter().map(state::parse_audit_log_entry).collect())
+}
+
+fn query_audit_log(
+    action_filter: Option<String>,
+    target_type_filter: Option<String>,
+    actor_filter: Option<Principal>,
+    target_id_contains: Option<String>,
+    limit: Option<u64>,
+) -> Result<Vec<AuditLogEntry>, String> {
+    require_admin("get_audit_log")?;
+
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        // Check ViewAuditLogs permission
+        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs) && !s.is_controller(&caller) {
+            return Err("Unauthorized: ViewAuditLogs permission required".to_string());
+        }
+
+        Ok(s.get_audit_log(
+            action_filter.as_deref(),
+            target_type_filter.as_deref(),
+            actor_filter.as_ref(),
+            target_id_contains.as_deref(),
+            limit,
+        ))
+    })
+}
+
+/// Per-(actor, action) audit entry counts over the last `window_secs`, busiest first
+/// Lets controllers spot spikes such as one admin mass-deleting contacts
+#[query]
+fn get_recent_action_rates(window_secs: u64) -> Result<Vec<(Principal, String, u64)>, String> {
+    require_admin("get_recent_action_rates")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs) && !s.is_controller(&caller) {
+            return Err("Unauthorized: ViewAuditLogs permission required".to_string());
+        }
+
+        let since = ic_cdk::api::time().saturating_sub(window_secs.saturating_mul(1_000_000_000));
+        Ok(s.get_recent_action_rates(since))
+    })
+}
+
+// =============================================================================
+// Contact API
+// =============================================================================
+
+/// Create a new contact (admin only)
+/// @see AC-5.6.10.1 - Sets owner_id to caller for row-level security
+/// @see AC-5.6.10.4 - Audit logging
+/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
+#[update]
+fn create_contact(mut request: CreateContactRequest) -> Result<Contact, String> {
+    require_admin("create_contact")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_create_contact(&request, &limits)?;
+    if STATE.with(|state| state.borrow().require_contact_name) {
+        validate_required_contact_name(&request)?;
+    }
+
+    // Only controllers may backdate records (data migration) or assign an owner
+    if !STATE.with(|state| state.borrow().is_controller(&caller)) {
+        request.created_at_override = None;
+        request.owner_id = None;
+    }
+
+    let contact = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        // Manually added contacts are rarely signups; use the configured default
+        request.source.get_or_insert_with(|| s.manual_contact_default_source.clone());
+        let contact = s.create_contact(request.clone(), caller)?;
+
+        // Audit log
+        s.record_audit_log(
+            caller,
+            AuditAction::CreateContact,
+            "contact",
+            &contact.id.to_string(),
+            Some(serde_json::json!({
+                "email": request.email,
+                "source": format!("{:?}", request.source.unwrap_or_default()),
+                "marketing_consent": request.marketing_consent,
+            }).to_string()),
+        );
+
+        Ok::<_, String>(contact)
+    })?;
+
+    ic_cdk::println!("Created contact {}", contact.id);
+    Ok(contact)
+}
+
+/// Called by user-service when a new user signs up
+/// @see AC-5.6.8.3 - Validates caller is user-service canister
+/// @see AC-5.6.10.1 - Sets owner_id to service principal (caller)
+/// @see AC-5.6.10.4 - Audit logging for CRM operations
+/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
+#[update]
+fn create_contact_from_signup(mut request: CreateContactRequest) -> Result<Contact, String> {
+    // Verify caller is the authorized user-service canister
+    require_authorized_canister("user-service")?;
+
+    // FOS-5.6.11: Validate input before processing
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_create_contact(&request, &limits)?;
+
+    // Blocklisted addresses must not enrich an existing contact either
+    if STATE.with(|state| state.borrow().is_email_blocked(&request.email)) {
+        return Err("Email is blocklisted".to_string());
+    }
+
+    // Signups are always created now and owned by the service; backdating and
+    // owner assignment are controller-only tools
+    request.created_at_override = None;
+    request.owner_id = None;
+    request.source = Some(ContactSource::Signup);
+
+    let caller = ic_cdk::caller();
+
+    // Signups have their own budget so activity logging cannot starve them (FOS-5.6.8)
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.check_audit_capacity()?;
+        s.check_rate_limit(&caller, state::SIGNUP_RATE_LIMIT_BUCKET)
+    })?;
+
+    let compact_audit = STATE.with(|state| state.borrow().compact_signup_audit);
+
+    // `created` is false when an existing contact was enriched; compact mode only
+    // combines the entries of a fresh contact and its auto-deal
+    let (contact, created) = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        // A contact added manually before the user signed up is enriched, not duplicated
+        if let Some(contact) = s.enrich_contact_from_signup(&request) {
+            s.record_audit_log(
+                caller,
+                AuditAction::EnrichContactFromSignup,
+                "contact",
+                &contact.id.to_string(),
+                Some(serde_json::json!({
+                    "source": "user_signup",
+                    "user_id": request.user_id,
+                }).to_string()),
+            );
+            return Ok((contact, false));
+        }
+
+        let contact = s.create_contact(request.clone(), caller)?;
+
+        // Audit log for contact creation (AC-5.6.10.4); compact mode writes it below
+        if !compact_audit {
+            s.record_audit_log(
+                caller,
+                AuditAction::CreateContactFromSignup,
+                "contact",
+                &contact.id.to_string(),
+                Some(serde_json::json!({
+                    "source": "user_signup",
+                    "user_id": request.user_id,
+                }).to_string()),
+            );
+        }
+
+        Ok::<_, String>((contact, true))
+    })?;
+    let compact_audit = compact_audit && created;
+
+    // Only auto-create a deal if the contact does not already have one
+    let deal_id = if STATE.with(|state| state.borrow().has_deal_for_contact(contact.id)) {
+        None
+    } else {
+        // Auto-create a deal for the new lead from the configured template
+        let deal_request = STATE.with(|state| state.borrow().signup_deal_request(contact.id));
+
+        let deal = STATE.with(|state| {
+            let mut s = state.borrow_mut();
+            let deal = s.create_deal(deal_request.clone(), caller)?;
+
+            // Audit log for auto-created deal (AC-5.6.10.4)
+            if !compact_audit {
+                s.record_audit_log(
+                    caller,
+                    AuditAction::CreateDealFromSignup,
+                    "deal",
+                    &deal.id.to_string(),
+                    Some(serde_json::json!({
+                        "contact_id": deal_request.contact_id,
+                        "source": "auto_signup",
+                    }).to_string()),
+                );
+            }
+
+            Ok::<_, String>(deal)
+        });
+
+        match deal {
+            Ok(deal) => Some(deal.id),
+            Err(e) => {
+                // Log but don't fail - contact was created successfully
+                ic_cdk::println!("Warning: Failed to auto-create deal: {}", e);
+                None
+            }
+        }
+    };
+
+    // One combined entry for the new contact and its auto-deal
+    if compact_audit {
+        STATE.with(|state| {
+            state.borrow_mut().record_compact_signup_audit(caller, &contact, deal_id);
+        });
+    }
+
+    Ok(contact)
+}
+
+/// Get contact by ID (admin only)
+/// Returns `None` for contacts the caller may not view, as in `get_contacts`
+/// @see AC-5.6.8.5 - Query endpoints require admin authorization
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contact(id: ContactId) -> Result<Option<Contact>, String> {
+    require_admin("get_contact")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_contact(id).filter(|c| s.can_view_contact(&caller, c)).cloned()
+    }))
+}
+
+/// Get contact by email (admin only)
+/// @see AC-5.6.8.5 - Query endpoints require admin authorization
+/// Note: This is especially sensitive due to email enumeration risk
+#[query]
+fn get_contact_by_email(email: String) -> Result<Option<Contact>, String> {
+    require_admin("get_contact_by_email")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_contact_by_email(&email).filter(|c| s.can_view_contact(&caller, c)).cloned()
+    }))
+}
+
+/// Resolve a platform user to their CRM contact (admin or user/auth service)
+/// Admins are subject to row-level security; the services see every contact
+/// @see AC-5.6.8.3 - Inter-canister call verification
+#[query]
+fn get_contact_by_user_id(user_id: String) -> Result<Option<Contact>, String> {
+    require_authorized_canister_or_admin(&["user-service", "auth-service"])?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_contact_by_user_id(&user_id)
+            .filter(|c| !s.is_admin(&caller) || s.can_view_contact(&caller, c))
+            .cloned()
+    }))
+}
+
+/// Batch lookup of up to `MAX_PAGE_SIZE` contacts by id (admin only)
+/// Missing ids and records the caller cannot view are omitted
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contacts_by_ids(ids: Vec<ContactId>) -> Result<Vec<Contact>, String> {
+    require_admin("get_contacts_by_ids")?;
+
+    if ids.len() as u64 > MAX_PAGE_SIZE {
+        return Err(format!("Too many ids: at most {} per call", MAX_PAGE_SIZE));
+    }
+
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| state.borrow().get_contacts_by_ids(&ids, &caller)))
+}
+
+/// Get contacts with row-level security filtering
+/// Filtering by `owner_id` is reserved for ViewAllContacts holders and controllers
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contacts(
+    filter: Option<ContactFilter>,
+    pagination: Option<PaginationParams>,
+) -> Result<PaginatedResponse<Contact>, String> {
+    require_admin("get_contacts")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        let filters_by_owner = filter.as_ref().and_then(|f| f.owner_id).is_some();
+        if filters_by_owner && !s.has_permission(&caller, &AdminPermission::ViewAllContacts) {
+            return Err("Unauthorized: ViewAllContacts permission required to filter by owner".to_string());
+        }
+
+        Ok(s.get_contacts(filter, s.resolve_pagination(pagination), &caller))
+    })
+}
+
+/// Get contacts with the fields that matched `filter.search`, for result highlighting
+/// Set `filter.with_match_info` to fill `matched_fields`; otherwise behaves like `get_contacts`
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contact_matches(
+    filter: Option<ContactFilter>,
+    pagination: Option<PaginationParams>,
+) -> Result<PaginatedResponse<ContactMatch>, String> {
+    require_admin("get_contact_matches")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        let filters_by_owner = filter.as_ref().and_then(|f| f.owner_id).is_some();
+        if filters_by_owner && !s.has_permission(&caller, &AdminPermission::ViewAllContacts) {
+            return Err("Unauthorized: ViewAllContacts permission required to filter by owner".to_string());
+        }
+
+        Ok(s.get_contact_matches(filter, s.resolve_pagination(pagination), &caller))
+    })
+}
+
+/// Search contacts (email, name, company) and deals (name, notes) in one call
+/// Each list is capped by `limit` (default 50, max 200)
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn global_search(query: String, limit: Option<u64>) -> Result<SearchResults, String> {
+    require_admin("global_search")?;
+    let caller = ic_cdk::caller();
+
+    Ok(STATE.with(|state| state.borrow().global_search(&query, limit, &caller)))
+}
+
+/// Contacts created per `bucket_secs` interval in `from..=to`, as `(bucket_start, count)`
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contact_growth(from: Timestamp, to: Timestamp, bucket_secs: u64) -> Result<Vec<(Timestamp, u64)>, String> {
+    require_admin("get_contact_growth")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| state.borrow().get_contact_growth(from, to, bucket_secs, &caller))
+}
+
+/// Active contacts with no recent updates and no open deals, oldest first
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_stale_contacts(
+    inactive_for_secs: u64,
+    pagination: Option<PaginationParams>,
+) -> Result<PaginatedResponse<Contact>, String> {
+    require_admin("get_stale_contacts")?;
+    let caller = ic_cdk::caller();
+
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_stale_contacts(inactive_for_secs, s.resolve_pagination(pagination), &caller)
+    }))
+}
+
+/// Active contacts with no deals yet, for lead-gen targeting
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contacts_without_deals(pagination: Option<PaginationParams>) -> Result<PaginatedResponse<Contact>, String> {
+    require_admin("get_contacts_without_deals")?;
+    let caller = ic_cdk::caller();
+
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_contacts_without_deals(s.resolve_pagination(pagination), &caller)
+    }))
+}
+
+/// Update a contact with permission check
+/// @see AC-5.6.10.3 - Granular CRUD permissions (EditOwnContacts/EditAllContacts)
+/// @see AC-5.6.10.4 - Audit logging
+/// @see AC-5.6.11.1, AC-5.6.11.2 - Input validation
+#[update]
+fn update_contact(request: UpdateContactRequest) -> Result<Contact, String> {
+    require_admin("update_contact")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_update_contact(&request, &limits)?;
+    if STATE.with(|state| state.borrow().require_contact_name) {
+        validate_required_contact_name_on_update(&request)?;
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        // Get contact to check ownership
+        let contact = s.get_contact(request.id)
+            .ok_or("Contact not found")?
+            .clone();
+
+        // Check permissions
+        let has_edit_all = s.has_permission(&caller, &AdminPermission::EditAllContacts);
+        let has_edit_own = s.has_permission(&caller, &AdminPermission::EditOwnContacts);
+        let is_owner = contact.owner_id.as_ref() == Some(&caller);
+
+        if !has_edit_all && !(has_edit_own && is_owner) {
+            return Err("Unauthorized: Cannot edit this contact".to_string());
+        }
+
+        // No-op update: leave updated_at and the audit log untouched
+        if !request.has_changes() {
+            return Ok(contact);
+        }
+
+        // Capture old values for audit log
+        let old_values = serde_json::json!({
+            "old": {
+                "name": contact.name,
+                "company": contact.company,
+                "status": format!("{:?}", contact.status),
+            },
+        }).to_string();
+
+        // Perform update
+        let mut updated = s.update_contact(
+            request.id,
+            request.name,
+            request.company,
+            request.job_title,
+            request.interest_area,
+            request.notes,
+            request.status,
+        ).ok_or("Failed to update contact")?;
+
+        if let Some(fields) = request.clear_fields.filter(|f| !f.is_empty()) {
+            updated = s.clear_contact_fields(request.id, &fields)
+                .ok_or("Failed to update contact")?;
+        }
+
+        // Consent changes get their own audit entry for compliance reporting
+        if let Some(consent) = request.marketing_consent.filter(|c| contact.marketing_consent != Some(*c)) {
+            updated = s.set_contact_consent(request.id, consent)
+                .ok_or("Failed to update contact")?;
+            s.record_audit_log(
+                caller,
+                AuditAction::UpdateMarketingConsent,
+                "contact",
+                &request.id.to_string(),
+                Some(serde_json::json!({
+                    "old": contact.marketing_consent,
+                    "new": consent,
+                }).to_string()),
+            );
+        }
+
+        // Audit log
+        s.record_audit_log(
+            caller,
+            AuditAction::UpdateContact,
+            "contact",
+            &request.id.to_string(),
+            Some(old_values),
+        );
+
+        Ok(updated)
+    })
+}
+
+/// Replace a contact's tags; tags are trimmed, lowercased and deduplicated
+/// @see AC-5.6.10.3 - Requires edit permission on the contact (EditOwnContacts/EditAllContacts)
+/// @see AC-5.6.10.4 - Audit logging
+#[update]
+fn set_contact_tags(id: ContactId, tags: Vec<String>) -> Result<Contact, String> {
+    require_admin("set_contact_tags")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    let tags = normalize_contact_tags(&tags)?;
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        let contact = s.get_contact(id)
+            .ok_or("Contact not found")?
+            .clone();
+
+        let has_edit_all = s.has_permission(&caller, &AdminPermission::EditAllContacts);
+        let has_edit_own = s.has_permission(&caller, &AdminPermission::EditOwnContacts);
+        let is_owner = contact.owner_id.as_ref() == Some(&caller);
+
+        if !(has_edit_all || (has_edit_own && is_owner)) {
+            return Err("Unauthorized: Cannot edit this contact".to_string());
+        }
+
+        let updated = s.set_contact_tags(id, tags)
+            .ok_or("Failed to update contact")?;
+
+        s.record_audit_log(
+            caller,
+            AuditAction::SetContactTags,
+            "contact",
+            &id.to_string(),
+            Some(serde_json::json!({
+                "old": contact.tags,
+                "new": updated.tags,
+            }).to_string()),
+        );
+
+        Ok(updated)
+    })
+}
+
+/// Rename a tag across every contact, merging into `new` where both exist
+/// Requires EditAllContacts (controllers have it implicitly). Returns the contacts changed.
+#[update]
+fn rename_tag(old: String, new: String) -> Result<u64, String> {
+    require_admin("rename_tag")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    let old = normalize_tag(&old);
+    let new = validate_tag(&new)?;
+    if old == new {
+        return Err("New tag name must differ from the old one".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        if !s.has_permission(&caller, &AdminPermission::EditAllContacts) {
+            return Err("Unauthorized: EditAllContacts permission required".to_string());
+        }
+
+        let affected = s.rename_tag(&old, &new);
+
+        s.record_audit_log(
+            caller,
+            AuditAction::RenameTag,
+            "tag",
+            &old,
+            Some(serde_json::json!({
+                "new": new,
+                "affected": affected,
+            }).to_string()),
+        );
+
+        Ok(affected)
+    })
+}
+
+/// Delete a contact with permission check
+/// @see AC-5.6.10.3 - Granular CRUD permissions (DeleteOwnContacts/DeleteAllContacts)
+/// @see AC-5.6.10.4 - Audit logging
+#[update]
+fn delete_contact(id: ContactId) -> Result<Contact, String> {
+    require_admin("delete_contact")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        // Get contact to check ownership
         let contact = s.get_contact(id)
             .ok_or("Contact not found")?
             .clone();
@@ -550,75 +1704,383 @@ fn delete_contact(id: ContactId) -> Result<Contact, String> {
             return Err("Unauthorized: Cannot delete this contact".to_string());
         }
 
-        // Audit log before deletion
+        // Deals removed along with the contact, traced individually below
+        let cascaded_deals: Vec<Deal> = s
+            .deals_by_contact
+            .get(&id)
+            .map(|ids| ids.iter().filter_map(|deal_id| s.get_deal(*deal_id).cloned()).collect())
+            .unwrap_or_default();
+
+        // Audit log before deletion
+        s.record_audit_log(
+            caller,
+            AuditAction::DeleteContact,
+            "contact",
+            &id.to_string(),
+            Some(serde_json::json!({
+                "email": contact.email,
+                "name": contact.name,
+                "cascaded_deal_ids": cascaded_deals.iter().map(|d| d.id).collect::<Vec<_>>(),
+            }).to_string()),
+        );
+
+        for deal in &cascaded_deals {
+            s.record_audit_log(
+                caller,
+                AuditAction::CascadeDeleteDeal,
+                "deal",
+                &deal.id.to_string(),
+                Some(serde_json::json!({
+                    "name": deal.name,
+                    "value": deal.value,
+                    "contact_id": deal.contact_id,
+                }).to_string()),
+            );
+        }
+
+        // Perform deletion
+        let deleted = s.delete_contact(id)
+            .ok_or("Failed to delete contact".to_string())?;
+        s.record_contact_tombstone(&deleted, ic_cdk::api::time());
+        Ok(deleted)
+    })
+}
+
+/// Contacts created, updated or deleted after `since`, as a patch for polling clients
+/// Deletions are reported from a bounded tombstone list (`MAX_CONTACT_TOMBSTONES`);
+/// a "Resync required" error means `since` is older than the retained tombstones
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contact_changes_since(
+    since: Timestamp,
+    pagination: Option<PaginationParams>,
+) -> Result<PaginatedResponse<ContactChange>, String> {
+    require_admin("get_contact_changes_since")?;
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let s = state.borrow();
+        let changes = s.get_contact_changes_since(since, &caller)?;
+        Ok(PaginatedResponse::paginate(changes, &s.resolve_pagination(pagination)))
+    })
+}
+
+// =============================================================================
+// Deal API
+// =============================================================================
+
+/// Create a new deal (admin only)
+/// @see AC-5.6.10.1 - Sets owner_id to caller for row-level security
+/// @see AC-5.6.10.4 - Audit logging
+/// @see AC-5.6.11.1, AC-5.6.11.3 - Input validation
+#[update]
+fn create_deal(mut request: CreateDealRequest) -> Result<Deal, String> {
+    require_admin("create_deal")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_create_deal(&request, &limits)?;
+
+    // Only controllers may backdate records (data migration) or assign an owner
+    if !STATE.with(|state| state.borrow().is_controller(&caller)) {
+        request.created_at_override = None;
+        request.owner_id = None;
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let deal = s.create_deal(request.clone(), caller)?;
+
+        // Audit log
+        s.record_audit_log(
+            caller,
+            AuditAction::CreateDeal,
+            "deal",
+            &deal.id.to_string(),
+            Some(serde_json::json!({
+                "contact_id": request.contact_id,
+                "name": request.name,
+                "value": request.value,
+            }).to_string()),
+        );
+
+        Ok(deal)
+    })
+}
+
+/// Create a new lead's contact and its first deal in one call (admin only)
+/// All validation and uniqueness checks run before any mutation; if the deal
+/// still fails to create, the contact is removed again
+/// @see AC-5.6.10.4 - Audit logging
+/// @see AC-5.6.11.1 - Input validation
+#[update]
+fn create_deal_with_new_contact(
+    mut contact: CreateContactRequest,
+    deal_name: String,
+    deal_value: Option<u64>,
+) -> Result<(Contact, Deal), String> {
+    require_admin("create_deal_with_new_contact")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // Backdating and owner assignment are reserved for the dedicated create_* endpoints
+    contact.created_at_override = None;
+    contact.owner_id = None;
+    let mut deal_request = CreateDealRequest {
+        contact_id: 0,
+        name: deal_name,
+        value: deal_value,
+        notes: None,
+        expected_close_date: None,
+        created_at_override: None,
+        owner_id: None,
+    };
+
+    // FOS-5.6.11: Validate both halves before touching state
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_create_contact(&contact, &limits)?;
+    if STATE.with(|state| state.borrow().require_contact_name) {
+        validate_required_contact_name(&contact)?;
+    }
+    validate_create_deal(&deal_request, &limits)?;
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        if s.get_contact_by_email(&contact.email).is_some() {
+            return Err("Contact with this email already exists".to_string());
+        }
+
+        contact.source.get_or_insert_with(|| s.manual_contact_default_source.clone());
+
+        let created_contact = s.create_contact(contact.clone(), caller)?;
+        deal_request.contact_id = created_contact.id;
+
+        let deal = match s.create_deal(deal_request.clone(), caller) {
+            Ok(deal) => deal,
+            Err(e) => {
+                // Roll back so no dangling contact is left behind
+                s.delete_contact(created_contact.id);
+                return Err(e);
+            }
+        };
+
         s.record_audit_log(
             caller,
-            "delete_contact",
+            AuditAction::CreateContact,
             "contact",
-            &id.to_string(),
+            &created_contact.id.to_string(),
             Some(serde_json::json!({
                 "email": contact.email,
-                "name": contact.name,
+                "source": format!("{:?}", contact.source.unwrap_or_default()),
+            }).to_string()),
+        );
+        s.record_audit_log(
+            caller,
+            AuditAction::CreateDeal,
+            "deal",
+            &deal.id.to_string(),
+            Some(serde_json::json!({
+                "contact_id": deal.contact_id,
+                "name": deal.name,
+                "value": deal.value,
             }).to_string()),
         );
 
-        // Perform deletion
-        s.delete_contact(id)
-            .ok_or("Failed to delete contact".to_string())
+        ic_cdk::println!("Created contact {} with deal {}", created_contact.id, deal.id);
+        Ok((created_contact, deal))
     })
 }
 
-// =============================================================================
-// Deal API
-// =============================================================================
+/// Contacts that opted in to marketing, for building compliant mailing lists (admin only)
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_consented_contacts(pagination: Option<PaginationParams>) -> Result<PaginatedResponse<Contact>, String> {
+    require_admin("get_consented_contacts")?;
+    let caller = ic_cdk::caller();
 
-/// Create a new deal (admin only)
-/// @see AC-5.6.10.1 - Sets owner_id to caller for row-level security
-/// @see AC-5.6.10.4 - Audit logging
-/// @see AC-5.6.11.1, AC-5.6.11.3 - Input validation
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_consented_contacts(s.resolve_pagination(pagination), &caller)
+    }))
+}
+
+/// Advisory data-quality messages for a contact (admin only); never fails validation
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_contact_warnings(id: ContactId) -> Result<Vec<String>, String> {
+    require_admin("get_contact_warnings")?;
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let s = state.borrow();
+        s.get_contact(id)
+            .filter(|c| s.can_view_contact(&caller, c))
+            .map(contact_warnings)
+            .ok_or("Contact not found".to_string())
+    })
+}
+
+/// Advisory data-quality messages for a deal (admin only); never fails validation
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_deal_warnings(id: DealId) -> Result<Vec<String>, String> {
+    require_admin("get_deal_warnings")?;
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        let s = state.borrow();
+        s.get_deal(id)
+            .filter(|d| s.can_view_deal(&caller, d))
+            .map(|d| deal_warnings(d, now))
+            .ok_or("Deal not found".to_string())
+    })
+}
+
+/// List deals quarantined on restore because their contact was missing (controller only)
+#[query]
+fn list_orphaned_deals() -> Result<Vec<Deal>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| state.borrow().orphaned_deals.clone()))
+}
+
+/// Verify the contact/deal indexes match the primary maps (controller only)
+/// Returns one line per inconsistency; an empty list means the state is consistent
+#[query]
+fn check_invariants() -> Result<Vec<String>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| state.borrow().check_invariants()))
+}
+
+/// Live deals whose `contact_id` points at a missing contact (controller only)
+/// Unlike `list_orphaned_deals`, these were not quarantined on restore
+#[query]
+fn validate_deal_integrity() -> Result<Vec<DealId>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| state.borrow().find_orphaned_deal_ids()))
+}
+
+/// Delete live deals whose contact no longer exists (controller only)
+/// Returns the number of deals removed
 #[update]
-fn create_deal(request: CreateDealRequest) -> Result<Deal, String> {
-    require_admin()?;
+async fn delete_orphaned_deals() -> Result<u64, String> {
+    require_controller("delete_orphaned_deals").await?;
     let caller = ic_cdk::caller();
 
-    // FOS-5.6.11: Validate input before processing
-    validate_create_deal(&request)?;
+    let removed = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let removed = s.delete_orphaned_deals();
+        for deal in &removed {
+            s.record_audit_log(
+                caller,
+                AuditAction::DeleteOrphanedDeal,
+                "deal",
+                &deal.id.to_string(),
+                Some(serde_json::json!({ "contact_id": deal.contact_id, "name": deal.name }).to_string()),
+            );
+        }
+        removed.len() as u64
+    });
 
-    STATE.with(|state| {
+    ic_cdk::println!("Deleted {} orphaned deals", removed);
+    Ok(removed)
+}
+
+/// List contacts with no owner, which row-level security hides from non-managers (controller only)
+/// These can be left behind when `migrate_ownership` ran with no admins
+#[query]
+fn list_unowned_contacts() -> Result<Vec<ContactId>, String> {
+    require_controller_query()?;
+    Ok(STATE.with(|state| state.borrow().list_unowned_contacts()))
+}
+
+/// Assign every owner-less contact and deal to an admin (controller only)
+/// `None` uses the migration default owner, falling back to the first admin
+/// Returns the number of contacts and deals fixed
+#[update]
+async fn assign_unowned_to(owner: Option<Principal>) -> Result<(u64, u64), String> {
+    require_controller("assign_unowned_to").await?;
+    let caller = ic_cdk::caller();
+
+    let (contacts, deals) = STATE.with(|state| {
         let mut s = state.borrow_mut();
-        let deal = s.create_deal(request.clone(), caller)?;
+        let fixed = s.assign_unowned_to(owner)?;
+        let target = owner.or_else(|| s.migration_owner()).map(|o| o.to_string()).unwrap_or_default();
+        s.record_audit_log(
+            caller,
+            AuditAction::AssignUnownedTo,
+            "config",
+            &target,
+            Some(serde_json::json!({ "contacts": fixed.0, "deals": fixed.1 }).to_string()),
+        );
+        Ok::<_, String>(fixed)
+    })?;
 
-        // Audit log
+    ic_cdk::println!("Assigned {} unowned contacts and {} unowned deals to {:?}", contacts, deals, owner);
+    Ok((contacts, deals))
+}
+
+/// Choose the admin that ownership migrations and `assign_unowned_to` default to (controller only)
+/// `None` restores the first admin
+#[update]
+async fn set_migration_default_owner(owner: Option<Principal>) -> Result<(), String> {
+    require_controller("set_migration_default_owner").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.set_migration_default_owner(owner)?;
         s.record_audit_log(
             caller,
-            "create_deal",
-            "deal",
-            &deal.id.to_string(),
-            Some(serde_json::json!({
-                "contact_id": request.contact_id,
-                "name": request.name,
-                "value": request.value,
-            }).to_string()),
+            AuditAction::SetMigrationDefaultOwner,
+            "config",
+            "migration_default_owner",
+            Some(serde_json::json!({ "owner": owner.map(|o| o.to_string()) }).to_string()),
         );
+        Ok::<_, String>(())
+    })?;
 
-        Ok(deal)
-    })
+    ic_cdk::println!("Migration default owner set to {:?}", owner);
+    Ok(())
 }
 
 /// Get deal by ID (admin only)
+/// Returns `None` for deals the caller may not view, as in `get_deals`
 /// @see AC-5.6.8.5 - Query endpoints require admin authorization
+/// @see AC-5.6.10.1 - Row-level security filtering
 #[query]
 fn get_deal(id: DealId) -> Result<Option<Deal>, String> {
-    require_admin()?;
-    Ok(STATE.with(|state| state.borrow().get_deal(id).cloned()))
+    require_admin("get_deal")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_deal(id).filter(|d| s.can_view_deal(&caller, d)).cloned()
+    }))
+}
+
+/// Get deal by ID with derived display values (admin only)
+/// `value` is in cents; `value_display_dollars` is the canonical dollar rendering
+#[query]
+fn get_deal_view(id: DealId) -> Result<Option<DealView>, String> {
+    require_admin("get_deal_view")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        s.get_deal(id).filter(|d| s.can_view_deal(&caller, d)).cloned().map(DealView::from)
+    }))
 }
 
 /// Update deal stage with ownership check
+/// A given `custom_stage` must name a stage of the configured pipeline; omitting it keeps the current one
 /// @see AC-5.6.10.3 - Granular CRUD permissions
 /// @see AC-5.6.10.4 - Audit logging
 #[update]
-fn update_deal_stage(id: DealId, stage: DealStage) -> Result<Deal, String> {
-    require_admin()?;
+fn update_deal_stage(id: DealId, stage: DealStage, custom_stage: Option<String>) -> Result<Deal, String> {
+    require_admin("update_deal_stage")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
     let caller = ic_cdk::caller();
 
     STATE.with(|state| {
@@ -629,49 +2091,99 @@ fn update_deal_stage(id: DealId, stage: DealStage) -> Result<Deal, String> {
             .ok_or("Deal not found")?
             .clone();
 
-        // Check permissions
-        let has_edit_all = s.has_permission(&caller, &AdminPermission::EditAllDeals);
-        let has_edit_own = s.has_permission(&caller, &AdminPermission::EditOwnDeals);
-        let is_owner = deal.owner_id.as_ref() == Some(&caller);
-
-        if !has_edit_all && !(has_edit_own && is_owner) {
+        if !s.can_edit_deal(&caller, &deal) {
             return Err("Unauthorized: Cannot update this deal".to_string());
         }
 
+        s.check_pipeline_stage(custom_stage.as_deref())?;
+        s.check_deal_close_approval(&deal, &stage, deal.value, &caller)?;
+
         // Capture old stage for audit
         let old_stage = format!("{:?}", deal.stage);
 
         // Perform update
-        let updated = s.update_deal_stage(id, stage.clone())
+        let updated = s.update_deal_stage(id, stage.clone(), custom_stage)
             .ok_or("Failed to update deal stage")?;
 
         // Audit log
         s.record_audit_log(
             caller,
-            "update_deal_stage",
+            AuditAction::UpdateDealStage,
             "deal",
             &id.to_string(),
             Some(serde_json::json!({
-                "old_stage": old_stage,
-                "new_stage": format!("{:?}", stage),
+                "old": { "stage": old_stage, "custom_stage": deal.custom_stage },
+                "new": { "stage": format!("{:?}", stage), "custom_stage": updated.custom_stage },
             }).to_string()),
         );
+        rederive_contact_status(&mut s, &updated, caller);
 
         Ok(updated)
     })
 }
 
+/// Sign off on closing a high-value deal as won (requires edit permission on the deal)
+/// The close itself must then be made by a different admin than the approver
+#[update]
+fn approve_deal_close(id: DealId) -> Result<Vec<Principal>, String> {
+    require_admin("approve_deal_close")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        let deal = s.get_deal(id).ok_or("Deal not found")?;
+        if !s.can_edit_deal(&caller, deal) {
+            return Err("Unauthorized: Cannot approve this deal".to_string());
+        }
+
+        let approvers = s.approve_deal_close(id, caller)?;
+        s.record_audit_log(
+            caller,
+            AuditAction::ApproveDealClose,
+            "deal",
+            &id.to_string(),
+            Some(serde_json::json!({ "approvals": approvers.len() }).to_string()),
+        );
+        Ok(approvers)
+    })
+}
+
+/// After a deal stage change, re-derive its contact's status (`auto_status_from_deals`)
+/// and audit the change under the canister, like auto-churn
+fn rederive_contact_status(s: &mut State, deal: &Deal, caller: Principal) {
+    if let Some((old, new)) = s.derive_contact_status_from_deals(deal.contact_id, deal.updated_at) {
+        s.record_audit_log(
+            ic_cdk::id(),
+            AuditAction::AutoDeriveContactStatus,
+            "contact",
+            &deal.contact_id.to_string(),
+            Some(serde_json::json!({
+                "old": { "status": format!("{:?}", old) },
+                "new": { "status": format!("{:?}", new) },
+                "deal_id": deal.id,
+                "triggered_by": caller.to_string(),
+            }).to_string()),
+        );
+    }
+}
+
 /// Update a deal with permission check
 /// @see AC-5.6.10.3 - Granular CRUD permissions (EditOwnDeals/EditAllDeals)
 /// @see AC-5.6.10.4 - Audit logging
 /// @see AC-5.6.11.1, AC-5.6.11.3 - Input validation
 #[update]
 fn update_deal(request: UpdateDealRequest) -> Result<Deal, String> {
-    require_admin()?;
+    require_admin("update_deal")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
     let caller = ic_cdk::caller();
 
     // FOS-5.6.11: Validate input before processing
-    validate_update_deal(&request)?;
+    let limits = STATE.with(|state| state.borrow().name_length_limits.clone());
+    validate_update_deal(&request, &limits)?;
 
     STATE.with(|state| {
         let mut s = state.borrow_mut();
@@ -681,24 +2193,32 @@ fn update_deal(request: UpdateDealRequest) -> Result<Deal, String> {
             .ok_or("Deal not found")?
             .clone();
 
-        // Check permissions
-        let has_edit_all = s.has_permission(&caller, &AdminPermission::EditAllDeals);
-        let has_edit_own = s.has_permission(&caller, &AdminPermission::EditOwnDeals);
-        let is_owner = deal.owner_id.as_ref() == Some(&caller);
-
-        if !has_edit_all && !(has_edit_own && is_owner) {
+        if !s.can_edit_deal(&caller, &deal) {
             return Err("Unauthorized: Cannot edit this deal".to_string());
         }
 
+        // No-op update: leave updated_at and the audit log untouched
+        if !request.has_changes() {
+            return Ok(deal);
+        }
+
+        s.check_pipeline_stage(request.custom_stage.as_deref())?;
+        if let Some(ref stage) = request.stage {
+            s.check_deal_close_approval(&deal, stage, request.value.or(deal.value), &caller)?;
+        }
+
         // Capture old values for audit
         let old_values = serde_json::json!({
-            "name": deal.name,
-            "value": deal.value,
-            "stage": format!("{:?}", deal.stage),
+            "old": {
+                "name": deal.name,
+                "value": deal.value,
+                "stage": format!("{:?}", deal.stage),
+                "custom_stage": deal.custom_stage,
+            },
         }).to_string();
 
         // Perform update
-        let updated = s.update_deal(
+        let mut updated = s.update_deal(
             request.id,
             request.name,
             request.value,
@@ -706,15 +2226,140 @@ fn update_deal(request: UpdateDealRequest) -> Result<Deal, String> {
             request.notes,
             request.expected_close_date,
         ).ok_or("Failed to update deal")?;
+        if let Some(custom_stage) = request.custom_stage {
+            updated = s.set_deal_custom_stage(request.id, custom_stage).ok_or("Failed to update deal")?;
+        }
 
         // Audit log
         s.record_audit_log(
             caller,
-            "update_deal",
+            AuditAction::UpdateDeal,
             "deal",
             &request.id.to_string(),
             Some(old_values),
         );
+        if updated.stage != deal.stage {
+            rederive_contact_status(&mut s, &updated, caller);
+        }
+
+        Ok(updated)
+    })
+}
+
+/// Append a timestamped comment to a deal
+/// @see AC-5.6.10.3 - Requires edit permission on the deal (EditOwnDeals/EditAllDeals)
+/// @see AC-5.6.10.4 - Audit logging
+#[update]
+fn add_deal_comment(id: DealId, text: String) -> Result<Deal, String> {
+    require_admin("add_deal_comment")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    validate_deal_comment(&text)?;
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        let deal = s.get_deal(id)
+            .ok_or("Deal not found")?
+            .clone();
+
+        if !s.can_edit_deal(&caller, &deal) {
+            return Err("Unauthorized: Cannot edit this deal".to_string());
+        }
+
+        let updated = s.add_deal_comment(id, caller, text)
+            .ok_or("Failed to add comment")?;
+
+        s.record_audit_log(
+            caller,
+            AuditAction::AddDealComment,
+            "deal",
+            &id.to_string(),
+            Some(serde_json::json!({
+                "comment_count": updated.comments.len(),
+            }).to_string()),
+        );
+
+        Ok(updated)
+    })
+}
+
+/// Link an external document (https:// or ipfs:// URL) to a deal
+/// @see AC-5.6.10.3 - Requires edit permission on the deal (EditOwnDeals/EditAllDeals)
+/// @see AC-5.6.10.4 - Audit logging
+#[update]
+fn add_deal_attachment(id: DealId, name: String, url: String) -> Result<Deal, String> {
+    require_admin("add_deal_attachment")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    // FOS-5.6.11: Validate input before processing
+    let name = name.trim().to_string();
+    let url = url.trim().to_string();
+    validate_deal_attachment(&name, &url)?;
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        let deal = s.get_deal(id)
+            .ok_or("Deal not found")?
+            .clone();
+
+        if !s.can_edit_deal(&caller, &deal) {
+            return Err("Unauthorized: Cannot edit this deal".to_string());
+        }
+
+        let updated = s.add_deal_attachment(id, name.clone(), url.clone(), caller)?;
+
+        s.record_audit_log(
+            caller,
+            AuditAction::AddDealAttachment,
+            "deal",
+            &id.to_string(),
+            Some(serde_json::json!({
+                "name": name,
+                "url": url,
+            }).to_string()),
+        );
+
+        Ok(updated)
+    })
+}
+
+/// Unlink the attachment with `url` from a deal
+/// @see AC-5.6.10.3 - Requires edit permission on the deal (EditOwnDeals/EditAllDeals)
+/// @see AC-5.6.10.4 - Audit logging
+#[update]
+fn remove_deal_attachment(id: DealId, url: String) -> Result<Deal, String> {
+    require_admin("remove_deal_attachment")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        let deal = s.get_deal(id)
+            .ok_or("Deal not found")?
+            .clone();
+
+        if !s.can_edit_deal(&caller, &deal) {
+            return Err("Unauthorized: Cannot edit this deal".to_string());
+        }
+
+        let updated = s.remove_deal_attachment(id, url.trim())?;
+
+        s.record_audit_log(
+            caller,
+            AuditAction::RemoveDealAttachment,
+            "deal",
+            &id.to_string(),
+            Some(serde_json::json!({ "url": url.trim() }).to_string()),
+        );
 
         Ok(updated)
     })
@@ -725,7 +2370,9 @@ fn update_deal(request: UpdateDealRequest) -> Result<Deal, String> {
 /// @see AC-5.6.10.4 - Audit logging
 #[update]
 fn delete_deal(id: DealId) -> Result<Deal, String> {
-    require_admin()?;
+    require_admin("delete_deal")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
     let caller = ic_cdk::caller();
 
     STATE.with(|state| {
@@ -748,7 +2395,7 @@ fn delete_deal(id: DealId) -> Result<Deal, String> {
         // Audit log before deletion
         s.record_audit_log(
             caller,
-            "delete_deal",
+            AuditAction::DeleteDeal,
             "deal",
             &id.to_string(),
             Some(serde_json::json!({
@@ -765,39 +2412,97 @@ fn delete_deal(id: DealId) -> Result<Deal, String> {
 }
 
 /// Get deals with row-level security filtering
+/// Filtering by `owner_id` is reserved for ViewAllDeals holders and controllers
 /// @see AC-5.6.10.1 - Row-level security filtering
 #[query]
 fn get_deals(
     filter: Option<DealFilter>,
     pagination: Option<PaginationParams>,
 ) -> Result<PaginatedResponse<Deal>, String> {
-    require_admin()?;
+    require_admin("get_deals")?;
     let caller = ic_cdk::caller();
 
-    Ok(STATE.with(|state| {
-        state.borrow().get_deals(filter, pagination.unwrap_or_default(), &caller)
-    }))
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        let filters_by_owner = filter.as_ref().and_then(|f| f.owner_id).is_some();
+        if filters_by_owner && !s.has_permission(&caller, &AdminPermission::ViewAllDeals) {
+            return Err("Unauthorized: ViewAllDeals permission required to filter by owner".to_string());
+        }
+
+        Ok(s.get_deals(filter, s.resolve_pagination(pagination), &caller))
+    })
 }
 
 // =============================================================================
 // Transaction API
 // =============================================================================
 
-/// Create a new transaction (admin only)
-/// @see AC-5.6.11.1, AC-5.6.11.4 - Input validation with amount limits
-#[update]
-fn create_transaction(request: CreateTransactionRequest) -> Result<Transaction, String> {
-    require_admin()?;
+/// Create a new transaction (admin only)
+/// @see AC-5.6.11.1, AC-5.6.11.4 - Input validation with amount limits
+#[update]
+fn create_transaction(request: CreateTransactionRequest) -> Result<Transaction, String> {
+    require_admin("create_transaction")?;
+    require_not_maintenance()?;
+
+    // FOS-5.6.11: Validate input before processing
+    validate_create_transaction(&request)?;
+
+    let transaction = STATE.with(|state| {
+        state.borrow_mut().create_transaction(request)
+    })?;
+
+    ic_cdk::println!("Created transaction {}: {} {}", transaction.id, transaction.amount, transaction.currency);
+    Ok(transaction)
+}
+
+/// Import a statement's worth of transactions in one call (admin only)
+/// Rows are validated and created independently: invalid rows are reported by index
+/// without aborting the rest. The batch gets one summary audit entry.
+#[update]
+fn bulk_create_transactions(requests: Vec<CreateTransactionRequest>) -> Result<BulkImportResult, String> {
+    require_admin("bulk_create_transactions")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    if requests.len() > state::MAX_TRANSACTION_BATCH_SIZE {
+        return Err(format!(
+            "Batch too large: at most {} transactions per call",
+            state::MAX_TRANSACTION_BATCH_SIZE
+        ));
+    }
+
+    let result = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let mut result = BulkImportResult { created: Vec::new(), errors: Vec::new() };
 
-    // FOS-5.6.11: Validate input before processing
-    validate_create_transaction(&request)?;
+        for (index, request) in requests.into_iter().enumerate() {
+            match validate_create_transaction(&request).and_then(|_| s.create_transaction(request)) {
+                Ok(transaction) => result.created.push(transaction.id),
+                Err(e) => result.errors.push((index as u32, e)),
+            }
+        }
 
-    let transaction = STATE.with(|state| {
-        state.borrow_mut().create_transaction(request)
+        s.record_audit_log(
+            caller,
+            AuditAction::BulkCreateTransactions,
+            "transaction",
+            "batch",
+            Some(serde_json::json!({
+                "created": result.created.len(),
+                "rejected": result.errors.len(),
+            }).to_string()),
+        );
+        result
     });
 
-    ic_cdk::println!("Created transaction {}: {} {}", transaction.id, transaction.amount, transaction.currency);
-    Ok(transaction)
+    ic_cdk::println!(
+        "Bulk imported {} transactions ({} rejected)",
+        result.created.len(),
+        result.errors.len()
+    );
+    Ok(result)
 }
 
 #[query]
@@ -805,22 +2510,45 @@ fn get_transactions(
     filter: Option<TransactionFilter>,
     pagination: Option<PaginationParams>,
 ) -> Result<PaginatedResponse<Transaction>, String> {
-    require_admin()?;
+    require_admin("get_transactions")?;
 
     Ok(STATE.with(|state| {
-        state.borrow().get_transactions(filter, pagination.unwrap_or_default())
+        let s = state.borrow();
+        s.get_transactions(filter, s.resolve_pagination(pagination))
     }))
 }
 
+/// Get financial summary for a period
+/// `net_of_reversals` nets refunds/reversals against their original transaction
 #[query]
-fn get_financial_summary(from: Timestamp, to: Timestamp) -> Result<FinancialSummary, String> {
-    require_admin()?;
+fn get_financial_summary(
+    from: Timestamp,
+    to: Timestamp,
+    net_of_reversals: Option<bool>,
+) -> Result<FinancialSummary, String> {
+    require_admin("get_financial_summary")?;
 
     Ok(STATE.with(|state| {
-        state.borrow().get_financial_summary(from, to)
+        state.borrow().get_financial_summary(from, to, net_of_reversals.unwrap_or(false))
     }))
 }
 
+/// Financial summary for `[current_from, current_to]` alongside the equal-length
+/// period immediately before it, with percentage changes for income, expenses and net
+#[query]
+fn get_financial_comparison(current_from: Timestamp, current_to: Timestamp) -> Result<FinancialComparison, String> {
+    require_admin("get_financial_comparison")?;
+    STATE.with(|state| state.borrow().get_financial_comparison(current_from, current_to))
+}
+
+/// Net cash position (income minus expenses, in cents) as of `as_of`, inclusive
+/// Amounts are summed as-is across currencies, as in `get_financial_summary`
+#[query]
+fn get_cash_position_as_of(as_of: Timestamp) -> Result<i64, String> {
+    require_admin("get_cash_position_as_of")?;
+    Ok(STATE.with(|state| state.borrow().get_cash_position_as_of(as_of)))
+}
+
 // =============================================================================
 // Feature Flag API
 // =============================================================================
@@ -829,9 +2557,14 @@ fn get_financial_summary(from: Timestamp, to: Timestamp) -> Result<FinancialSumm
 /// @see AC-5.6.10.5 - Feature flag audit logging
 #[update]
 fn set_feature_flag(request: SetFeatureFlagRequest) -> Result<(), String> {
-    require_admin()?;
+    require_admin("set_feature_flag")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
     let caller = ic_cdk::caller();
 
+    // FOS-5.6.11: Validate input before processing
+    validate_set_feature_flag(&request)?;
+
     STATE.with(|state| {
         let mut s = state.borrow_mut();
 
@@ -840,11 +2573,18 @@ fn set_feature_flag(request: SetFeatureFlagRequest) -> Result<(), String> {
             return Err("Unauthorized: ManageFeatureFlags permission required".to_string());
         }
 
+        if s.is_flag_protected(&request.key) && !s.is_controller(&caller) {
+            return Err(format!("Feature flag '{}' is protected: only controllers can modify it", request.key));
+        }
+
+        s.check_flag_unchanged(&request)?;
+
         // Get old value for audit
         let old_value = s.get_feature_flag(&request.key).map(|f| serde_json::json!({
             "enabled": f.enabled,
             "percentage": f.percentage,
-        }).to_string());
+            "permille": f.permille,
+        }));
 
         // Perform update
         s.set_feature_flag(request.clone());
@@ -852,13 +2592,16 @@ fn set_feature_flag(request: SetFeatureFlagRequest) -> Result<(), String> {
         // Audit log
         s.record_audit_log(
             caller,
-            "set_feature_flag",
+            AuditAction::SetFeatureFlag,
             "feature_flag",
             &request.key,
             Some(serde_json::json!({
-                "old_value": old_value.unwrap_or_else(|| "null".to_string()),
-                "new_enabled": request.enabled,
-                "new_percentage": request.percentage,
+                "old": old_value,
+                "new": {
+                    "enabled": request.enabled,
+                    "percentage": request.percentage,
+                    "permille": request.permille,
+                },
             }).to_string()),
         );
 
@@ -866,9 +2609,227 @@ fn set_feature_flag(request: SetFeatureFlagRequest) -> Result<(), String> {
     })
 }
 
+/// Set several feature flags at once, all-or-nothing (requires ManageFeatureFlags)
+/// Every request is validated before any flag changes; the batch is recorded as one audit
+/// entry under target "batch" listing each key's old and new values. Returns the count set.
+#[update]
+fn set_feature_flags(requests: Vec<SetFeatureFlagRequest>) -> Result<u32, String> {
+    require_admin("set_feature_flags")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    if requests.len() > state::MAX_FEATURE_FLAG_BATCH_SIZE {
+        return Err(format!(
+            "Batch too large: at most {} flags per call",
+            state::MAX_FEATURE_FLAG_BATCH_SIZE
+        ));
+    }
+
+    let mut keys = std::collections::BTreeSet::new();
+    for request in &requests {
+        validate_set_feature_flag(request).map_err(|e| format!("Flag '{}': {}", request.key, e))?;
+        if !keys.insert(request.key.clone()) {
+            return Err(format!("Flag '{}' appears more than once", request.key));
+        }
+    }
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        if !s.has_permission(&caller, &AdminPermission::ManageFeatureFlags) {
+            return Err("Unauthorized: ManageFeatureFlags permission required".to_string());
+        }
+
+        if !s.is_controller(&caller) {
+            if let Some(key) = keys.iter().find(|k| s.is_flag_protected(k)) {
+                return Err(format!("Feature flag '{}' is protected: only controllers can modify it", key));
+            }
+        }
+
+        for request in &requests {
+            s.check_flag_unchanged(request)?;
+        }
+
+        let count = requests.len() as u32;
+        let mut changes = serde_json::Map::new();
+        for request in requests {
+            let old_value = s.get_feature_flag(&request.key).map(|f| serde_json::json!({
+                "enabled": f.enabled,
+                "percentage": f.percentage,
+                "permille": f.permille,
+            }));
+            changes.insert(
+                request.key.clone(),
+                serde_json::json!({
+                    "old": old_value,
+                    "new": {
+                        "enabled": request.enabled,
+                        "percentage": request.percentage,
+                        "permille": request.permille,
+                    },
+                }),
+            );
+            s.set_feature_flag(request);
+        }
+
+        s.record_audit_log(
+            caller,
+            AuditAction::SetFeatureFlags,
+            "feature_flag",
+            "batch",
+            Some(serde_json::json!({ "count": count, "flags": changes }).to_string()),
+        );
+
+        Ok(count)
+    })
+}
+
+/// Delete a feature flag (requires ManageFeatureFlags; protected flags are controller only)
+#[update]
+fn delete_feature_flag(key: String) -> Result<FeatureFlag, String> {
+    require_admin("delete_feature_flag")?;
+    require_not_maintenance()?;
+    require_audit_capacity()?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+
+        if !s.has_permission(&caller, &AdminPermission::ManageFeatureFlags) && !s.is_controller(&caller) {
+            return Err("Unauthorized: ManageFeatureFlags permission required".to_string());
+        }
+
+        if s.is_flag_protected(&key) && !s.is_controller(&caller) {
+            return Err(format!("Feature flag '{}' is protected: only controllers can modify it", key));
+        }
+
+        let flag = s.delete_feature_flag(&key).ok_or("Feature flag not found")?;
+
+        s.record_audit_log(
+            caller,
+            AuditAction::DeleteFeatureFlag,
+            "feature_flag",
+            &key,
+            Some(serde_json::json!({
+                "old": {
+                    "enabled": flag.enabled,
+                    "percentage": flag.percentage,
+                    "permille": flag.permille,
+                },
+            }).to_string()),
+        );
+
+        Ok(flag)
+    })
+}
+
+/// Change timeline for one feature flag from the audit log, newest first
+/// Requires ViewAuditLogs or ManageFeatureFlags
+#[query]
+fn get_feature_flag_history(key: String, limit: Option<u64>) -> Result<Vec<AuditLogEntry>, String> {
+    require_admin("get_feature_flag_history")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs)
+            && !s.has_permission(&caller, &AdminPermission::ManageFeatureFlags)
+        {
+            return Err("Unauthorized: ViewAuditLogs or ManageFeatureFlags permission required".to_string());
+        }
+
+        Ok(s.get_audit_log_for_target("feature_flag", &key, limit))
+    })
+}
+
+/// Flags whose `updated_at` is more than `unchanged_for_secs` old, oldest first, as pruning candidates
+/// Requires ManageFeatureFlags (controllers implicitly have it)
+#[query]
+fn get_stale_feature_flags(unchanged_for_secs: u64) -> Result<Vec<FeatureFlag>, String> {
+    require_admin("get_stale_feature_flags")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        if !s.has_permission(&caller, &AdminPermission::ManageFeatureFlags) && !s.is_controller(&caller) {
+            return Err("Unauthorized: ManageFeatureFlags permission required".to_string());
+        }
+
+        Ok(s.get_stale_feature_flags(unchanged_for_secs, ic_cdk::api::time()))
+    })
+}
+
+/// Replace the list of flag keys that only controllers may set or delete (controller only)
+#[update]
+async fn set_protected_flag_keys(keys: Vec<String>) -> Result<(), String> {
+    require_controller("set_protected_flag_keys").await?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        s.protected_flag_keys = keys.clone();
+        s.record_audit_log(
+            caller,
+            AuditAction::SetProtectedFlagKeys,
+            "config",
+            "protected_flag_keys",
+            Some(serde_json::json!({ "keys": keys }).to_string()),
+        );
+    });
+
+    ic_cdk::println!("Protected flag keys set: {:?}", keys);
+    Ok(())
+}
+
+/// List the flag keys that only controllers may set or delete (admin only)
+#[query]
+fn get_protected_flag_keys() -> Result<Vec<String>, String> {
+    require_admin("get_protected_flag_keys")?;
+    Ok(STATE.with(|state| state.borrow().protected_flag_keys.clone()))
+}
+
+/// Seed the documented starter flags (disabled) on a fresh deployment (controller only)
+/// Idempotent: flags that already exist are left untouched. Returns the count created.
+#[update]
+async fn seed_default_feature_flags() -> Result<u32, String> {
+    require_controller("seed_default_feature_flags").await?;
+    let caller = ic_cdk::caller();
+
+    let created = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let created = s.seed_default_feature_flags();
+        s.record_audit_log(
+            caller,
+            AuditAction::SeedDefaultFeatureFlags,
+            "feature_flag",
+            "defaults",
+            Some(serde_json::json!({ "created": created }).to_string()),
+        );
+        created
+    });
+
+    ic_cdk::println!("Seeded {} default feature flags", created.len());
+    Ok(created.len() as u32)
+}
+
+/// Get a feature flag
+/// Non-admin callers receive a redacted copy without `allowed_principals`, `excluded_principals` or `description`
 #[query]
 fn get_feature_flag(key: String) -> Option<FeatureFlag> {
-    STATE.with(|state| state.borrow().get_feature_flag(&key).cloned())
+    let caller = ic_cdk::caller();
+    let full_view = STATE.with(|state| state.borrow().is_admin(&caller));
+    STATE.with(|state| {
+        state.borrow().get_feature_flag(&key).map(|flag| {
+            if full_view {
+                flag.clone()
+            } else {
+                flag.redacted()
+            }
+        })
+    })
 }
 
 #[query]
@@ -877,9 +2838,54 @@ fn is_feature_enabled(key: String) -> bool {
     STATE.with(|state| state.borrow().is_feature_enabled(&key, &caller))
 }
 
+/// Update variant of `is_feature_enabled` for flags with `audit_evaluations` on
+/// True evaluations by authenticated callers are recorded in `flag_evaluations`
+/// (not the audit log), throttled per caller; checks beyond the budget still return normally
+#[update]
+fn check_feature_audited(key: String) -> bool {
+    let caller = ic_cdk::caller();
+    STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let enabled = s.is_feature_enabled(&key, &caller);
+        let audited = s.get_feature_flag(&key).is_some_and(|f| f.audit_evaluations);
+        if enabled
+            && audited
+            && caller != Principal::anonymous()
+            && s.check_rate_limit(&caller, state::FLAG_EVALUATION_BUCKET).is_ok()
+        {
+            s.record_flag_evaluation(FlagEvaluation {
+                timestamp: ic_cdk::api::time(),
+                caller,
+                key,
+            });
+        }
+        enabled
+    })
+}
+
+/// Recorded true evaluations of one flag, newest first
+/// Requires ViewAuditLogs or ManageFeatureFlags
+#[query]
+fn get_flag_evaluations(key: String, limit: Option<u64>) -> Result<Vec<FlagEvaluation>, String> {
+    require_admin("get_flag_evaluations")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+
+        if !s.has_permission(&caller, &AdminPermission::ViewAuditLogs)
+            && !s.has_permission(&caller, &AdminPermission::ManageFeatureFlags)
+        {
+            return Err("Unauthorized: ViewAuditLogs or ManageFeatureFlags permission required".to_string());
+        }
+
+        Ok(s.get_flag_evaluations(&key, limit))
+    })
+}
+
 #[query]
 fn list_feature_flags() -> Result<Vec<FeatureFlag>, String> {
-    require_admin()?;
+    require_admin("list_feature_flags")?;
 
     Ok(STATE.with(|state| state.borrow().list_feature_flags()))
 }
@@ -900,7 +2906,7 @@ fn log_activity(user_id: String, action: String, metadata: Option<String>) -> Re
 
     // Check and enforce rate limit (FOS-5.6.8)
     STATE.with(|state| {
-        state.borrow_mut().check_rate_limit(&caller)
+        state.borrow_mut().check_rate_limit(&caller, "log_activity")
     })?;
 
     STATE.with(|state| {
@@ -917,12 +2923,53 @@ fn log_activity(user_id: String, action: String, metadata: Option<String>) -> Re
     Ok(())
 }
 
+/// Log a batch of user activities in one call (requires admin or authorized canister)
+/// The whole batch consumes a single token from the `log_activity` rate-limit bucket
+/// @see AC-5.6.8.4 - Same authorization as log_activity
+#[update]
+fn log_activities(events: Vec<(String, String, Option<String>)>) -> Result<u32, String> {
+    require_authorized_canister_or_admin(&["user-service", "auth-service", "frontend"])?;
+
+    if events.len() > state::MAX_ACTIVITY_BATCH_SIZE {
+        return Err(format!(
+            "Batch too large: at most {} events per call",
+            state::MAX_ACTIVITY_BATCH_SIZE
+        ));
+    }
+
+    let caller = ic_cdk::caller();
+
+    // Check and enforce rate limit (FOS-5.6.8)
+    STATE.with(|state| {
+        state.borrow_mut().check_rate_limit(&caller, "log_activity")
+    })?;
+
+    let count = STATE.with(|state| {
+        let mut s = state.borrow_mut();
+        let count = s.log_activities(events);
+        s.cleanup_rate_limits();
+        count
+    });
+
+    Ok(count)
+}
+
+/// Conversion funnel over `activity_log`: distinct users per ordered step
+/// Each step must follow the previous one within `window_secs`
+#[query]
+fn get_activity_funnel(steps: Vec<String>, window_secs: u64) -> Result<Vec<(String, u64)>, String> {
+    require_admin("get_activity_funnel")?;
+    Ok(STATE.with(|state| state.borrow().get_activity_funnel(&steps, window_secs)))
+}
+
+/// Record a metrics snapshot (requires admin or the analytics-service canister)
 #[update]
 fn record_metrics(snapshot: MetricsSnapshot) -> Result<(), String> {
-    require_admin()?;
+    require_authorized_canister_or_admin(&["analytics-service"])?;
+    require_not_maintenance()?;
 
     STATE.with(|state| {
-        state.borrow_mut().record_metrics(snapshot);
+        state.borrow_mut().record_metrics(snapshot, ic_cdk::api::time());
     });
 
     Ok(())
@@ -930,16 +2977,77 @@ fn record_metrics(snapshot: MetricsSnapshot) -> Result<(), String> {
 
 #[query]
 fn list_metrics(from: Timestamp, to: Timestamp, limit: Option<u64>) -> Result<Vec<MetricsSnapshot>, String> {
-    require_admin()?;
+    require_admin("list_metrics")?;
     Ok(STATE.with(|state| state.borrow().list_metrics(from, to, limit)))
 }
 
 #[query]
 fn get_latest_metrics() -> Result<Option<MetricsSnapshot>, String> {
-    require_admin()?;
+    require_admin("get_latest_metrics")?;
     Ok(STATE.with(|state| state.borrow().get_latest_metrics()))
 }
 
+/// Deals reopened after closing more than `min_reopens` times, most reopened first
+/// Requires ViewAllDeals (controllers have it implicitly)
+#[query]
+fn get_frequently_reopened_deals(min_reopens: u32) -> Result<Vec<Deal>, String> {
+    require_admin("get_frequently_reopened_deals")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+        if !s.has_permission(&caller, &AdminPermission::ViewAllDeals) {
+            return Err("Unauthorized: ViewAllDeals permission required".to_string());
+        }
+        Ok(s.get_frequently_reopened_deals(min_reopens))
+    })
+}
+
+/// Average nanoseconds closed deals spent in each stage, in pipeline order (admin only)
+/// Only deals the caller can view are included; stages never left are omitted
+#[query]
+fn get_stage_velocity() -> Result<Vec<(DealStage, u64)>, String> {
+    require_admin("get_stage_velocity")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| state.borrow().get_stage_velocity(&caller)))
+}
+
+/// The caller's own contact and deal counts and open pipeline value (admin only)
+/// Holders of ViewAllContacts/ViewAllDeals get totals across all records
+#[query]
+fn get_my_stats() -> Result<MyStats, String> {
+    require_admin("get_my_stats")?;
+    let caller = ic_cdk::caller();
+    Ok(STATE.with(|state| state.borrow().get_my_stats(&caller)))
+}
+
+/// Per-rep deal board: open and won counts and values grouped by owner
+/// Requires ViewAllDeals (controllers have it implicitly)
+#[query]
+fn get_deals_by_owner() -> Result<Vec<OwnerDealSummary>, String> {
+    require_admin("get_deals_by_owner")?;
+    let caller = ic_cdk::caller();
+
+    STATE.with(|state| {
+        let s = state.borrow();
+        if !s.has_permission(&caller, &AdminPermission::ViewAllDeals) {
+            return Err("Unauthorized: ViewAllDeals permission required".to_string());
+        }
+        Ok(s.get_deals_by_owner())
+    })
+}
+
+/// Deal win rate (ClosedWon / (ClosedWon + ClosedLost)) overall and per owner
+/// `win_rate` is None when no deals were closed in the window
+/// @see AC-5.6.10.1 - Row-level security filtering
+#[query]
+fn get_win_rate(from: Option<Timestamp>, to: Option<Timestamp>) -> Result<WinRateReport, String> {
+    require_admin("get_win_rate")?;
+    let caller = ic_cdk::caller();
+
+    Ok(STATE.with(|state| state.borrow().get_win_rate(from, to, &caller)))
+}
+
 // =============================================================================
 // Stats & Health
 // =============================================================================
@@ -954,7 +3062,7 @@ pub struct AdminStats {
 
 #[query]
 fn get_admin_stats() -> Result<AdminStats, String> {
-    require_admin()?;
+    require_admin("get_admin_stats")?;
 
     Ok(STATE.with(|state| {
         let s = state.borrow();
@@ -972,6 +3080,115 @@ fn health() -> String {
     "ok".to_string()
 }
 
+/// Detailed health report for operators (admin only)
+#[derive(candid::CandidType, serde::Serialize)]
+pub struct HealthDetailed {
+    pub status: String,
+    pub cycle_balance: u64,
+    /// True when `cycle_balance` is below the configured threshold (0 disables the warning)
+    pub low_cycles: bool,
+    pub low_cycles_threshold: u64,
+    pub maintenance_mode: bool,
+    pub schema_version: u32,
+    /// True when unexported audit entries reached the high watermark
+    pub audit_export_needed: bool,
+    pub audit_log_len: u64,
+    pub audit_exported_through_id: u64,
+}
+
+#[query]
+fn health_detailed() -> Result<HealthDetailed, String> {
+    require_admin("health_detailed")?;
+
+    let cycle_balance = ic_cdk::api::canister_balance();
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        let low_cycles = s.low_cycles_threshold > 0 && cycle_balance < s.low_cycles_threshold;
+        HealthDetailed {
+            status: if low_cycles { "low_cycles" } else { "ok" }.to_string(),
+            cycle_balance,
+            low_cycles,
+            low_cycles_threshold: s.low_cycles_threshold,
+            maintenance_mode: s.maintenance_mode,
+            schema_version: s.schema_version,
+            audit_export_needed: s.audit_export_needed,
+            audit_log_len: s.audit_log.len() as u64,
+            audit_exported_through_id: s.audit_exported_through_id,
+        }
+    }))
+}
+
+/// Record count and approximate Candid-encoded size of one state collection
+#[derive(candid::CandidType, serde::Serialize)]
+pub struct CollectionSize {
+    pub count: u64,
+    pub approx_bytes: u64,
+}
+
+impl CollectionSize {
+    fn of<T: candid::CandidType>(items: &[T]) -> Self {
+        CollectionSize {
+            count: items.len() as u64,
+            approx_bytes: candid::encode_one(items).map(|b| b.len() as u64).unwrap_or(0),
+        }
+    }
+}
+
+/// Per-collection state sizes, for diagnosing memory growth (controller only)
+#[derive(candid::CandidType, serde::Serialize)]
+pub struct StateSizes {
+    pub contacts: CollectionSize,
+    pub deals: CollectionSize,
+    pub transactions: CollectionSize,
+    pub audit_log: CollectionSize,
+    pub activity_log: CollectionSize,
+    pub metrics_history: CollectionSize,
+    pub feature_flags: CollectionSize,
+    pub total_approx_bytes: u64,
+}
+
+/// Counts and rough byte estimates for the largest state collections (controller only)
+/// Estimates are the Candid-encoded length, not exact heap usage
+#[query]
+fn get_state_sizes() -> Result<StateSizes, String> {
+    require_controller_query()?;
+
+    Ok(STATE.with(|state| {
+        let s = state.borrow();
+        let contacts = CollectionSize::of(&s.contacts.values().collect::<Vec<_>>());
+        let deals = CollectionSize::of(&s.deals.values().collect::<Vec<_>>());
+        let transactions = CollectionSize::of(&s.transactions.values().collect::<Vec<_>>());
+        let audit_log = CollectionSize::of(&s.audit_log);
+        let activity_log = CollectionSize::of(&s.activity_log);
+        let metrics_history = CollectionSize::of(&s.metrics_history);
+        let feature_flags = CollectionSize::of(&s.feature_flags.values().collect::<Vec<_>>());
+        let total_approx_bytes = [
+            &contacts, &deals, &transactions, &audit_log, &activity_log, &metrics_history, &feature_flags,
+        ]
+        .iter()
+        .map(|c| c.approx_bytes)
+        .sum();
+
+        StateSizes {
+            contacts,
+            deals,
+            transactions,
+            audit_log,
+            activity_log,
+            metrics_history,
+            feature_flags,
+            total_approx_bytes,
+        }
+    }))
+}
+
+/// Current cycle balance of this canister (admin only)
+#[query]
+fn get_cycle_balance() -> Result<u64, String> {
+    require_admin("get_cycle_balance")?;
+    Ok(ic_cdk::api::canister_balance())
+}
+
 // =============================================================================
 // State Export/Import (FOS-5.6.19: Canister Backup & Export)
 // =============================================================================
@@ -986,7 +3203,7 @@ pub struct StateExportMetadata {
 }
 
 /// Export the canister's complete state for backup purposes.
-/// Returns Candid-encoded bytes containing metadata and state.
+/// Returns a version header followed by Candid-encoded metadata and state.
 #[query]
 fn export_state() -> Vec<u8> {
     use candid::encode_args;
@@ -998,12 +3215,14 @@ fn export_state() -> Vec<u8> {
     };
     STATE.with(|s| {
         let stable: StableState = (&*s.borrow()).into();
-        encode_args((metadata, stable)).expect("Failed to encode dao-admin state for export")
+        let payload = encode_args((metadata, stable)).expect("Failed to encode dao-admin state for export");
+        state::add_export_header(payload)
     })
 }
 
 /// Import state from a backup. Controller-only access.
-/// Accepts Candid-encoded bytes containing metadata and state.
+/// Accepts bytes produced by `export_state`, including header-less legacy exports;
+/// the header check rejects backups from a newer version.
 #[update]
 fn import_state(data: Vec<u8>) -> Result<(), String> {
     use candid::decode_args;
@@ -1012,19 +3231,20 @@ fn import_state(data: Vec<u8>) -> Result<(), String> {
         return Err("Unauthorized: controller access required".to_string());
     }
 
+    let payload = state::strip_export_header(&data)?;
     let (metadata, imported_stable): (StateExportMetadata, StableState) =
-        decode_args(&data).map_err(|e| format!("Failed to decode state: {}", e))?;
-
-    if metadata.state_version > state::STATE_VERSION {
-        return Err(format!(
-            "State version mismatch: backup v{} is newer than canister v{}",
-            metadata.state_version, state::STATE_VERSION
-        ));
+        decode_args(payload).map_err(|e| format!("Failed to decode state: {}", e))?;
+
+    // Convert StableState back to State, bringing older exports up to the current schema
+    let (restored, applied) = state::restore_imported_state(imported_stable);
+    ic_cdk::println!("[BACKUP] Applied {} data migrations to imported state", applied);
+    if !restored.orphaned_deals.is_empty() {
+        ic_cdk::println!(
+            "[BACKUP] Warning: {} orphaned deals quarantined (missing contact)",
+            restored.orphaned_deals.len()
+        );
     }
 
-    // Convert StableState back to State
-    let restored = State::from(imported_stable);
-
     STATE.with(|s| {
         *s.borrow_mut() = restored;
     });
@@ -8,10 +8,119 @@ const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
 
 /// Rate limit configuration (FOS-5.6.8)
 /// - Window: 1 minute sliding window
-/// - Max calls: 100 per caller per window
+/// - Max calls: 100 per caller per endpoint per window, unless overridden below
 pub const RATE_LIMIT_WINDOW_NS: u64 = 60 * NANOSECONDS_PER_SECOND;
 pub const RATE_LIMIT_MAX_CALLS: usize = 100;
 
+/// Per-endpoint rate limits (calls per window), keyed by bucket name
+/// Endpoints not listed here fall back to `RATE_LIMIT_MAX_CALLS`
+pub const RATE_LIMITS: &[(&str, usize)] = &[
+    ("log_activity", 100),
+    // Recorded feature flag evaluations, so one hot caller cannot crowd out the rest
+    (FLAG_EVALUATION_BUCKET, 10),
+];
+
+/// Per-caller bucket bounding recorded `check_feature_audited` evaluations
+pub const FLAG_EVALUATION_BUCKET: &str = "feature_evaluated";
+
+/// Bucket for `create_contact_from_signup`, limited by `signup_rate_limit` instead of `RATE_LIMITS`
+pub const SIGNUP_RATE_LIMIT_BUCKET: &str = "create_contact_from_signup";
+/// Default per-window signup cap per calling canister
+/// user-service calls this once per signup for all users, so it needs more headroom
+/// Controllers can change it with `set_signup_rate_limit`
+pub const DEFAULT_SIGNUP_RATE_LIMIT: u32 = 1000;
+/// Most (caller, bucket) pairs tracked in `rate_limit_violations`; beyond this the
+/// pairs with the fewest violations are dropped, so arbitrary principals cannot grow it
+pub const MAX_RATE_LIMIT_VIOLATIONS: usize = 1_000;
+
+/// Name prefix for signup auto-deals when the template sets none (or an invalid one)
+pub const DEFAULT_SIGNUP_DEAL_PREFIX: &str = "New signup: ";
+
+/// Default cycle balance below which `health_detailed` warns (0.5T cycles)
+pub const DEFAULT_LOW_CYCLES_THRESHOLD: u64 = 500_000_000_000;
+
+/// Default `MetricsRetention` windows: full resolution for 90 days, weekly after that
+pub const DEFAULT_METRICS_FULL_RESOLUTION_DAYS: u32 = 90;
+pub const DEFAULT_METRICS_DOWNSAMPLE_INTERVAL_DAYS: u32 = 7;
+/// Hard cap on `metrics_history` after compaction; the oldest snapshots go first
+pub const MAX_METRICS_HISTORY: usize = 365;
+const NANOSECONDS_PER_DAY: u64 = 24 * 60 * 60 * NANOSECONDS_PER_SECOND;
+
+/// Default cap on audit `details` length in characters, marker included
+/// Controllers can change it with `set_max_audit_detail_len`
+pub const DEFAULT_MAX_AUDIT_DETAIL_LEN: u32 = 2048;
+/// Smallest accepted `max_audit_detail_len`, so truncated details stay readable
+pub const MIN_AUDIT_DETAIL_LEN: u32 = 64;
+/// Appended to audit details cut at `max_audit_detail_len`
+pub const AUDIT_TRUNCATION_MARKER: &str = "…(truncated)";
+
+/// Audit log length that triggers an overflow, and how many of the oldest entries it drains
+pub const AUDIT_LOG_CAPACITY: u64 = 10_000;
+pub const AUDIT_LOG_OVERFLOW_DRAIN: u64 = 1_000;
+/// Most recent rejected access attempts kept in `access_denials`; older ones are dropped
+pub const MAX_ACCESS_DENIALS: usize = 1_000;
+/// Most recent audited flag evaluations kept in `flag_evaluations`; older ones are dropped
+pub const MAX_FLAG_EVALUATIONS: usize = 1_000;
+/// Default count of unexported audit entries at which `audit_export_needed` is raised
+/// Controllers can change it with `set_audit_export_policy`
+pub const DEFAULT_AUDIT_LOG_HIGH_WATERMARK: u64 = 9_000;
+
+/// Default per-window limit for authorized canisters (e.g. user-service bulk signups)
+/// Controllers can change it with `set_authorized_canister_rate_limit`
+pub const DEFAULT_AUTHORIZED_CANISTER_RATE_LIMIT: u32 = 1000;
+
+/// Rate limit for a bucket from `RATE_LIMITS`
+pub fn rate_limit_for(endpoint: &str) -> usize {
+    RATE_LIMITS
+        .iter()
+        .find(|(name, _)| *name == endpoint)
+        .map(|(_, limit)| *limit)
+        .unwrap_or(RATE_LIMIT_MAX_CALLS)
+}
+
+/// Maximum buckets returned by a single `get_contact_growth` query
+pub const MAX_GROWTH_BUCKETS: u64 = 1000;
+
+/// Minimum gap between auto-churn passes run from the heartbeat
+pub const AUTO_CHURN_INTERVAL_NS: u64 = 60 * NANOSECONDS_PER_SECOND;
+
+/// Maximum contacts marked Churned by a single auto-churn pass
+pub const AUTO_CHURN_BATCH_SIZE: usize = 100;
+
+/// Deleted-contact tombstones kept for change feeds; the oldest are dropped beyond this
+pub const MAX_CONTACT_TOMBSTONES: usize = 10_000;
+
+/// Minimum gap between invariant self-checks run from the heartbeat (1 hour)
+pub const INVARIANT_CHECK_INTERVAL_NS: u64 = 3600 * NANOSECONDS_PER_SECOND;
+
+/// Maximum events accepted by a single `log_activities` call
+pub const MAX_ACTIVITY_BATCH_SIZE: usize = 100;
+
+/// Maximum flags accepted by a single `set_feature_flags` call
+pub const MAX_FEATURE_FLAG_BATCH_SIZE: usize = 100;
+
+/// Maximum rows accepted by a single `bulk_create_transactions` call
+pub const MAX_TRANSACTION_BATCH_SIZE: usize = 500;
+
+/// Roles accepted by `register_authorized_canister`
+/// Controllers can extend the set with `add_canister_role`
+pub const KNOWN_CANISTER_ROLES: &[&str] = &[
+    "user-service",
+    "auth-service",
+    "frontend",
+    "notifier",
+    "analytics-service",
+];
+
+/// Starter feature flags created by `seed_default_feature_flags` (key, description)
+/// All are seeded disabled so a fresh deployment behaves exactly as before
+pub const DEFAULT_FEATURE_FLAGS: &[(&str, &str)] = &[
+    ("new_dashboard", "Redesigned admin dashboard"),
+    ("beta_reports", "Early access to new CRM and finance reports"),
+    ("maintenance_banner", "Show a maintenance notice banner in the frontend"),
+    ("signup_auto_deal", "Create a deal automatically for new signups"),
+];
+
 /// State structure for the DAO Admin canister
 #[derive(Default)]
 pub struct State {
@@ -21,26 +130,74 @@ pub struct State {
     /// Authorized canisters for inter-canister calls (role -> canister_id)
     /// Roles: "user-service", "auth-service", etc.
     pub authorized_canisters: BTreeMap<String, Principal>,
+    /// Controller-added roles accepted in addition to `KNOWN_CANISTER_ROLES`
+    pub extra_canister_roles: Vec<String>,
+    /// Per-window rate limit for authorized canisters (never below the endpoint default)
+    pub authorized_canister_rate_limit: u32,
+    /// Per-window cap on `create_contact_from_signup` calls per calling canister
+    pub signup_rate_limit: u32,
+    /// Rejected calls per (caller, bucket), for spotting runaway clients
+    pub rate_limit_violations: BTreeMap<(Principal, String), u64>,
+    /// Page size used when a list request omits `pagination` or its `limit`
+    pub default_page_size: u64,
 
     /// Granular admin permissions (FOS-5.6.10)
     /// @see AC-5.6.10.3 - Granular CRUD permissions
     pub admin_permissions: BTreeMap<Principal, Vec<AdminPermission>>,
 
-    /// Rate limiting: caller -> list of timestamps (FOS-5.6.8)
+    /// Rate limiting: (caller, endpoint) -> list of timestamps (FOS-5.6.8)
+    /// Each endpoint has an independent budget per caller
     /// NOTE: Intentionally NOT persisted in StableState - rate limits are ephemeral
     /// and time-bound (1 minute window). Stale timestamps would be invalid after upgrade.
-    pub rate_limit_buckets: BTreeMap<Principal, Vec<u64>>,
+    pub rate_limit_buckets: BTreeMap<(Principal, String), Vec<u64>>,
 
     // CRM - Contacts
     pub contacts: BTreeMap<ContactId, Contact>,
     pub contacts_by_email: BTreeMap<String, ContactId>,
     pub contacts_by_user: BTreeMap<String, ContactId>,
     pub next_contact_id: ContactId,
+    /// Recent contact deletions, oldest first (see `get_contact_changes_since`)
+    pub contact_tombstones: Vec<ContactTombstone>,
+    /// Strip `+tag` from the email local part when indexing for dedup
+    pub normalize_plus_addressing: bool,
+    /// Controller-managed email domains rejected on contact creation
+    pub blocked_email_domains: Vec<String>,
+    /// Controller-managed exact email addresses rejected on contact creation
+    pub blocked_emails: Vec<String>,
+    /// Source given to contacts created via `create_contact` without one
+    pub manual_contact_default_source: ContactSource,
+    /// Reject admin-created or updated contacts without a name
+    pub require_contact_name: bool,
+    /// Contact and deal name lengths enforced by validation
+    pub name_length_limits: NameLengthLimits,
+    /// Mark Active contacts Churned after this many seconds without activity (None = off)
+    pub auto_churn_after_secs: Option<u64>,
+    /// Re-derive contact status from deal outcomes on deal stage changes
+    pub auto_status_from_deals: bool,
+    /// Deals valued at or above this (cents) need a second admin's approval to close won (None = off)
+    pub deal_approval_threshold: Option<u64>,
+    /// Admins who approved closing each deal as won, via `approve_deal_close`
+    pub deal_close_approvals: BTreeMap<DealId, Vec<Principal>>,
+    /// Owner for records fixed by `migrate_ownership` and `assign_unowned_to`
+    /// (None = the first admin)
+    pub migration_default_owner: Option<Principal>,
+    /// When the heartbeat last ran an auto-churn pass (not persisted)
+    pub last_auto_churn_run: Timestamp,
+    /// When the heartbeat last ran `check_invariants` (not persisted)
+    pub last_invariant_check: Timestamp,
 
     // CRM - Deals
     pub deals: BTreeMap<DealId, Deal>,
     pub deals_by_contact: BTreeMap<ContactId, Vec<DealId>>,
     pub next_deal_id: DealId,
+    /// Deals quarantined on restore because their contact no longer exists
+    pub orphaned_deals: Vec<Deal>,
+    /// Controller-configured template for signup auto-deals
+    pub signup_deal_template: Option<SignupDealTemplate>,
+    /// Record one combined audit entry per signup (contact + auto-deal) instead of two
+    pub compact_signup_audit: bool,
+    /// Ordered custom pipeline stage names; empty means only the built-in stages
+    pub pipeline_stages: Vec<String>,
 
     // Finance - Transactions
     pub transactions: BTreeMap<TransactionId, Transaction>,
@@ -49,14 +206,46 @@ pub struct State {
     // Analytics
     pub activity_log: Vec<UserActivity>,
     pub metrics_history: Vec<MetricsSnapshot>,
+    /// Compaction windows for `metrics_history` (None = defaults)
+    pub metrics_retention: Option<MetricsRetention>,
 
     // Feature Flags
     pub feature_flags: BTreeMap<String, FeatureFlag>,
+    /// Flag keys only controllers may set or delete
+    pub protected_flag_keys: Vec<String>,
+    /// True evaluations of flags with `audit_evaluations`, kept apart from `audit_log`
+    /// because any caller can trigger them; bounded by `MAX_FLAG_EVALUATIONS`
+    pub flag_evaluations: Vec<FlagEvaluation>,
 
     // Audit Log (FOS-5.6.10)
     /// @see AC-5.6.10.4, AC-5.6.10.5 - Audit logging
     pub audit_log: Vec<AuditLogEntry>,
     pub next_audit_log_id: u64,
+    /// Rejected access attempts, kept apart from `audit_log` so unauthenticated
+    /// callers can neither evict audit history nor fill it up; bounded by `MAX_ACCESS_DENIALS`
+    pub access_denials: Vec<AccessDenial>,
+
+    /// Last data migration applied (see `SCHEMA_VERSION`)
+    pub schema_version: u32,
+
+    /// Block admin writes (controllers exempt) during migrations
+    pub maintenance_mode: bool,
+    /// Cycle balance below which health reports `low_cycles`; 0 disables the warning
+    pub low_cycles_threshold: u64,
+    /// Mask contact emails/names in audit `details` at write time
+    pub redact_pii_in_audit: bool,
+    /// Audit `details` longer than this many characters are truncated at write time
+    pub max_audit_detail_len: u32,
+    /// Also append CRM mutations to `activity_log`, keyed by the actor's principal text
+    pub mirror_crm_to_activity_log: bool,
+    /// Unexported audit entries at which `audit_export_needed` is raised
+    pub audit_log_high_watermark: u64,
+    /// Set when unexported entries reach the watermark; cleared by `acknowledge_audit_export`
+    pub audit_export_needed: bool,
+    /// Highest audit entry id an off-chain job has confirmed exporting
+    pub audit_exported_through_id: u64,
+    /// Reject audited writes rather than drain unexported entries on overflow
+    pub block_on_export: bool,
 }
 
 impl State {
@@ -65,22 +254,59 @@ impl State {
             controllers: Vec::new(),
             admins: Vec::new(),
             authorized_canisters: BTreeMap::new(),
+            extra_canister_roles: Vec::new(),
+            authorized_canister_rate_limit: DEFAULT_AUTHORIZED_CANISTER_RATE_LIMIT,
+            signup_rate_limit: DEFAULT_SIGNUP_RATE_LIMIT,
+            default_page_size: DEFAULT_PAGE_SIZE,
+            rate_limit_violations: BTreeMap::new(),
             admin_permissions: BTreeMap::new(),
             rate_limit_buckets: BTreeMap::new(),
             contacts: BTreeMap::new(),
             contacts_by_email: BTreeMap::new(),
             contacts_by_user: BTreeMap::new(),
             next_contact_id: 1,
+            contact_tombstones: Vec::new(),
+            normalize_plus_addressing: false,
+            blocked_email_domains: Vec::new(),
+            blocked_emails: Vec::new(),
+            manual_contact_default_source: ContactSource::Other,
+            require_contact_name: false,
+            name_length_limits: NameLengthLimits::default(),
+            auto_churn_after_secs: None,
+            auto_status_from_deals: false,
+            deal_approval_threshold: None,
+            deal_close_approvals: BTreeMap::new(),
+            migration_default_owner: None,
+            last_auto_churn_run: 0,
+            last_invariant_check: 0,
             deals: BTreeMap::new(),
             deals_by_contact: BTreeMap::new(),
             next_deal_id: 1,
+            orphaned_deals: Vec::new(),
+            signup_deal_template: None,
+            compact_signup_audit: false,
+            pipeline_stages: Vec::new(),
             transactions: BTreeMap::new(),
             next_transaction_id: 1,
             activity_log: Vec::new(),
             metrics_history: Vec::new(),
+            metrics_retention: None,
             feature_flags: BTreeMap::new(),
+            protected_flag_keys: Vec::new(),
             audit_log: Vec::new(),
+            access_denials: Vec::new(),
+            flag_evaluations: Vec::new(),
             next_audit_log_id: 1,
+            schema_version: SCHEMA_VERSION,
+            maintenance_mode: false,
+            low_cycles_threshold: DEFAULT_LOW_CYCLES_THRESHOLD,
+            redact_pii_in_audit: false,
+            max_audit_detail_len: DEFAULT_MAX_AUDIT_DETAIL_LEN,
+            mirror_crm_to_activity_log: false,
+            audit_log_high_watermark: DEFAULT_AUDIT_LOG_HIGH_WATERMARK,
+            audit_export_needed: false,
+            audit_exported_through_id: 0,
+            block_on_export: false,
         }
     }
 
@@ -88,23 +314,47 @@ impl State {
     // Rate Limiting (FOS-5.6.8 AC-4.2)
     // =========================================================================
 
-    /// Check rate limit for a caller, returning Ok if allowed or Err with message
+    /// Check rate limit for a caller on an endpoint bucket, returning Ok if allowed or Err with message
     /// Also cleans up expired entries and records the new call if allowed
-    pub fn check_rate_limit(&mut self, caller: &Principal) -> Result<(), String> {
+    /// Controllers are exempt; authorized canisters get `authorized_canister_rate_limit`,
+    /// except for signups which always use `signup_rate_limit`
+    /// Rejected calls are counted in `rate_limit_violations`
+    pub fn check_rate_limit(&mut self, caller: &Principal, endpoint: &str) -> Result<(), String> {
+        if self.is_controller(caller) {
+            return Ok(());
+        }
+
         let now = ic_cdk::api::time();
         let window_start = now.saturating_sub(RATE_LIMIT_WINDOW_NS);
+        let limit = if endpoint == SIGNUP_RATE_LIMIT_BUCKET {
+            self.signup_rate_limit as usize
+        } else if self.is_any_authorized_canister(caller) {
+            rate_limit_for(endpoint).max(self.authorized_canister_rate_limit as usize)
+        } else {
+            rate_limit_for(endpoint)
+        };
 
-        // Get or create the bucket for this caller
-        let bucket = self.rate_limit_buckets.entry(*caller).or_default();
+        // Get or create the bucket for this caller and endpoint
+        let bucket = self
+            .rate_limit_buckets
+            .entry((*caller, endpoint.to_string()))
+            .or_default();
 
         // Remove timestamps older than the window
         bucket.retain(|&ts| ts >= window_start);
 
         // Check if at limit
-        if bucket.len() >= RATE_LIMIT_MAX_CALLS {
+        if bucket.len() >= limit {
+            *self
+                .rate_limit_violations
+                .entry((*caller, endpoint.to_string()))
+                .or_insert(0) += 1;
+            if self.rate_limit_violations.len() > MAX_RATE_LIMIT_VIOLATIONS {
+                self.prune_rate_limit_violations();
+            }
             return Err(format!(
-                "Rate limit exceeded: {} calls per minute allowed, try again later",
-                RATE_LIMIT_MAX_CALLS
+                "Rate limit exceeded: {} calls per minute allowed for {}, try again later",
+                limit, endpoint
             ));
         }
 
@@ -125,6 +375,32 @@ impl State {
             bucket.retain(|&ts| ts >= window_start);
             !bucket.is_empty()
         });
+        self.prune_rate_limit_violations();
+    }
+
+    /// Keep only the `MAX_RATE_LIMIT_VIOLATIONS` pairs with the most violations
+    /// Prunes a tenth below the cap so a steady stream of new callers does not re-sort every call
+    fn prune_rate_limit_violations(&mut self) {
+        if self.rate_limit_violations.len() <= MAX_RATE_LIMIT_VIOLATIONS {
+            return;
+        }
+        let keep = MAX_RATE_LIMIT_VIOLATIONS - MAX_RATE_LIMIT_VIOLATIONS / 10;
+        let mut counts: Vec<u64> = self.rate_limit_violations.values().copied().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let min_kept = counts[keep - 1];
+
+        // Ties at the threshold are dropped oldest-key first until `keep` remain
+        let mut at_threshold = counts[..keep].iter().filter(|&&c| c == min_kept).count();
+        self.rate_limit_violations.retain(|_, count| {
+            if *count > min_kept {
+                true
+            } else if *count == min_kept && at_threshold > 0 {
+                at_threshold -= 1;
+                true
+            } else {
+                false
+            }
+        });
     }
 
     /// Check if a principal is a controller
@@ -147,11 +423,44 @@ impl State {
     /// Remove an admin
     pub fn remove_admin(&mut self, principal: &Principal) {
         self.admins.retain(|p| p != principal);
+        // A removed admin must not keep receiving migrated records
+        if self.migration_default_owner.as_ref() == Some(principal) {
+            self.migration_default_owner = None;
+        }
+    }
+
+    /// Roles that may be registered: the built-in set plus controller additions
+    pub fn valid_canister_roles(&self) -> Vec<String> {
+        KNOWN_CANISTER_ROLES
+            .iter()
+            .map(|r| r.to_string())
+            .chain(self.extra_canister_roles.iter().cloned())
+            .collect()
     }
 
     /// Register an authorized canister for inter-canister calls
-    pub fn register_authorized_canister(&mut self, role: String, canister_id: Principal) {
+    /// Rejects unknown roles so a typo cannot create a registration nothing checks
+    pub fn register_authorized_canister(&mut self, role: String, canister_id: Principal) -> Result<(), String> {
+        let valid_roles = self.valid_canister_roles();
+        if !valid_roles.contains(&role) {
+            return Err(format!(
+                "Unknown canister role '{}'. Valid roles: {}",
+                role,
+                valid_roles.join(", ")
+            ));
+        }
+
         self.authorized_canisters.insert(role, canister_id);
+        Ok(())
+    }
+
+    /// Allow an additional canister role to be registered; returns false if it was already allowed
+    pub fn add_canister_role(&mut self, role: String) -> bool {
+        if KNOWN_CANISTER_ROLES.contains(&role.as_str()) || self.extra_canister_roles.contains(&role) {
+            return false;
+        }
+        self.extra_canister_roles.push(role);
+        true
     }
 
     /// Unregister an authorized canister
@@ -221,6 +530,29 @@ impl State {
             .unwrap_or_default()
     }
 
+    /// Every admin and controller with their effective permissions
+    /// Controllers are reported with the full permission set
+    pub fn admins_with_permissions(&self) -> Vec<(Principal, Vec<AdminPermission>)> {
+        let mut principals: Vec<Principal> = self.admins.clone();
+        for controller in &self.controllers {
+            if !principals.contains(controller) {
+                principals.push(*controller);
+            }
+        }
+
+        principals
+            .into_iter()
+            .map(|p| {
+                let perms = if self.is_controller(&p) {
+                    AdminPermission::all()
+                } else {
+                    self.get_permissions(&p)
+                };
+                (p, perms)
+            })
+            .collect()
+    }
+
     /// Grant default permissions to a new admin (view own + edit own)
     pub fn grant_default_permissions(&mut self, principal: Principal) {
         let default_perms = vec![
@@ -242,20 +574,43 @@ impl State {
     // =========================================================================
 
     /// Record an audit log entry
+    /// `details` is a JSON object: creations and deletions record a flat snapshot of
+    /// the affected fields, changes record `old` and `new` objects
     /// @see AC-5.6.10.4, AC-5.6.10.5 - Audit logging
     pub fn record_audit_log(
         &mut self,
         actor: Principal,
-        action: &str,
+        action: AuditAction,
         target_type: &str,
         target_id: &str,
         details: Option<String>,
     ) {
+        // Hard cap: never grow past AUDIT_LOG_CAPACITY while the overflow drain is held back.
+        // Trapping rolls back the whole call, so no change lands without its audit entry;
+        // writers call `check_audit_capacity` up front to fail with a clean error instead
+        if let Err(e) = self.check_audit_capacity() {
+            ic_cdk::trap(&e);
+        }
+
+        let details = if self.redact_pii_in_audit {
+            details.map(|d| redact_audit_details(&d, target_type == "contact"))
+        } else {
+            details
+        };
+        let details = details.map(|d| truncate_audit_details(d, self.max_audit_detail_len as usize));
+
+        let timestamp = ic_cdk::api::time();
+        if self.mirror_crm_to_activity_log {
+            if let Some(activity) = action.crm_activity() {
+                self.mirror_activity(actor, activity, target_type, target_id, timestamp);
+            }
+        }
+
         let entry = AuditLogEntry {
             id: self.next_audit_log_id,
-            timestamp: ic_cdk::api::time(),
+            timestamp,
             actor,
-            action: action.to_string(),
+            action: action.as_str().to_string(),
             target_type: target_type.to_string(),
             target_id: target_id.to_string(),
             details,
@@ -264,10 +619,138 @@ impl State {
         self.next_audit_log_id += 1;
         self.audit_log.push(entry);
 
-        // Keep only last 10000 entries
-        if self.audit_log.len() > 10000 {
-            self.audit_log.drain(0..1000);
+        if self.unexported_audit_entries() >= self.audit_log_high_watermark {
+            self.audit_export_needed = true;
+        }
+
+        // Drain the oldest entries on overflow, unless that would lose unexported ones
+        if self.audit_log.len() as u64 > AUDIT_LOG_CAPACITY && !self.audit_overflow_blocked() {
+            self.audit_log.drain(0..AUDIT_LOG_OVERFLOW_DRAIN as usize);
+        }
+    }
+
+    /// Record the single `compact_signup_audit` entry for a new signup contact and its auto-deal
+    /// When mirroring is on the deal also gets its own `deal_created` activity, so the
+    /// activity log matches what the separate contact and deal entries would have produced
+    pub fn record_compact_signup_audit(&mut self, actor: Principal, contact: &Contact, deal_id: Option<DealId>) {
+        self.record_audit_log(
+            actor,
+            AuditAction::CreateContactFromSignup,
+            "contact",
+            &contact.id.to_string(),
+            Some(serde_json::json!({
+                "source": "user_signup",
+                "user_id": contact.user_id,
+                "deal_id": deal_id,
+            }).to_string()),
+        );
+
+        if self.mirror_crm_to_activity_log {
+            if let (Some(deal_id), Some(activity)) = (deal_id, AuditAction::CreateDealFromSignup.crm_activity()) {
+                self.mirror_activity(actor, activity, "deal", &deal_id.to_string(), ic_cdk::api::time());
+            }
+        }
+    }
+
+    /// Append a CRM mutation to `activity_log` with the actor's principal as `user_id`
+    /// Bypasses `log_activity` so synthetic ids never touch contact `last_activity_at`
+    fn mirror_activity(&mut self, actor: Principal, action: &str, target_type: &str, target_id: &str, now: Timestamp) {
+        self.activity_log.push(UserActivity {
+            user_id: actor.to_text(),
+            action: action.to_string(),
+            metadata: Some(serde_json::json!({ "target_type": target_type, "target_id": target_id }).to_string()),
+            timestamp: now,
+        });
+
+        if self.activity_log.len() > 10000 {
+            self.activity_log.drain(0..1000);
+        }
+    }
+
+    /// Audit entries newer than `audit_exported_through_id`
+    pub fn unexported_audit_entries(&self) -> u64 {
+        let exported = self
+            .audit_log
+            .partition_point(|e| e.id <= self.audit_exported_through_id);
+        (self.audit_log.len() - exported) as u64
+    }
+
+    /// True when `block_on_export` is set and the next overflow drain would drop unexported entries
+    fn audit_overflow_blocked(&self) -> bool {
+        self.block_on_export
+            && self
+                .audit_log
+                .get(AUDIT_LOG_OVERFLOW_DRAIN as usize - 1)
+                .is_some_and(|e| e.id > self.audit_exported_through_id)
+    }
+
+    /// True while the log is full of unexported entries and `block_on_export` is set
+    pub fn audit_log_full(&self) -> bool {
+        self.audit_log.len() as u64 >= AUDIT_LOG_CAPACITY && self.audit_overflow_blocked()
+    }
+
+    /// Reject audited writes while the log is full of unexported entries and `block_on_export` is set
+    pub fn check_audit_capacity(&self) -> Result<(), String> {
+        if self.audit_log_full() {
+            let oldest_drained = self.audit_log[AUDIT_LOG_OVERFLOW_DRAIN as usize - 1].id;
+            Err(format!(
+                "Audit log is full of unexported entries: export it and call acknowledge_audit_export through at least entry {}",
+                oldest_drained
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Record that entries up to `through_id` were exported, clearing `audit_export_needed`
+    /// when the remaining unexported entries are back under the watermark
+    pub fn acknowledge_audit_export(&mut self, through_id: u64) -> Result<(), String> {
+        if through_id >= self.next_audit_log_id {
+            return Err(format!("Audit entry {} does not exist yet", through_id));
+        }
+        self.audit_exported_through_id = self.audit_exported_through_id.max(through_id);
+        self.audit_export_needed = self.unexported_audit_entries() >= self.audit_log_high_watermark;
+        Ok(())
+    }
+
+    /// Remove audit entries recorded before `timestamp`, returning how many were removed
+    /// With `block_on_export` set, unexported entries are kept whatever their age
+    pub fn purge_audit_entries_before(&mut self, timestamp: Timestamp) -> u64 {
+        let before = self.audit_log.len();
+        let keep_unexported = self.block_on_export;
+        let exported_through = self.audit_exported_through_id;
+        self.audit_log
+            .retain(|entry| entry.timestamp >= timestamp || (keep_unexported && entry.id > exported_through));
+        self.audit_export_needed = self.unexported_audit_entries() >= self.audit_log_high_watermark;
+        (before - self.audit_log.len()) as u64
+    }
+
+    /// Audit entries for one record, newest first
+    pub fn get_audit_log_for_target(&self, target_type: &str, target_id: &str, limit: Option<u64>) -> Vec<AuditLogEntry> {
+        let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE) as usize;
+
+        self.audit_log
+            .iter()
+            .rev()
+            .filter(|entry| entry.target_type == target_type && entry.target_id == target_id)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Count audit entries per (actor, action) at or after `since`, busiest first
+    pub fn get_recent_action_rates(&self, since: Timestamp) -> Vec<(Principal, String, u64)> {
+        let mut counts: BTreeMap<(Principal, &str), u64> = BTreeMap::new();
+        for entry in self.audit_log.iter().rev().take_while(|e| e.timestamp >= since) {
+            *counts.entry((entry.actor, entry.action.as_str())).or_insert(0) += 1;
         }
+
+        let mut rates: Vec<(Principal, String, u64)> = counts
+            .into_iter()
+            .map(|((actor, action), count)| (actor, action.to_string(), count))
+            .collect();
+        rates.sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+        rates
     }
 
     /// Get audit log entries with optional filtering
@@ -276,9 +759,10 @@ impl State {
         action_filter: Option<&str>,
         target_type_filter: Option<&str>,
         actor_filter: Option<&Principal>,
+        target_id_contains: Option<&str>,
         limit: Option<u64>,
     ) -> Vec<AuditLogEntry> {
-        let limit = limit.unwrap_or(100) as usize;
+        let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE) as usize;
 
         self.audit_log
             .iter()
@@ -287,27 +771,61 @@ impl State {
                 action_filter.map_or(true, |a| entry.action == a)
                     && target_type_filter.map_or(true, |t| entry.target_type == t)
                     && actor_filter.map_or(true, |p| &entry.actor == p)
+                    && target_id_contains.is_none_or(|t| entry.target_id.contains(t))
             })
             .take(limit)
             .cloned()
             .collect()
     }
 
+    /// Record a rejected access attempt, dropping the oldest beyond `MAX_ACCESS_DENIALS`
+    pub fn record_access_denial(&mut self, denial: AccessDenial) {
+        self.access_denials.push(denial);
+        if self.access_denials.len() > MAX_ACCESS_DENIALS {
+            let excess = self.access_denials.len() - MAX_ACCESS_DENIALS;
+            self.access_denials.drain(0..excess);
+        }
+    }
+
+    /// Most recent access denials, newest first
+    pub fn get_access_denials(&self, limit: Option<u64>) -> Vec<AccessDenial> {
+        let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE) as usize;
+        self.access_denials.iter().rev().take(limit).cloned().collect()
+    }
+
     // =========================================================================
     // Contact Operations
     // =========================================================================
 
     /// Create a new contact
+    /// Emails are unique by `email_index_key`: the index holds one contact per key,
+    /// so a second contact would leave the first unreachable by email
     /// @see AC-5.6.10.1 - Sets owner_id to caller for row-level security
-    pub fn create_contact(&mut self, request: CreateContactRequest, caller: Principal) -> Contact {
+    pub fn create_contact(&mut self, request: CreateContactRequest, caller: Principal) -> Result<Contact, String> {
+        if self.is_email_blocked(&request.email) {
+            return Err("Email is blocklisted".to_string());
+        }
+
+        let email_key = self.email_index_key(&request.email);
+        if self.contacts_by_email.contains_key(&email_key) {
+            return Err("Contact with this email already exists".to_string());
+        }
+
+        // `contacts_by_user` holds one contact per user; overwriting it would orphan the first
+        if request.user_id.as_ref().is_some_and(|u| self.contacts_by_user.contains_key(u)) {
+            return Err("Contact with this user_id already exists".to_string());
+        }
+
+        let owner = self.resolve_owner(request.owner_id, caller)?;
         let now = ic_cdk::api::time();
+        let created_at = resolve_created_at(request.created_at_override, now)?;
         let id = self.next_contact_id;
         self.next_contact_id += 1;
 
         let contact = Contact {
             id,
             user_id: request.user_id.clone(),
-            email: request.email.clone(),
+            email: request.email,
             name: request.name,
             company: request.company,
             job_title: request.job_title,
@@ -315,19 +833,212 @@ impl State {
             source: request.source.unwrap_or_default(),
             notes: request.notes,
             status: ContactStatus::Active,
-            owner_id: Some(caller),
+            owner_id: Some(owner),
             team_id: None,
-            created_at: now,
+            marketing_consent: request.marketing_consent,
+            consent_updated_at: request.marketing_consent.map(|_| now),
+            last_activity_at: None,
+            tags: Vec::new(),
+            status_manually_set: false,
+            created_at,
             updated_at: now,
         };
 
         self.contacts.insert(id, contact.clone());
-        self.contacts_by_email.insert(request.email.to_lowercase(), id);
+        self.contacts_by_email.insert(email_key, id);
         if let Some(ref user_id) = request.user_id {
             self.contacts_by_user.insert(user_id.clone(), id);
         }
 
-        contact
+        Ok(contact)
+    }
+
+    /// Contacts like `get_contacts`, each paired with the fields that matched the search
+    /// `matched_fields` is only filled when `with_match_info` is set and a search is given
+    pub fn get_contact_matches(
+        &self,
+        filter: Option<ContactFilter>,
+        pagination: PaginationParams,
+        caller: &Principal,
+    ) -> PaginatedResponse<ContactMatch> {
+        let search = filter
+            .as_ref()
+            .filter(|f| f.with_match_info.unwrap_or(false))
+            .and_then(|f| f.search.clone());
+        let page = self.get_contacts(filter, pagination, caller);
+
+        PaginatedResponse {
+            items: page
+                .items
+                .into_iter()
+                .map(|contact| {
+                    let matched_fields = search
+                        .as_deref()
+                        .map(|q| contact_search_matched_fields(&contact, q))
+                        .unwrap_or_default();
+                    ContactMatch { contact, matched_fields }
+                })
+                .collect(),
+            total: page.total,
+            offset: page.offset,
+            limit: page.limit,
+        }
+    }
+
+    /// Contacts created in `from..=to`, counted per `bucket_secs` interval starting at `from`
+    /// Every bucket in the range is returned (empty ones as 0); row-level security matches `get_contacts`
+    pub fn get_contact_growth(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+        bucket_secs: u64,
+        caller: &Principal,
+    ) -> Result<Vec<(Timestamp, u64)>, String> {
+        if bucket_secs == 0 {
+            return Err("bucket_secs must be greater than zero".to_string());
+        }
+        if to < from {
+            return Err("'to' must not be before 'from'".to_string());
+        }
+
+        let bucket_ns = bucket_secs.saturating_mul(NANOSECONDS_PER_SECOND);
+        let bucket_count = (to - from) / bucket_ns + 1;
+        if bucket_count > MAX_GROWTH_BUCKETS {
+            return Err(format!("Too many buckets: at most {} per query", MAX_GROWTH_BUCKETS));
+        }
+
+        let mut counts = vec![0u64; bucket_count as usize];
+        for contact in self.contacts.values() {
+            if contact.created_at < from || contact.created_at > to || !self.can_view_contact(caller, contact) {
+                continue;
+            }
+            counts[((contact.created_at - from) / bucket_ns) as usize] += 1;
+        }
+
+        Ok(counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (from + i as u64 * bucket_ns, count))
+            .collect())
+    }
+
+    /// Contacts (email/name/company) and deals (name/notes) matching `query`, each
+    /// list capped at `limit`, with the same row-level security as the list endpoints
+    pub fn global_search(&self, query: &str, limit: Option<u64>, caller: &Principal) -> SearchResults {
+        let query = query.trim();
+        if query.is_empty() {
+            return SearchResults { contacts: Vec::new(), deals: Vec::new() };
+        }
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize;
+
+        let contacts = self
+            .contacts
+            .values()
+            .filter(|c| self.can_view_contact(caller, c) && contact_matches_search(c, query))
+            .take(limit)
+            .cloned()
+            .collect();
+        let deals = self
+            .deals
+            .values()
+            .filter(|d| self.can_view_deal(caller, d) && deal_matches_search(d, query))
+            .take(limit)
+            .cloned()
+            .collect();
+
+        SearchResults { contacts, deals }
+    }
+
+    /// Active contacts untouched for `inactive_for_secs` that have no open deals,
+    /// oldest first, with the same row-level security as `get_contacts`
+    pub fn get_stale_contacts(
+        &self,
+        inactive_for_secs: u64,
+        pagination: PaginationParams,
+        caller: &Principal,
+    ) -> PaginatedResponse<Contact> {
+        let has_view_all = self.has_permission(caller, &AdminPermission::ViewAllContacts);
+        let has_view_own = self.has_permission(caller, &AdminPermission::ViewOwnContacts);
+
+        if !has_view_all && !has_view_own {
+            return PaginatedResponse::paginate(Vec::new(), &pagination);
+        }
+
+        let now = ic_cdk::api::time();
+        let cutoff = now.saturating_sub(inactive_for_secs.saturating_mul(NANOSECONDS_PER_SECOND));
+
+        let has_open_deal = |contact_id: &ContactId| {
+            self.deals_by_contact.get(contact_id).is_some_and(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.deals.get(id))
+                    .any(|d| !d.stage.is_closed())
+            })
+        };
+
+        let mut contacts: Vec<Contact> = self
+            .contacts
+            .values()
+            .filter(|c| has_view_all || c.owner_id.as_ref() == Some(caller))
+            .filter(|c| c.status == ContactStatus::Active && c.updated_at < cutoff)
+            .filter(|c| !has_open_deal(&c.id))
+            .cloned()
+            .collect();
+        contacts.sort_by_key(|c| (c.updated_at, c.id));
+
+        PaginatedResponse::paginate(contacts, &pagination)
+    }
+
+    /// Active contacts with no deals at all (untapped leads), in id order,
+    /// with the same row-level security as `get_contacts`
+    pub fn get_contacts_without_deals(&self, pagination: PaginationParams, caller: &Principal) -> PaginatedResponse<Contact> {
+        let contacts: Vec<Contact> = self
+            .contacts
+            .values()
+            .filter(|c| c.status == ContactStatus::Active)
+            .filter(|c| self.deals_by_contact.get(&c.id).is_none_or(|ids| ids.is_empty()))
+            .filter(|c| self.can_view_contact(caller, c))
+            .cloned()
+            .collect();
+
+        PaginatedResponse::paginate(contacts, &pagination)
+    }
+
+    /// Enrich the contact matching a signup's email instead of creating a duplicate
+    /// Sets `user_id` if it was missing (and not already linked to another contact) and
+    /// replaces an `Other` source with the signup's; returns None when no contact has that email
+    pub fn enrich_contact_from_signup(&mut self, request: &CreateContactRequest) -> Option<Contact> {
+        let id = *self.contacts_by_email.get(&self.email_index_key(&request.email))?;
+        let contact = self.contacts.get_mut(&id)?;
+
+        let mut changed = false;
+        if contact.user_id.is_none() {
+            if let Some(user_id) = request.user_id.as_ref().filter(|u| !self.contacts_by_user.contains_key(*u)) {
+                contact.user_id = Some(user_id.clone());
+                self.contacts_by_user.insert(user_id.clone(), id);
+                changed = true;
+            }
+        }
+        if contact.source == ContactSource::Other {
+            let source = request.source.clone().unwrap_or_default();
+            if source != ContactSource::Other {
+                contact.source = source;
+                changed = true;
+            }
+        }
+        if changed {
+            contact.updated_at = ic_cdk::api::time();
+        }
+
+        Some(contact.clone())
+    }
+
+    /// Owner for a new record: the requested admin if given, else the caller
+    fn resolve_owner(&self, owner_id: Option<Principal>, caller: Principal) -> Result<Principal, String> {
+        match owner_id {
+            Some(owner) if !self.is_admin(&owner) => Err(format!("Owner {} is not an admin", owner)),
+            Some(owner) => Ok(owner),
+            None => Ok(caller),
+        }
     }
 
     /// Get a contact by ID
@@ -335,13 +1046,129 @@ impl State {
         self.contacts.get(&id)
     }
 
+    /// Row-level security for single-contact reads, matching `get_contacts`
+    /// @see AC-5.6.10.1 - Row-level security filtering
+    pub fn can_view_contact(&self, caller: &Principal, contact: &Contact) -> bool {
+        self.has_permission(caller, &AdminPermission::ViewAllContacts)
+            || (self.has_permission(caller, &AdminPermission::ViewOwnContacts)
+                && contact.owner_id.as_ref() == Some(caller))
+    }
+
+    /// Get a contact by the platform user id it is linked to
+    pub fn get_contact_by_user_id(&self, user_id: &str) -> Option<&Contact> {
+        self.contacts_by_user
+            .get(user_id)
+            .and_then(|id| self.contacts.get(id))
+    }
+
+    /// Get the contacts for `ids` that exist and are visible to the caller, in request order
+    /// Missing, hidden and repeated ids are skipped
+    pub fn get_contacts_by_ids(&self, ids: &[ContactId], caller: &Principal) -> Vec<Contact> {
+        let mut seen = std::collections::BTreeSet::new();
+        ids.iter()
+            .filter(|id| seen.insert(**id))
+            .filter_map(|id| self.contacts.get(id))
+            .filter(|c| self.can_view_contact(caller, c))
+            .cloned()
+            .collect()
+    }
+
     /// Get a contact by email
     pub fn get_contact_by_email(&self, email: &str) -> Option<&Contact> {
         self.contacts_by_email
-            .get(&email.to_lowercase())
+            .get(&self.email_index_key(email))
             .and_then(|id| self.contacts.get(id))
     }
 
+    /// Key used for `contacts_by_email`: lowercased, and with any `+tag`
+    /// stripped from the local part when plus-address normalization is on
+    pub fn email_index_key(&self, email: &str) -> String {
+        let email = email.to_lowercase();
+        if !self.normalize_plus_addressing {
+            return email;
+        }
+
+        match email.split_once('@') {
+            Some((local, domain)) => {
+                let local = local.split('+').next().unwrap_or(local);
+                format!("{}@{}", local, domain)
+            }
+            None => email,
+        }
+    }
+
+    /// Toggle plus-address normalization and re-key the email index to match
+    /// Refused while existing contacts would share an index key, since only one of
+    /// them could stay indexed
+    pub fn set_normalize_plus_addressing(&mut self, enabled: bool) -> Result<(), String> {
+        let previous = std::mem::replace(&mut self.normalize_plus_addressing, enabled);
+        let collisions = self.email_index_collisions();
+        if let Some((key, ids)) = collisions.iter().next() {
+            self.normalize_plus_addressing = previous;
+            return Err(format!(
+                "{} email(s) would be shared by several contacts (e.g. '{}' by contacts {:?}); merge them first",
+                collisions.len(),
+                key,
+                ids
+            ));
+        }
+        self.rebuild_email_index();
+        Ok(())
+    }
+
+    /// Index keys shared by more than one contact, with the contacts sharing them
+    pub fn email_index_collisions(&self) -> BTreeMap<String, Vec<ContactId>> {
+        let mut by_key: BTreeMap<String, Vec<ContactId>> = BTreeMap::new();
+        for (id, c) in &self.contacts {
+            by_key.entry(self.email_index_key(&c.email)).or_default().push(*id);
+        }
+        by_key.retain(|_, ids| ids.len() > 1);
+        by_key
+    }
+
+    /// Whether an email matches the blocklist, either exactly or by domain
+    /// (subdomains of a blocked domain are blocked too)
+    pub fn is_email_blocked(&self, email: &str) -> bool {
+        let email = email.trim().to_lowercase();
+        if self.blocked_emails.contains(&email) {
+            return true;
+        }
+
+        let Some((_, domain)) = email.rsplit_once('@') else {
+            return false;
+        };
+        self.blocked_email_domains.iter().any(|blocked| {
+            domain == blocked || domain.ends_with(&format!(".{}", blocked))
+        })
+    }
+
+    /// Replace the email blocklist; entries are trimmed, lowercased and deduplicated
+    pub fn set_email_blocklist(&mut self, domains: Vec<String>, emails: Vec<String>) {
+        fn normalize(entries: Vec<String>) -> Vec<String> {
+            let mut entries: Vec<String> = entries
+                .into_iter()
+                .map(|e| e.trim().trim_start_matches('@').to_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect();
+            entries.sort();
+            entries.dedup();
+            entries
+        }
+
+        self.blocked_email_domains = normalize(domains);
+        self.blocked_emails = normalize(emails);
+    }
+
+    /// Rebuild `contacts_by_email` from `contacts`
+    /// On a shared key the oldest contact is indexed; `check_invariants` reports the rest
+    fn rebuild_email_index(&mut self) {
+        let mut index: BTreeMap<String, ContactId> = BTreeMap::new();
+        for (id, c) in &self.contacts {
+            index.entry(self.email_index_key(&c.email)).or_insert(*id);
+        }
+        self.contacts_by_email = index;
+    }
+
     /// Update a contact
     /// @see AC-5.6.10.3 - Granular CRUD permissions
     pub fn update_contact(
@@ -373,34 +1200,235 @@ impl State {
         }
         if let Some(s) = status {
             contact.status = s;
+            contact.status_manually_set = true;
         }
 
         contact.updated_at = ic_cdk::api::time();
         Some(contact.clone())
     }
 
-    /// Delete a contact
-    /// @see AC-5.6.10.3 - Granular CRUD permissions
-    pub fn delete_contact(&mut self, id: ContactId) -> Option<Contact> {
-        let contact = self.contacts.remove(&id)?;
+    /// Reset the given optional contact fields to `None`
+    pub fn clear_contact_fields(&mut self, id: ContactId, fields: &[ContactField]) -> Option<Contact> {
+        let contact = self.contacts.get_mut(&id)?;
 
-        // Remove from indexes
-        self.contacts_by_email.remove(&contact.email.to_lowercase());
-        if let Some(ref user_id) = contact.user_id {
-            self.contacts_by_user.remove(user_id);
+        for field in fields {
+            match field {
+                ContactField::Name => contact.name = None,
+                ContactField::Company => contact.company = None,
+                ContactField::JobTitle => contact.job_title = None,
+                ContactField::InterestArea => contact.interest_area = None,
+                ContactField::Notes => contact.notes = None,
+            }
         }
 
-        // Remove associated deals
-        if let Some(deal_ids) = self.deals_by_contact.remove(&id) {
-            for deal_id in deal_ids {
-                self.deals.remove(&deal_id);
+        contact.updated_at = ic_cdk::api::time();
+        Some(contact.clone())
+    }
+
+    /// Replace a contact's tags (already normalized by the caller)
+    pub fn set_contact_tags(&mut self, id: ContactId, tags: Vec<String>) -> Option<Contact> {
+        let contact = self.contacts.get_mut(&id)?;
+        contact.tags = tags;
+        contact.updated_at = ic_cdk::api::time();
+        Some(contact.clone())
+    }
+
+    /// Rename (or merge) a tag across all contacts, returning the number of contacts changed
+    /// Contacts that already carry `new` simply lose `old`
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> u64 {
+        let now = ic_cdk::api::time();
+        let mut affected = 0;
+        for contact in self.contacts.values_mut() {
+            if !contact.tags.iter().any(|t| t == old) {
+                continue;
             }
+            contact.tags.retain(|t| t != old);
+            contact.tags.push(new.to_string());
+            contact.tags.sort();
+            contact.tags.dedup();
+            contact.updated_at = now;
+            affected += 1;
+        }
+        affected
+    }
+
+    /// Mark Active contacts inactive for longer than `auto_churn_after_secs` as Churned
+    /// Runs at most once per `AUTO_CHURN_INTERVAL_NS` and churns at most
+    /// `AUTO_CHURN_BATCH_SIZE` contacts per pass; returns the churned ids
+    pub fn run_auto_churn(&mut self, now: Timestamp) -> Vec<ContactId> {
+        let Some(after_secs) = self.auto_churn_after_secs else {
+            return Vec::new();
+        };
+        if now.saturating_sub(self.last_auto_churn_run) < AUTO_CHURN_INTERVAL_NS {
+            return Vec::new();
         }
+        self.last_auto_churn_run = now;
 
-        Some(contact)
+        let cutoff = now.saturating_sub(after_secs.saturating_mul(NANOSECONDS_PER_SECOND));
+        let mut churned = Vec::new();
+        for contact in self.contacts.values_mut() {
+            if churned.len() >= AUTO_CHURN_BATCH_SIZE {
+                break;
+            }
+            let last_seen = contact.last_activity_at.unwrap_or(contact.updated_at);
+            if contact.status == ContactStatus::Active && last_seen < cutoff {
+                contact.status = ContactStatus::Churned;
+                contact.updated_at = now;
+                churned.push(contact.id);
+            }
+        }
+        churned
     }
 
-    /// Get contacts with filter and row-level security
+    /// Re-derive a contact's status from its deals when `auto_status_from_deals` is on:
+    /// any ClosedWon makes it Active; all deals ClosedLost makes it Churned
+    /// Contacts with a manually set status are left alone. Returns (old, new) on change.
+    pub fn derive_contact_status_from_deals(
+        &mut self,
+        contact_id: ContactId,
+        now: Timestamp,
+    ) -> Option<(ContactStatus, ContactStatus)> {
+        if !self.auto_status_from_deals {
+            return None;
+        }
+        let stages: Vec<&DealStage> = self
+            .deals_by_contact
+            .get(&contact_id)?
+            .iter()
+            .filter_map(|id| self.deals.get(id))
+            .map(|d| &d.stage)
+            .collect();
+        let derived = if stages.contains(&&DealStage::ClosedWon) {
+            ContactStatus::Active
+        } else if !stages.is_empty() && stages.iter().all(|s| **s == DealStage::ClosedLost) {
+            ContactStatus::Churned
+        } else {
+            return None;
+        };
+
+        let contact = self.contacts.get_mut(&contact_id)?;
+        if contact.status_manually_set || contact.status == derived {
+            return None;
+        }
+        let old = std::mem::replace(&mut contact.status, derived.clone());
+        contact.updated_at = now;
+        Some((old, derived))
+    }
+
+    /// Set a contact's marketing consent, stamping `consent_updated_at` when it changes
+    pub fn set_contact_consent(&mut self, id: ContactId, consent: bool) -> Option<Contact> {
+        let contact = self.contacts.get_mut(&id)?;
+
+        if contact.marketing_consent != Some(consent) {
+            let now = ic_cdk::api::time();
+            contact.marketing_consent = Some(consent);
+            contact.consent_updated_at = Some(now);
+            contact.updated_at = now;
+        }
+        Some(contact.clone())
+    }
+
+    /// Contacts that opted in to marketing and are visible to the caller
+    pub fn get_consented_contacts(&self, pagination: PaginationParams, caller: &Principal) -> PaginatedResponse<Contact> {
+        let contacts: Vec<Contact> = self
+            .contacts
+            .values()
+            .filter(|c| c.marketing_consent == Some(true) && self.can_view_contact(caller, c))
+            .cloned()
+            .collect();
+
+        PaginatedResponse::paginate(contacts, &pagination)
+    }
+
+    /// Delete a contact
+    /// @see AC-5.6.10.3 - Granular CRUD permissions
+    pub fn delete_contact(&mut self, id: ContactId) -> Option<Contact> {
+        let contact = self.contacts.remove(&id)?;
+
+        // Remove from indexes
+        let email_key = self.email_index_key(&contact.email);
+        self.contacts_by_email.remove(&email_key);
+        if let Some(ref user_id) = contact.user_id {
+            self.contacts_by_user.remove(user_id);
+        }
+
+        // Remove associated deals
+        if let Some(deal_ids) = self.deals_by_contact.remove(&id) {
+            for deal_id in deal_ids {
+                self.deals.remove(&deal_id);
+                self.deal_close_approvals.remove(&deal_id);
+            }
+        }
+
+        Some(contact)
+    }
+
+    /// Fill in a missing `pagination` or `limit` with the configured `default_page_size`
+    pub fn resolve_pagination(&self, pagination: Option<PaginationParams>) -> PaginationParams {
+        let pagination = pagination.unwrap_or(PaginationParams { offset: None, limit: None });
+        PaginationParams {
+            offset: pagination.offset,
+            limit: Some(pagination.limit.unwrap_or(self.default_page_size).min(MAX_PAGE_SIZE)),
+        }
+    }
+
+    /// Remember a deleted contact for change feeds, keeping at most `MAX_CONTACT_TOMBSTONES`
+    pub fn record_contact_tombstone(&mut self, contact: &Contact, now: Timestamp) {
+        self.contact_tombstones.push(ContactTombstone {
+            id: contact.id,
+            owner_id: contact.owner_id,
+            deleted_at: now,
+        });
+        if self.contact_tombstones.len() > MAX_CONTACT_TOMBSTONES {
+            let excess = self.contact_tombstones.len() - MAX_CONTACT_TOMBSTONES;
+            self.contact_tombstones.drain(..excess);
+        }
+    }
+
+    /// Contacts created, updated or deleted after `since`, oldest change first,
+    /// with the same row-level security as `get_contacts`
+    /// Fails when tombstones after `since` may already have been dropped, since the
+    /// feed could then miss deletions; the client must resync from `get_contacts`
+    pub fn get_contact_changes_since(&self, since: Timestamp, caller: &Principal) -> Result<Vec<ContactChange>, String> {
+        if self.contact_tombstones.len() >= MAX_CONTACT_TOMBSTONES
+            && self.contact_tombstones.first().is_some_and(|t| t.deleted_at > since)
+        {
+            return Err(format!(
+                "Resync required: deletions before {} are no longer retained",
+                self.contact_tombstones[0].deleted_at
+            ));
+        }
+
+        let has_view_all = self.has_permission(caller, &AdminPermission::ViewAllContacts);
+        let has_view_own = self.has_permission(caller, &AdminPermission::ViewOwnContacts);
+
+        let mut changes: Vec<(Timestamp, ContactChange)> = self
+            .contacts
+            .values()
+            .filter(|c| c.updated_at > since && self.can_view_contact(caller, c))
+            .map(|c| {
+                let change = if c.created_at > since {
+                    ContactChange::Created(c.clone())
+                } else {
+                    ContactChange::Updated(c.clone())
+                };
+                (c.updated_at, change)
+            })
+            .collect();
+
+        changes.extend(
+            self.contact_tombstones
+                .iter()
+                .filter(|t| t.deleted_at > since)
+                .filter(|t| has_view_all || (has_view_own && t.owner_id.as_ref() == Some(caller)))
+                .map(|t| (t.deleted_at, ContactChange::Deleted { id: t.id, deleted_at: t.deleted_at })),
+        );
+
+        changes.sort_by_key(|(timestamp, _)| *timestamp);
+        Ok(changes.into_iter().map(|(_, change)| change).collect())
+    }
+
+    /// Get contacts with filter and row-level security
     /// @see AC-5.6.10.1 - Row-level security filtering
     pub fn get_contacts(
         &self,
@@ -413,12 +1441,7 @@ impl State {
 
         // If no view permissions, return empty
         if !has_view_all && !has_view_own {
-            return PaginatedResponse {
-                items: Vec::new(),
-                total: 0,
-                offset: pagination.offset.unwrap_or(0),
-                limit: pagination.limit.unwrap_or(50),
-            };
+            return PaginatedResponse::paginate(Vec::new(), &pagination);
         }
 
         let mut contacts: Vec<Contact> = self.contacts.values().cloned().collect();
@@ -435,32 +1458,15 @@ impl State {
             if let Some(ref source) = f.source {
                 contacts.retain(|c| &c.source == source);
             }
+            if let Some(ref owner) = f.owner_id {
+                contacts.retain(|c| c.owner_id.as_ref() == Some(owner));
+            }
             if let Some(ref search) = f.search {
-                let search_lower = search.to_lowercase();
-                contacts.retain(|c| {
-                    c.email.to_lowercase().contains(&search_lower)
-                        || c.name.as_ref().map_or(false, |n| n.to_lowercase().contains(&search_lower))
-                        || c.company.as_ref().map_or(false, |co| co.to_lowercase().contains(&search_lower))
-                });
+                contacts.retain(|c| contact_matches_search(c, search));
             }
         }
 
-        let total = contacts.len() as u64;
-        let offset = pagination.offset.unwrap_or(0);
-        let limit = pagination.limit.unwrap_or(50);
-
-        let items: Vec<Contact> = contacts
-            .into_iter()
-            .skip(offset as usize)
-            .take(limit as usize)
-            .collect();
-
-        PaginatedResponse {
-            items,
-            total,
-            offset,
-            limit,
-        }
+        PaginatedResponse::paginate(contacts, &pagination)
     }
 
     // =========================================================================
@@ -474,7 +1480,9 @@ impl State {
             return Err("Contact not found".to_string());
         }
 
+        let owner = self.resolve_owner(request.owner_id, caller)?;
         let now = ic_cdk::api::time();
+        let created_at = resolve_created_at(request.created_at_override, now)?;
         let id = self.next_deal_id;
         self.next_deal_id += 1;
 
@@ -486,10 +1494,20 @@ impl State {
             stage: DealStage::Lead,
             notes: request.notes,
             expected_close_date: request.expected_close_date,
-            owner_id: Some(caller),
+            owner_id: Some(owner),
             created_by: Some(caller),
-            created_at: now,
+            created_at,
             updated_at: now,
+            closed_at: None,
+            comments: Vec::new(),
+            custom_stage: self.pipeline_stages.first().cloned(),
+            reopen_count: 0,
+            stage_history: vec![StageChange {
+                from: None,
+                to: DealStage::Lead,
+                timestamp: created_at,
+            }],
+            attachments: Vec::new(),
         };
 
         self.deals.insert(id, deal.clone());
@@ -501,19 +1519,152 @@ impl State {
         Ok(deal)
     }
 
+    /// Build the auto-deal request for a signup contact from the configured template
+    /// A template that fails deal validation under the current limits falls back to the
+    /// built-in defaults, with the name fitted to those limits
+    pub fn signup_deal_request(&self, contact_id: ContactId) -> CreateDealRequest {
+        let template = self.signup_deal_template.clone().unwrap_or_default();
+        let request = signup_deal_from_template(&template, contact_id);
+        if crate::validation::validate_create_deal(&request, &self.name_length_limits).is_ok() {
+            return request;
+        }
+
+        // Limits may have changed since the template was set: use the built-in
+        // defaults with the name fitted to the current deal name limits
+        let mut request = signup_deal_from_template(&SignupDealTemplate::default(), contact_id);
+        request.name = clamp_signup_deal_name(contact_id, &self.name_length_limits);
+        request
+    }
+
     /// Get a deal by ID
     pub fn get_deal(&self, id: DealId) -> Option<&Deal> {
         self.deals.get(&id)
     }
 
+    /// Row-level security for single-deal reads, matching `get_deals`
+    /// @see AC-5.6.10.1 - Row-level security filtering
+    pub fn can_view_deal(&self, caller: &Principal, deal: &Deal) -> bool {
+        self.has_permission(caller, &AdminPermission::ViewAllDeals)
+            || (self.has_permission(caller, &AdminPermission::ViewOwnDeals)
+                && deal.owner_id.as_ref() == Some(caller))
+    }
+
+    /// Row-level security for deal writes: EditAllDeals, or EditOwnDeals on an owned deal
+    /// @see AC-5.6.10.3 - Granular CRUD permissions
+    pub fn can_edit_deal(&self, caller: &Principal, deal: &Deal) -> bool {
+        self.has_permission(caller, &AdminPermission::EditAllDeals)
+            || (self.has_permission(caller, &AdminPermission::EditOwnDeals)
+                && deal.owner_id.as_ref() == Some(caller))
+    }
+
     /// Update deal stage
-    pub fn update_deal_stage(&mut self, id: DealId, stage: DealStage) -> Option<Deal> {
+    pub fn update_deal_stage(&mut self, id: DealId, stage: DealStage, custom_stage: Option<String>) -> Option<Deal> {
         let deal = self.deals.get_mut(&id)?;
-        deal.stage = stage;
+        let now = ic_cdk::api::time();
+        deal.set_stage(stage, now);
+        if custom_stage.is_some() {
+            deal.custom_stage = custom_stage;
+        }
+        deal.updated_at = now;
+        let deal = deal.clone();
+        if deal.stage.is_closed() {
+            self.deal_close_approvals.remove(&id);
+        }
+        Some(deal)
+    }
+
+    /// Set a deal's custom pipeline stage (validate with `check_pipeline_stage` first)
+    pub fn set_deal_custom_stage(&mut self, id: DealId, custom_stage: String) -> Option<Deal> {
+        let deal = self.deals.get_mut(&id)?;
+        deal.custom_stage = Some(custom_stage);
         deal.updated_at = ic_cdk::api::time();
         Some(deal.clone())
     }
 
+    /// Check a requested custom stage against the configured pipeline
+    /// Omitting it (None) keeps the deal's current stage and is always allowed
+    pub fn check_pipeline_stage(&self, custom_stage: Option<&str>) -> Result<(), String> {
+        match custom_stage {
+            None => Ok(()),
+            Some(_) if self.pipeline_stages.is_empty() => {
+                Err("No custom pipeline is configured".to_string())
+            }
+            Some(stage) if self.pipeline_stages.iter().any(|s| s == stage) => Ok(()),
+            Some(stage) => Err(format!(
+                "Unknown pipeline stage '{}'. Valid stages: {}",
+                stage,
+                self.pipeline_stages.join(", ")
+            )),
+        }
+    }
+
+    /// Replace the configured pipeline, clearing `custom_stage` on deals whose stage
+    /// is no longer in it. Returns the ids of the deals that were cleared.
+    pub fn set_pipeline_stages(&mut self, stages: Vec<String>, now: Timestamp) -> Vec<DealId> {
+        let mut cleared = Vec::new();
+        for deal in self.deals.values_mut() {
+            let stale = deal.custom_stage.as_ref().is_some_and(|c| !stages.contains(c));
+            if stale {
+                deal.custom_stage = None;
+                deal.updated_at = now;
+                cleared.push(deal.id);
+            }
+        }
+        self.pipeline_stages = stages;
+        cleared
+    }
+
+    /// Append a comment to a deal
+    pub fn add_deal_comment(&mut self, id: DealId, author: Principal, text: String) -> Option<Deal> {
+        let deal = self.deals.get_mut(&id)?;
+        let now = ic_cdk::api::time();
+
+        deal.comments.push(DealComment {
+            author,
+            text,
+            timestamp: now,
+        });
+        deal.updated_at = now;
+        Some(deal.clone())
+    }
+
+    /// Link an external document to a deal (URL already validated by the caller)
+    /// Rejects a URL already attached and enforces `MAX_DEAL_ATTACHMENTS`
+    pub fn add_deal_attachment(&mut self, id: DealId, name: String, url: String, added_by: Principal) -> Result<Deal, String> {
+        let deal = self.deals.get_mut(&id).ok_or("Deal not found")?;
+        if deal.attachments.iter().any(|a| a.url == url) {
+            return Err("Attachment with this URL already exists".to_string());
+        }
+        if deal.attachments.len() >= crate::validation::MAX_DEAL_ATTACHMENTS {
+            return Err(format!(
+                "A deal can have at most {} attachments",
+                crate::validation::MAX_DEAL_ATTACHMENTS
+            ));
+        }
+
+        let now = ic_cdk::api::time();
+        deal.attachments.push(Attachment {
+            name,
+            url,
+            added_by,
+            added_at: now,
+        });
+        deal.updated_at = now;
+        Ok(deal.clone())
+    }
+
+    /// Unlink the attachment with `url` from a deal
+    pub fn remove_deal_attachment(&mut self, id: DealId, url: &str) -> Result<Deal, String> {
+        let deal = self.deals.get_mut(&id).ok_or("Deal not found")?;
+        let before = deal.attachments.len();
+        deal.attachments.retain(|a| a.url != url);
+        if deal.attachments.len() == before {
+            return Err("Attachment not found".to_string());
+        }
+        deal.updated_at = ic_cdk::api::time();
+        Ok(deal.clone())
+    }
+
     /// Update a deal
     /// @see AC-5.6.10.3 - Granular CRUD permissions
     pub fn update_deal(
@@ -526,6 +1677,8 @@ impl State {
         expected_close_date: Option<Timestamp>,
     ) -> Option<Deal> {
         let deal = self.deals.get_mut(&id)?;
+        let now = ic_cdk::api::time();
+        let value_changed = value.is_some_and(|v| deal.value != Some(v));
 
         if let Some(n) = name {
             deal.name = n;
@@ -534,7 +1687,7 @@ impl State {
             deal.value = Some(v);
         }
         if let Some(s) = stage {
-            deal.stage = s;
+            deal.set_stage(s, now);
         }
         if let Some(n) = notes {
             deal.notes = Some(n);
@@ -543,14 +1696,20 @@ impl State {
             deal.expected_close_date = Some(d);
         }
 
-        deal.updated_at = ic_cdk::api::time();
-        Some(deal.clone())
+        deal.updated_at = now;
+        let deal = deal.clone();
+        // Approvals were given for the old value, so a value change needs fresh sign-off
+        if deal.stage.is_closed() || value_changed {
+            self.deal_close_approvals.remove(&id);
+        }
+        Some(deal)
     }
 
     /// Delete a deal
     /// @see AC-5.6.10.3 - Granular CRUD permissions
     pub fn delete_deal(&mut self, id: DealId) -> Option<Deal> {
         let deal = self.deals.remove(&id)?;
+        self.deal_close_approvals.remove(&id);
 
         // Remove from contact's deal list
         if let Some(deal_ids) = self.deals_by_contact.get_mut(&deal.contact_id) {
@@ -560,6 +1719,52 @@ impl State {
         Some(deal)
     }
 
+    /// Record `approver`'s sign-off for closing a deal as won, returning all approvers so far
+    pub fn approve_deal_close(&mut self, id: DealId, approver: Principal) -> Result<Vec<Principal>, String> {
+        let deal = self.deals.get(&id).ok_or("Deal not found")?;
+        if deal.stage.is_closed() {
+            return Err("Deal is already closed".to_string());
+        }
+        let approvers = self.deal_close_approvals.entry(id).or_default();
+        if !approvers.contains(&approver) {
+            approvers.push(approver);
+        }
+        Ok(approvers.clone())
+    }
+
+    /// Reject closing a deal worth at least `deal_approval_threshold` as won unless an admin
+    /// other than `closer` approved it. `value` is the deal's value after the update;
+    /// approvals only cover the deal's current value, so closing at a new value needs new ones.
+    pub fn check_deal_close_approval(
+        &self,
+        deal: &Deal,
+        stage: &DealStage,
+        value: Option<u64>,
+        closer: &Principal,
+    ) -> Result<(), String> {
+        let Some(threshold) = self.deal_approval_threshold else {
+            return Ok(());
+        };
+        if *stage != DealStage::ClosedWon || deal.stage == DealStage::ClosedWon || value.unwrap_or(0) < threshold {
+            return Ok(());
+        }
+        let approved = value == deal.value
+            && self
+                .deal_close_approvals
+                .get(&deal.id)
+                .is_some_and(|approvers| approvers.iter().any(|p| p != closer));
+        if approved {
+            Ok(())
+        } else {
+            Err("Deal value meets the approval threshold: closing as won needs approve_deal_close from a second admin".to_string())
+        }
+    }
+
+    /// Whether any deal references the contact
+    pub fn has_deal_for_contact(&self, contact_id: ContactId) -> bool {
+        self.deals_by_contact.get(&contact_id).is_some_and(|ids| !ids.is_empty())
+    }
+
     /// Get deals with filter and row-level security
     /// @see AC-5.6.10.1 - Row-level security filtering
     pub fn get_deals(
@@ -573,12 +1778,7 @@ impl State {
 
         // If no view permissions, return empty
         if !has_view_all && !has_view_own {
-            return PaginatedResponse {
-                items: Vec::new(),
-                total: 0,
-                offset: pagination.offset.unwrap_or(0),
-                limit: pagination.limit.unwrap_or(50),
-            };
+            return PaginatedResponse::paginate(Vec::new(), &pagination);
         }
 
         let mut deals: Vec<Deal> = self.deals.values().cloned().collect();
@@ -592,26 +1792,165 @@ impl State {
             if let Some(ref stage) = f.stage {
                 deals.retain(|d| &d.stage == stage);
             }
+            if let Some(ref stages) = f.stages {
+                deals.retain(|d| stages.contains(&d.stage));
+            }
             if let Some(contact_id) = f.contact_id {
                 deals.retain(|d| d.contact_id == contact_id);
             }
+            if let Some(ref owner) = f.owner_id {
+                deals.retain(|d| d.owner_id.as_ref() == Some(owner));
+            }
+            if let Some(min) = f.min_value {
+                deals.retain(|d| d.value.unwrap_or(0) >= min);
+            }
+            if let Some(max) = f.max_value {
+                deals.retain(|d| d.value.unwrap_or(0) <= max);
+            }
         }
 
-        let total = deals.len() as u64;
-        let offset = pagination.offset.unwrap_or(0);
-        let limit = pagination.limit.unwrap_or(50);
+        PaginatedResponse::paginate(deals, &pagination)
+    }
 
-        let items: Vec<Deal> = deals
-            .into_iter()
-            .skip(offset as usize)
-            .take(limit as usize)
+    /// Deals reopened more than `min_reopens` times, most reopened first
+    pub fn get_frequently_reopened_deals(&self, min_reopens: u32) -> Vec<Deal> {
+        let mut deals: Vec<Deal> = self
+            .deals
+            .values()
+            .filter(|d| d.reopen_count > min_reopens)
+            .cloned()
             .collect();
+        deals.sort_by_key(|d| std::cmp::Reverse(d.reopen_count));
+        deals
+    }
 
-        PaginatedResponse {
-            items,
-            total,
-            offset,
-            limit,
+    /// Average time (ns) closed deals spent in each stage before leaving it, in pipeline order
+    /// Dwell time is the gap between consecutive `stage_history` entries; stages no visible
+    /// closed deal ever left are omitted
+    pub fn get_stage_velocity(&self, caller: &Principal) -> Vec<(DealStage, u64)> {
+        // u128 so summed dwell times across many deals cannot overflow
+        let mut totals = [(0u128, 0u128); DealStage::ALL.len()];
+        for deal in self.deals.values() {
+            if !deal.stage.is_closed() || !self.can_view_deal(caller, deal) {
+                continue;
+            }
+            for pair in deal.stage_history.windows(2) {
+                let Some(idx) = DealStage::ALL.iter().position(|s| *s == pair[0].to) else {
+                    continue;
+                };
+                totals[idx].0 += pair[1].timestamp.saturating_sub(pair[0].timestamp) as u128;
+                totals[idx].1 += 1;
+            }
+        }
+
+        DealStage::ALL
+            .iter()
+            .zip(totals)
+            .filter(|(_, (_, count))| *count > 0)
+            .map(|(stage, (total, count))| (stage.clone(), (total / count) as u64))
+            .collect()
+    }
+
+    /// Open and won deal totals per owner, in a single pass over all deals
+    /// Unowned deals are grouped under `Principal::anonymous()`; lost deals are not counted
+    pub fn get_deals_by_owner(&self) -> Vec<OwnerDealSummary> {
+        let mut by_owner: BTreeMap<Principal, OwnerDealSummary> = BTreeMap::new();
+        for deal in self.deals.values() {
+            let owner = deal.owner_id.unwrap_or_else(Principal::anonymous);
+            let summary = by_owner.entry(owner).or_insert_with(|| OwnerDealSummary {
+                owner,
+                open_count: 0,
+                won_count: 0,
+                open_value: 0,
+                won_value: 0,
+            });
+            let value = deal.value.unwrap_or(0);
+            match deal.stage {
+                DealStage::ClosedWon => {
+                    summary.won_count += 1;
+                    summary.won_value = summary.won_value.saturating_add(value);
+                }
+                DealStage::ClosedLost => {}
+                _ => {
+                    summary.open_count += 1;
+                    summary.open_value = summary.open_value.saturating_add(value);
+                }
+            }
+        }
+        by_owner.into_values().collect()
+    }
+
+    /// Contact and deal counts for the caller's dashboard
+    /// Counts the caller's own records, or every record with the matching View All permission
+    pub fn get_my_stats(&self, caller: &Principal) -> MyStats {
+        let all_contacts = self.has_permission(caller, &AdminPermission::ViewAllContacts);
+        let all_deals = self.has_permission(caller, &AdminPermission::ViewAllDeals);
+
+        let contacts = self
+            .contacts
+            .values()
+            .filter(|c| all_contacts || c.owner_id.as_ref() == Some(caller))
+            .count() as u64;
+
+        let mut stats = MyStats {
+            contacts,
+            deals: 0,
+            open_deals: 0,
+            closed_deals: 0,
+            open_pipeline_value: 0,
+        };
+        for deal in self.deals.values().filter(|d| all_deals || d.owner_id.as_ref() == Some(caller)) {
+            stats.deals += 1;
+            if deal.stage.is_closed() {
+                stats.closed_deals += 1;
+            } else {
+                stats.open_deals += 1;
+                stats.open_pipeline_value = stats.open_pipeline_value.saturating_add(deal.value.unwrap_or(0));
+            }
+        }
+        stats
+    }
+
+    /// Win rate of closed deals in the window, overall and per owner
+    /// Closed deals are dated by `closed_at`; row-level security matches `get_deals`
+    pub fn get_win_rate(
+        &self,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        caller: &Principal,
+    ) -> WinRateReport {
+        let has_view_all = self.has_permission(caller, &AdminPermission::ViewAllDeals);
+        let has_view_own = self.has_permission(caller, &AdminPermission::ViewOwnDeals);
+
+        let mut tallies: BTreeMap<Option<Principal>, (u64, u64)> = BTreeMap::new();
+        let closed = self.deals.values().filter(|d| {
+            (has_view_all || (has_view_own && d.owner_id.as_ref() == Some(caller)))
+                && d.close_time().is_some_and(|closed_at| {
+                    from.is_none_or(|f| closed_at >= f) && to.is_none_or(|t| closed_at <= t)
+                })
+        });
+        for deal in closed {
+            let tally = tallies.entry(deal.owner_id).or_default();
+            match deal.stage {
+                DealStage::ClosedWon => tally.0 += 1,
+                DealStage::ClosedLost => tally.1 += 1,
+                _ => {}
+            }
+        }
+        tallies.retain(|_, (won, lost)| *won + *lost > 0);
+
+        let (won, lost) = tallies
+            .values()
+            .fold((0, 0), |(w, l), (won, lost)| (w + won, l + lost));
+
+        WinRateReport {
+            overall: WinRate::new(won, lost),
+            by_owner: tallies
+                .into_iter()
+                .map(|(owner, (won, lost))| (owner, WinRate::new(won, lost)))
+                .collect(),
+            period_start: from,
+            period_end: to,
         }
     }
 
@@ -620,7 +1959,18 @@ impl State {
     // =========================================================================
 
     /// Create a new transaction
-    pub fn create_transaction(&mut self, request: CreateTransactionRequest) -> Transaction {
+    /// A reversing transaction must reference an existing transaction of the opposite type
+    pub fn create_transaction(&mut self, request: CreateTransactionRequest) -> Result<Transaction, String> {
+        if let Some(original_id) = request.reverses {
+            let original = self
+                .transactions
+                .get(&original_id)
+                .ok_or_else(|| format!("Reversed transaction {} not found", original_id))?;
+            if original.transaction_type == request.transaction_type {
+                return Err("A reversal must be of the opposite type to the original transaction".to_string());
+            }
+        }
+
         let now = ic_cdk::api::time();
         let id = self.next_transaction_id;
         self.next_transaction_id += 1;
@@ -635,10 +1985,12 @@ impl State {
             reference: request.reference,
             date: request.date.unwrap_or(now),
             created_at: now,
+            reverses: request.reverses,
+            receipt_hash: request.receipt_hash.map(|h| h.to_lowercase()),
         };
 
         self.transactions.insert(id, transaction.clone());
-        transaction
+        Ok(transaction)
     }
 
     /// Get transactions with filter
@@ -662,53 +2014,118 @@ impl State {
             if let Some(to) = f.to_date {
                 transactions.retain(|t| t.date <= to);
             }
+            if let Some(ref prefix) = f.reference_prefix {
+                transactions.retain(|t| t.reference.as_ref().is_some_and(|r| r.starts_with(prefix.as_str())));
+            }
+            // Sort before pagination so pages form a continuous statement
+            if f.sort_by_date.unwrap_or(false) {
+                transactions.sort_by_key(|t| (t.date, t.id));
+                if f.descending.unwrap_or(false) {
+                    transactions.reverse();
+                }
+            }
         }
 
-        let total = transactions.len() as u64;
-        let offset = pagination.offset.unwrap_or(0);
-        let limit = pagination.limit.unwrap_or(50);
+        PaginatedResponse::paginate(transactions, &pagination)
+    }
 
-        let items: Vec<Transaction> = transactions
-            .into_iter()
-            .skip(offset as usize)
-            .take(limit as usize)
-            .collect();
+    /// Net cash position (income minus expenses, in cents) over transactions dated on or before `as_of`
+    /// Reversals carry the opposite type, so they net out naturally; clamped to the i64 range
+    pub fn get_cash_position_as_of(&self, as_of: Timestamp) -> i64 {
+        let net: i128 = self
+            .transactions
+            .values()
+            .filter(|t| t.date <= as_of)
+            .map(|t| match t.transaction_type {
+                TransactionType::Income => t.amount as i128,
+                TransactionType::Expense => -(t.amount as i128),
+            })
+            .sum();
+        net.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
 
-        PaginatedResponse {
-            items,
-            total,
-            offset,
-            limit,
+    /// Summary of `[from, to]` compared with the equal-length window ending just before `from`
+    pub fn get_financial_comparison(&self, from: Timestamp, to: Timestamp) -> Result<FinancialComparison, String> {
+        if from > to {
+            return Err("Invalid period: from must not be after to".to_string());
         }
+        let length = to - from;
+        let previous_to = from.checked_sub(1).ok_or("No prior period before timestamp 0")?;
+        let previous_from = previous_to.saturating_sub(length);
+
+        let current = self.get_financial_summary(from, to, false);
+        let previous = self.get_financial_summary(previous_from, previous_to, false);
+
+        Ok(FinancialComparison {
+            income_change_pct: percent_change(previous.total_income as f64, current.total_income as f64),
+            expenses_change_pct: percent_change(previous.total_expenses as f64, current.total_expenses as f64),
+            net_change_pct: percent_change(previous.net as f64, current.net as f64),
+            current,
+            previous,
+        })
     }
 
     /// Get financial summary
-    pub fn get_financial_summary(&self, from: Timestamp, to: Timestamp) -> FinancialSummary {
-        let mut total_income: u64 = 0;
-        let mut total_expenses: u64 = 0;
-        let mut subscription_income: u64 = 0;
-
-        for t in self.transactions.values() {
-            if t.date >= from && t.date <= to {
-                match t.transaction_type {
-                    TransactionType::Income => {
-                        total_income += t.amount;
-                        if t.category == TransactionCategory::Subscription {
-                            subscription_income += t.amount;
-                        }
-                    }
-                    TransactionType::Expense => {
-                        total_expenses += t.amount;
+    /// With `net_of_reversals`, reversals in the period are netted against their
+    /// original transaction instead of being booked on the opposite side
+    pub fn get_financial_summary(&self, from: Timestamp, to: Timestamp, net_of_reversals: bool) -> FinancialSummary {
+        // Accumulate in u128 so large totals cannot overflow; clamp on output
+        let mut total_income: u128 = 0;
+        let mut total_expenses: u128 = 0;
+        let mut subscription_income: u128 = 0;
+        let mut income_count: u64 = 0;
+        let mut expense_count: u64 = 0;
+
+        let in_period = |t: &&Transaction| t.date >= from && t.date <= to;
+
+        let mut reversed_amounts: BTreeMap<TransactionId, u64> = BTreeMap::new();
+        if net_of_reversals {
+            for t in self.transactions.values().filter(in_period) {
+                if let Some(original_id) = t.reverses {
+                    let reversed = reversed_amounts.entry(original_id).or_insert(0);
+                    *reversed = reversed.saturating_add(t.amount);
+                }
+            }
+        }
+
+        for t in self.transactions.values().filter(in_period) {
+            if net_of_reversals && t.reverses.is_some() {
+                continue;
+            }
+            let amount = t.amount.saturating_sub(reversed_amounts.get(&t.id).copied().unwrap_or(0)) as u128;
+            match t.transaction_type {
+                TransactionType::Income => {
+                    total_income += amount;
+                    income_count += 1;
+                    if t.category == TransactionCategory::Subscription {
+                        subscription_income += amount;
                     }
                 }
+                TransactionType::Expense => {
+                    total_expenses += amount;
+                    expense_count += 1;
+                }
             }
         }
 
+        // Zero transactions in the window reports an average of 0
+        let avg_transaction_amount = (total_income + total_expenses)
+            .checked_div((income_count + expense_count) as u128)
+            .unwrap_or(0) as u64;
+
+        let net = total_income as i128 - total_expenses as i128;
+        let clamped_net = net.clamp(i64::MIN as i128, i64::MAX as i128);
+        let to_u64 = |v: u128| u64::try_from(v).unwrap_or(u64::MAX);
+
         FinancialSummary {
-            total_income,
-            total_expenses,
-            net: (total_income as i64) - (total_expenses as i64),
-            mrr: subscription_income / 12,
+            total_income: to_u64(total_income),
+            total_expenses: to_u64(total_expenses),
+            net: clamped_net as i64,
+            net_truncated: clamped_net != net,
+            mrr: to_u64(subscription_income / 12),
+            income_count,
+            expense_count,
+            avg_transaction_amount,
             period_start: from,
             period_end: to,
         }
@@ -719,26 +2136,79 @@ impl State {
     // =========================================================================
 
     /// Set a feature flag
+    /// `audit_evaluations` is kept from the stored flag unless the request sets it
     pub fn set_feature_flag(&mut self, request: SetFeatureFlagRequest) {
         let now = ic_cdk::api::time();
+        let audit_evaluations = request.audit_evaluations.unwrap_or_else(|| {
+            self.feature_flags.get(&request.key).is_some_and(|f| f.audit_evaluations)
+        });
 
         let flag = FeatureFlag {
             key: request.key.clone(),
             enabled: request.enabled,
             description: request.description,
             percentage: request.percentage,
+            permille: request.permille,
             allowed_principals: request.allowed_principals.unwrap_or_default(),
+            excluded_principals: request.excluded_principals.unwrap_or_default(),
             updated_at: now,
+            audit_evaluations,
         };
 
         self.feature_flags.insert(request.key, flag);
     }
 
+    /// Enforce `expected_updated_at`: the stored flag must exist and be unchanged since it was read
+    pub fn check_flag_unchanged(&self, request: &SetFeatureFlagRequest) -> Result<(), String> {
+        let Some(expected) = request.expected_updated_at else {
+            return Ok(());
+        };
+        match self.feature_flags.get(&request.key) {
+            Some(flag) if flag.updated_at == expected => Ok(()),
+            Some(flag) => Err(format!(
+                "Conflict: feature flag '{}' was modified at {} (expected {})",
+                request.key, flag.updated_at, expected
+            )),
+            None => Err(format!("Conflict: feature flag '{}' no longer exists", request.key)),
+        }
+    }
+
     /// Get a feature flag
     pub fn get_feature_flag(&self, key: &str) -> Option<&FeatureFlag> {
         self.feature_flags.get(key)
     }
 
+    /// Delete a feature flag
+    pub fn delete_feature_flag(&mut self, key: &str) -> Option<FeatureFlag> {
+        self.feature_flags.remove(key)
+    }
+
+    /// Whether only controllers may modify this flag
+    pub fn is_flag_protected(&self, key: &str) -> bool {
+        self.protected_flag_keys.iter().any(|k| k == key)
+    }
+
+    /// Record a true audited evaluation, dropping the oldest beyond `MAX_FLAG_EVALUATIONS`
+    pub fn record_flag_evaluation(&mut self, evaluation: FlagEvaluation) {
+        self.flag_evaluations.push(evaluation);
+        if self.flag_evaluations.len() > MAX_FLAG_EVALUATIONS {
+            let excess = self.flag_evaluations.len() - MAX_FLAG_EVALUATIONS;
+            self.flag_evaluations.drain(0..excess);
+        }
+    }
+
+    /// Recorded evaluations of one flag, newest first
+    pub fn get_flag_evaluations(&self, key: &str, limit: Option<u64>) -> Vec<FlagEvaluation> {
+        let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE) as usize;
+        self.flag_evaluations
+            .iter()
+            .rev()
+            .filter(|e| e.key == key)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
     /// Check if a feature is enabled for a principal
     pub fn is_feature_enabled(&self, key: &str, principal: &Principal) -> bool {
         match self.feature_flags.get(key) {
@@ -747,15 +2217,22 @@ impl State {
                     return false;
                 }
 
+                // Excluded principals opt out before any rollout logic
+                if flag.excluded_principals.contains(principal) {
+                    return false;
+                }
+
                 // Check allowed principals
                 if !flag.allowed_principals.is_empty() {
                     return flag.allowed_principals.contains(principal);
                 }
 
-                // Check percentage rollout
+                // Check per-mille, then percentage rollout (simple hash-based)
+                let hash = principal.as_slice().iter().fold(0u64, |acc, b| acc.wrapping_add(*b as u64));
+                if let Some(permille) = flag.permille {
+                    return (hash % 1000) < (permille as u64);
+                }
                 if let Some(pct) = flag.percentage {
-                    // Simple hash-based rollout
-                    let hash = principal.as_slice().iter().fold(0u64, |acc, b| acc.wrapping_add(*b as u64));
                     return (hash % 100) < (pct as u64);
                 }
 
@@ -765,11 +2242,48 @@ impl State {
         }
     }
 
+    /// Insert any missing starter flags (disabled), returning the keys created
+    /// Existing flags are never overwritten
+    pub fn seed_default_feature_flags(&mut self) -> Vec<String> {
+        let mut created = Vec::new();
+        for (key, description) in DEFAULT_FEATURE_FLAGS {
+            if self.feature_flags.contains_key(*key) {
+                continue;
+            }
+            self.set_feature_flag(SetFeatureFlagRequest {
+                key: key.to_string(),
+                enabled: false,
+                description: Some(description.to_string()),
+                percentage: None,
+                allowed_principals: None,
+                excluded_principals: None,
+                expected_updated_at: None,
+                audit_evaluations: None,
+                permille: None,
+            });
+            created.push(key.to_string());
+        }
+        created
+    }
+
     /// List all feature flags
     pub fn list_feature_flags(&self) -> Vec<FeatureFlag> {
         self.feature_flags.values().cloned().collect()
     }
 
+    /// Flags not changed for more than `unchanged_for_secs` as of `now`, oldest first
+    pub fn get_stale_feature_flags(&self, unchanged_for_secs: u64, now: Timestamp) -> Vec<FeatureFlag> {
+        let cutoff = now.saturating_sub(unchanged_for_secs.saturating_mul(NANOSECONDS_PER_SECOND));
+        let mut flags: Vec<FeatureFlag> = self
+            .feature_flags
+            .values()
+            .filter(|f| f.updated_at < cutoff)
+            .cloned()
+            .collect();
+        flags.sort_by_key(|f| f.updated_at);
+        flags
+    }
+
     // =========================================================================
     // Analytics Operations
     // =========================================================================
@@ -778,13 +2292,44 @@ impl State {
     // Migration Operations (FOS-5.6.10)
     // =========================================================================
 
+    /// Apply every migration newer than `schema_version`, in order, bumping
+    /// the marker after each so none is re-run on a later upgrade.
+    /// Stops at the first migration that cannot be applied yet.
+    /// Returns the number of migrations applied.
+    pub fn run_migrations(&mut self) -> u32 {
+        let mut applied = 0;
+
+        if self.schema_version < SCHEMA_VERSION_OWNERSHIP {
+            if !self.migrate_ownership() {
+                return applied;
+            }
+            self.schema_version = SCHEMA_VERSION_OWNERSHIP;
+            applied += 1;
+        }
+
+        applied
+    }
+
+    /// Owner for records without one: `migration_default_owner`, else the first admin
+    pub fn migration_owner(&self) -> Option<Principal> {
+        self.migration_default_owner.or_else(|| self.admins.first().cloned())
+    }
+
+    /// Set or clear the owner used by `migrate_ownership` and `assign_unowned_to`
+    pub fn set_migration_default_owner(&mut self, owner: Option<Principal>) -> Result<(), String> {
+        if let Some(owner) = owner {
+            self.resolve_owner(Some(owner), owner)?;
+        }
+        self.migration_default_owner = owner;
+        Ok(())
+    }
+
     /// Migrate existing contacts and deals without owner_id
-    /// Sets owner_id to the first admin if not already set
+    /// Sets owner_id to `migration_owner()` if not already set
+    /// Returns false if there was no admin to assign ownership to
     /// @see AC-5.6.10.1 - Migration for row-level security
-    pub fn migrate_ownership(&mut self) {
-        let first_admin = self.admins.first().cloned();
-
-        if let Some(admin) = first_admin {
+    pub fn migrate_ownership(&mut self) -> bool {
+        if let Some(admin) = self.migration_owner() {
             // Migrate contacts
             for contact in self.contacts.values_mut() {
                 if contact.owner_id.is_none() {
@@ -804,45 +2349,280 @@ impl State {
 
             ic_cdk::println!("Migrated ownership for {} contacts and {} deals to admin {}",
                 self.contacts.len(), self.deals.len(), admin);
+            true
         } else {
             ic_cdk::println!("No admins found, skipping ownership migration");
+            false
         }
     }
 
-    // =========================================================================
-    // Analytics Operations
-    // =========================================================================
+    /// Ids of contacts left without an owner (e.g. migrated while there were no admins)
+    pub fn list_unowned_contacts(&self) -> Vec<ContactId> {
+        self.contacts
+            .values()
+            .filter(|c| c.owner_id.is_none())
+            .map(|c| c.id)
+            .collect()
+    }
 
-    /// Log user activity
-    pub fn log_activity(&mut self, user_id: String, action: String, metadata: Option<String>) {
-        let activity = UserActivity {
-            user_id,
-            action,
-            metadata,
-            timestamp: ic_cdk::api::time(),
-        };
+    /// Assign every owner-less contact and deal to `owner` (default `migration_owner()`),
+    /// returning (contacts, deals) fixed
+    pub fn assign_unowned_to(&mut self, owner: Option<Principal>) -> Result<(u64, u64), String> {
+        let owner = owner
+            .or_else(|| self.migration_owner())
+            .ok_or_else(|| "No owner given and no admins to default to".to_string())?;
+        let owner = self.resolve_owner(Some(owner), owner)?;
+
+        let mut contacts = 0;
+        for contact in self.contacts.values_mut().filter(|c| c.owner_id.is_none()) {
+            contact.owner_id = Some(owner);
+            contacts += 1;
+        }
 
-        self.activity_log.push(activity);
+        let mut deals = 0;
+        for deal in self.deals.values_mut().filter(|d| d.owner_id.is_none()) {
+            deal.owner_id = Some(owner);
+            if deal.created_by.is_none() {
+                deal.created_by = Some(owner);
+            }
+            deals += 1;
+        }
 
-        // Keep only last 10000 entries
-        if self.activity_log.len() > 10000 {
+        Ok((contacts, deals))
+    }
+
+    // =========================================================================
+    // Integrity Operations
+    // =========================================================================
+
+    /// Move deals whose contact no longer exists out of `deals` and into
+    /// `orphaned_deals`, returning the ids that were quarantined
+    pub fn quarantine_orphaned_deals(&mut self) -> Vec<DealId> {
+        let orphan_ids: Vec<DealId> = self
+            .deals
+            .values()
+            .filter(|d| !self.contacts.contains_key(&d.contact_id))
+            .map(|d| d.id)
+            .collect();
+
+        for id in &orphan_ids {
+            if let Some(deal) = self.deals.remove(id) {
+                if let Some(deal_ids) = self.deals_by_contact.get_mut(&deal.contact_id) {
+                    deal_ids.retain(|did| did != id);
+                    if deal_ids.is_empty() {
+                        self.deals_by_contact.remove(&deal.contact_id);
+                    }
+                }
+                self.orphaned_deals.push(deal);
+            }
+        }
+
+        orphan_ids
+    }
+
+    /// Check that the contact and deal indexes agree with the primary maps,
+    /// returning one human-readable line per inconsistency (empty when healthy)
+    pub fn check_invariants(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (key, id) in &self.contacts_by_email {
+            match self.contacts.get(id) {
+                None => problems.push(format!("contacts_by_email '{}' points to missing contact {}", key, id)),
+                Some(c) if self.email_index_key(&c.email) != *key => problems.push(format!(
+                    "contacts_by_email '{}' points to contact {} with email '{}'",
+                    key, id, c.email
+                )),
+                Some(_) => {}
+            }
+        }
+        for (user_id, id) in &self.contacts_by_user {
+            match self.contacts.get(id) {
+                None => problems.push(format!("contacts_by_user '{}' points to missing contact {}", user_id, id)),
+                Some(c) if c.user_id.as_deref() != Some(user_id.as_str()) => problems.push(format!(
+                    "contacts_by_user '{}' points to contact {} with user_id {:?}",
+                    user_id, id, c.user_id
+                )),
+                Some(_) => {}
+            }
+        }
+        for (contact_id, deal_ids) in &self.deals_by_contact {
+            for deal_id in deal_ids {
+                match self.deals.get(deal_id) {
+                    None => problems.push(format!("deals_by_contact {} lists missing deal {}", contact_id, deal_id)),
+                    Some(d) if d.contact_id != *contact_id => problems.push(format!(
+                        "deals_by_contact {} lists deal {} which belongs to contact {}",
+                        contact_id, deal_id, d.contact_id
+                    )),
+                    Some(_) => {}
+                }
+            }
+        }
+        for (key, ids) in self.email_index_collisions() {
+            problems.push(format!("contacts {:?} share email index key '{}'", ids, key));
+        }
+        for deal in self.deals.values() {
+            if !self.contacts.contains_key(&deal.contact_id) {
+                problems.push(format!("deal {} references missing contact {}", deal.id, deal.contact_id));
+            }
+        }
+
+        problems
+    }
+
+    /// Heartbeat wrapper around `check_invariants`, run at most once per
+    /// `INVARIANT_CHECK_INTERVAL_NS`; returns `None` when the check was skipped
+    pub fn run_periodic_invariant_check(&mut self, now: Timestamp) -> Option<Vec<String>> {
+        if now.saturating_sub(self.last_invariant_check) < INVARIANT_CHECK_INTERVAL_NS {
+            return None;
+        }
+        self.last_invariant_check = now;
+        Some(self.check_invariants())
+    }
+
+    /// Ids of live deals whose `contact_id` no longer exists in `contacts`
+    pub fn find_orphaned_deal_ids(&self) -> Vec<DealId> {
+        self.deals
+            .values()
+            .filter(|d| !self.contacts.contains_key(&d.contact_id))
+            .map(|d| d.id)
+            .collect()
+    }
+
+    /// Delete every live deal whose contact no longer exists, returning the removed deals
+    pub fn delete_orphaned_deals(&mut self) -> Vec<Deal> {
+        let removed: Vec<Deal> = self
+            .find_orphaned_deal_ids()
+            .into_iter()
+            .filter_map(|id| self.delete_deal(id))
+            .collect();
+        self.deals_by_contact.retain(|_, ids| !ids.is_empty());
+        removed
+    }
+
+    // =========================================================================
+    // Analytics Operations
+    // =========================================================================
+
+    /// Log user activity
+    pub fn log_activity(&mut self, user_id: String, action: String, metadata: Option<String>) {
+        let now = ic_cdk::api::time();
+        if let Some(contact) = self.contacts_by_user.get(&user_id).and_then(|id| self.contacts.get_mut(id)) {
+            contact.last_activity_at = Some(now);
+        }
+
+        let activity = UserActivity {
+            user_id,
+            action,
+            metadata,
+            timestamp: now,
+        };
+
+        self.activity_log.push(activity);
+
+        // Keep only last 10000 entries
+        if self.activity_log.len() > 10000 {
             self.activity_log.drain(0..1000);
         }
     }
 
+    /// Log a batch of user activities, returning the number stored
+    pub fn log_activities(&mut self, events: Vec<(String, String, Option<String>)>) -> u32 {
+        let count = events.len() as u32;
+        for (user_id, action, metadata) in events {
+            self.log_activity(user_id, action, metadata);
+        }
+        count
+    }
+
+    /// Distinct users reaching each funnel step, in order
+    /// A step counts when it follows the user's previous step within `window_secs`
+    pub fn get_activity_funnel(&self, steps: &[String], window_secs: u64) -> Vec<(String, u64)> {
+        let window = window_secs.saturating_mul(NANOSECONDS_PER_SECOND);
+
+        let mut events: Vec<&UserActivity> = self.activity_log.iter().collect();
+        events.sort_by_key(|a| a.timestamp);
+
+        // Per user, the latest time each step was reached by a valid chain
+        let mut progress: BTreeMap<&str, Vec<Option<Timestamp>>> = BTreeMap::new();
+        for event in events {
+            let reached = progress
+                .entry(event.user_id.as_str())
+                .or_insert_with(|| vec![None; steps.len()]);
+
+            // Walk steps backwards so one event never advances two steps at once
+            for (i, step) in steps.iter().enumerate().rev() {
+                if *step != event.action {
+                    continue;
+                }
+                let valid = i == 0
+                    || reached[i - 1].is_some_and(|prev| event.timestamp - prev <= window);
+                if valid {
+                    reached[i] = Some(event.timestamp);
+                }
+            }
+        }
+
+        steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let count = progress.values().filter(|reached| reached[i].is_some()).count();
+                (step.clone(), count as u64)
+            })
+            .collect()
+    }
+
     /// Record metrics snapshot
-    pub fn record_metrics(&mut self, snapshot: MetricsSnapshot) {
-        self.metrics_history.push(snapshot);
+    /// Late snapshots are inserted in place so `metrics_history` stays sorted without re-sorting
+    pub fn record_metrics(&mut self, snapshot: MetricsSnapshot, now: Timestamp) {
+        let at = self.metrics_history.partition_point(|m| m.timestamp <= snapshot.timestamp);
+        self.metrics_history.insert(at, snapshot);
+        self.compact_metrics_history(now);
+    }
 
-        // Keep only last 365 entries
-        if self.metrics_history.len() > 365 {
-            self.metrics_history.drain(0..30);
+    /// Downsample snapshots older than the full-resolution window instead of
+    /// dropping them, keeping the latest snapshot of each interval bucket, then
+    /// apply the `MAX_METRICS_HISTORY` hard cap. The window is measured back from `now`
+    /// (canister time), so a caller-supplied future timestamp cannot age out real history.
+    /// Compacts the sorted history in place, touching only the aged-out prefix.
+    pub fn compact_metrics_history(&mut self, now: Timestamp) {
+        let retention = self.metrics_retention.clone().unwrap_or_default();
+        let full_days = retention
+            .full_resolution_days
+            .unwrap_or(DEFAULT_METRICS_FULL_RESOLUTION_DAYS);
+        let interval_days = retention
+            .downsample_interval_days
+            .unwrap_or(DEFAULT_METRICS_DOWNSAMPLE_INTERVAL_DAYS)
+            .max(1);
+
+        let cutoff = now.saturating_sub(full_days as u64 * NANOSECONDS_PER_DAY);
+        let interval = interval_days as u64 * NANOSECONDS_PER_DAY;
+        let aged = self.metrics_history.partition_point(|m| m.timestamp < cutoff);
+
+        // Old snapshots: one per bucket, the later one replacing the earlier
+        let history = &mut self.metrics_history;
+        let mut kept = 0;
+        for i in 0..aged {
+            if kept > 0 && history[kept - 1].timestamp / interval == history[i].timestamp / interval {
+                history.swap(kept - 1, i);
+            } else {
+                history.swap(kept, i);
+                kept += 1;
+            }
+        }
+        if kept < aged {
+            history.drain(kept..aged);
+        }
+
+        if history.len() > MAX_METRICS_HISTORY {
+            let excess = history.len() - MAX_METRICS_HISTORY;
+            history.drain(0..excess);
         }
     }
 
     /// List metrics within a date range
     pub fn list_metrics(&self, from: Timestamp, to: Timestamp, limit: Option<u64>) -> Vec<MetricsSnapshot> {
-        let limit = limit.unwrap_or(100) as usize;
+        let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE) as usize;
 
         let mut filtered: Vec<MetricsSnapshot> = self
             .metrics_history
@@ -865,12 +2645,264 @@ impl State {
     }
 }
 
+/// Fold a string for search: lowercase, strip common diacritics, and drop
+/// whitespace and punctuation so "ACME  Corp." and "acmecorp" compare equal
+fn normalize_search_text(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(fold_diacritic)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Map common accented Latin letters to their unaccented base letter
+fn fold_diacritic(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        _ => c,
+    }
+}
+
+/// Substring match of a search query against a contact's email, name, and company.
+fn contact_matches_search(contact: &Contact, search: &str) -> bool {
+    !contact_search_matched_fields(contact, search).is_empty()
+}
+
+/// Names of the fields ("email", "name", "company") that contain the search query.
+fn contact_search_matched_fields(contact: &Contact, search: &str) -> Vec<String> {
+    let fields = [
+        ("email", Some(&contact.email)),
+        ("name", contact.name.as_ref()),
+        ("company", contact.company.as_ref()),
+    ];
+
+    fields
+        .iter()
+        .filter(|(_, value)| value.is_some_and(|v| text_matches_search(v, search)))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Substring match of a search query against a deal's name and notes.
+fn deal_matches_search(deal: &Deal, search: &str) -> bool {
+    text_matches_search(&deal.name, search)
+        || deal.notes.as_ref().is_some_and(|n| text_matches_search(n, search))
+}
+
+/// Normalized substring match shared by the contact and deal searches.
+/// Falls back to a plain lowercase match when the query is only punctuation.
+fn text_matches_search(value: &str, search: &str) -> bool {
+    let query = normalize_search_text(search);
+    if query.is_empty() {
+        value.to_lowercase().contains(&search.to_lowercase())
+    } else {
+        normalize_search_text(value).contains(&query)
+    }
+}
+
+/// Decode an audit entry's JSON `details` into top-level key/value pairs
+/// String values are returned as-is; other values as JSON text. Details that are
+/// not a JSON object are returned under a single "value" key.
+pub fn parse_audit_log_entry(entry: AuditLogEntry) -> ParsedAuditLogEntry {
+    let details = match entry.details.as_deref().map(serde_json::from_str::<serde_json::Value>) {
+        None => Vec::new(),
+        Some(Ok(serde_json::Value::Object(map))) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let text = match value {
+                    serde_json::Value::String(text) => text,
+                    other => other.to_string(),
+                };
+                (key, text)
+            })
+            .collect(),
+        Some(_) => vec![("value".to_string(), entry.details.clone().unwrap_or_default())],
+    };
+
+    ParsedAuditLogEntry {
+        id: entry.id,
+        timestamp: entry.timestamp,
+        actor: entry.actor,
+        action: entry.action,
+        target_type: entry.target_type,
+        target_id: entry.target_id,
+        details,
+    }
+}
+
+/// Fit the default signup deal name into `limits`: the longest form that keeps the
+/// contact id, else the shortest form cut to the maximum or the full form padded with '.'
+fn clamp_signup_deal_name(contact_id: ContactId, limits: &NameLengthLimits) -> String {
+    let (min, max) = (limits.deal_name_min as usize, limits.deal_name_max as usize);
+    let candidates = [
+        format!("{}Contact #{}", DEFAULT_SIGNUP_DEAL_PREFIX, contact_id),
+        format!("Contact #{}", contact_id),
+        format!("#{}", contact_id),
+    ];
+    if let Some(name) = candidates.iter().find(|n| (min..=max).contains(&n.chars().count())) {
+        return name.clone();
+    }
+
+    let shortest = &candidates[candidates.len() - 1];
+    if shortest.chars().count() > max {
+        return shortest.chars().take(max).collect();
+    }
+    let mut name = candidates[0].clone();
+    while name.chars().count() < min {
+        name.push('.');
+    }
+    name
+}
+
+/// The auto-deal `template` produces for `contact_id`, before runtime name limits are applied
+pub fn signup_deal_from_template(template: &SignupDealTemplate, contact_id: ContactId) -> CreateDealRequest {
+    let prefix = template.name_prefix.as_deref().unwrap_or(DEFAULT_SIGNUP_DEAL_PREFIX);
+    CreateDealRequest {
+        contact_id,
+        name: format!("{}Contact #{}", prefix, contact_id),
+        value: template.default_value,
+        notes: Some(template.notes.clone().unwrap_or_else(|| "Auto-created from user signup".to_string())),
+        expected_close_date: None,
+        created_at_override: None,
+        owner_id: None,
+    }
+}
+
+/// Cut audit details to at most `max_len` characters, ending with
+/// `AUDIT_TRUNCATION_MARKER` when anything was removed
+pub fn truncate_audit_details(details: String, max_len: usize) -> String {
+    if details.chars().count() <= max_len {
+        return details;
+    }
+    let keep = max_len.saturating_sub(AUDIT_TRUNCATION_MARKER.chars().count());
+    let mut truncated: String = details.chars().take(keep).collect();
+    truncated.push_str(AUDIT_TRUNCATION_MARKER);
+    truncated
+}
+
+/// Mask PII in JSON audit details: every `email` value, plus `name` values when
+/// `mask_names` is set (contact entries; deal names are not personal data).
+/// Details that are not JSON are returned unchanged.
+pub fn redact_audit_details(details: &str, mask_names: bool) -> String {
+    fn mask(text: &str) -> String {
+        match text.chars().next() {
+            Some(first) => format!("{}***", first),
+            None => String::new(),
+        }
+    }
+
+    fn redact(value: &mut serde_json::Value, mask_names: bool) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    match (key.as_str(), &*value) {
+                        ("email", serde_json::Value::String(email)) => {
+                            *value = serde_json::Value::String(match email.split_once('@') {
+                                Some((local, domain)) => format!("{}@{}", mask(local), domain),
+                                None => mask(email),
+                            });
+                        }
+                        ("name", serde_json::Value::String(name)) if mask_names => {
+                            *value = serde_json::Value::String(mask(name));
+                        }
+                        _ => redact(value, mask_names),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| redact(item, mask_names)),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<serde_json::Value>(details) {
+        Ok(mut value) => {
+            redact(&mut value, mask_names);
+            value.to_string()
+        }
+        Err(_) => details.to_string(),
+    }
+}
+
+/// Effective creation time: the override if given (never in the future), else now
+fn resolve_created_at(created_at_override: Option<Timestamp>, now: Timestamp) -> Result<Timestamp, String> {
+    match created_at_override {
+        Some(ts) if ts > now => Err("created_at_override cannot be in the future".to_string()),
+        Some(ts) => Ok(ts),
+        None => Ok(now),
+    }
+}
+
 thread_local! {
     pub static STATE: RefCell<State> = RefCell::new(State::new());
 }
 
 /// Current state version for migration support (FOS-5.6.18)
-pub const STATE_VERSION: u32 = 1;
+/// Bump whenever `StableState` gains, drops or changes a field, so an older build
+/// refuses exports it would silently truncate. Header-less exports count as v0.
+/// - 1: state before versioned export headers
+/// - 2: audit export policy, deal close approvals, access denial and flag evaluation buffers
+pub const STATE_VERSION: u32 = 2;
+
+/// Magic prefix on exported state, followed by `STATE_VERSION` as 4 little-endian bytes
+pub const EXPORT_MAGIC: &[u8; 4] = b"DAOX";
+const EXPORT_HEADER_LEN: usize = 8;
+
+/// Turn an imported `StableState` into the state to swap in, applying pending data
+/// migrations as `post_upgrade` does, since v0 exports predate ownership
+/// Returns the state and the number of migrations applied
+pub fn restore_imported_state(stable: StableState) -> (State, u32) {
+    let mut state = State::from(stable);
+    let applied = state.run_migrations();
+    (state, applied)
+}
+
+/// Prefix Candid-encoded export bytes with the magic and current state version
+pub fn add_export_header(payload: Vec<u8>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(EXPORT_HEADER_LEN + payload.len());
+    data.extend_from_slice(EXPORT_MAGIC);
+    data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+    data.extend(payload);
+    data
+}
+
+/// Validate the export header and return the payload after it
+/// Rejects backups from a newer state version before any decoding is attempted,
+/// since an older build would drop or misread the fields it does not know.
+/// Legacy exports made before the header existed are bare Candid (`DIDL`) and read as v0.
+pub fn strip_export_header(data: &[u8]) -> Result<&[u8], String> {
+    if data.starts_with(b"DIDL") {
+        return Ok(data);
+    }
+    let Some(payload) = data.strip_prefix(EXPORT_MAGIC.as_slice()) else {
+        return Err("Invalid backup: missing export header".to_string());
+    };
+    if payload.len() < EXPORT_HEADER_LEN - EXPORT_MAGIC.len() {
+        return Err("Invalid backup: truncated export header".to_string());
+    }
+
+    let (version, payload) = payload.split_at(EXPORT_HEADER_LEN - EXPORT_MAGIC.len());
+    let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+    if version > STATE_VERSION {
+        return Err(format!(
+            "Incompatible backup: export format v{} is newer than this canister's v{}; upgrade the canister before importing",
+            version, STATE_VERSION
+        ));
+    }
+    Ok(payload)
+}
+
+/// Data migration marker, tracked separately from the export format version.
+/// Each migration runs once when restoring from an older schema:
+/// - 1: owner_id/created_by populated on contacts and deals (FOS-5.6.10)
+pub const SCHEMA_VERSION: u32 = 1;
+const SCHEMA_VERSION_OWNERSHIP: u32 = 1;
 
 /// Serializable state for upgrades
 /// FOS-5.6.18: Added version field for future migrations
@@ -883,23 +2915,103 @@ pub struct StableState {
     pub admins: Vec<Principal>,
     #[serde(default)]
     pub authorized_canisters: Vec<(String, Principal)>,
+    #[serde(default)]
+    pub extra_canister_roles: Vec<String>,
+    /// 0 for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub authorized_canister_rate_limit: u32,
+    /// 0 for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub signup_rate_limit: u32,
+    #[serde(default)]
+    pub rate_limit_violations: Vec<(Principal, String, u64)>,
+    /// 0 for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub default_page_size: u64,
     /// Admin permissions (FOS-5.6.10)
     #[serde(default)]
     pub admin_permissions: Vec<(Principal, Vec<AdminPermission>)>,
     pub contacts: Vec<(ContactId, Contact)>,
     pub next_contact_id: ContactId,
+    #[serde(default)]
+    pub contact_tombstones: Vec<ContactTombstone>,
+    #[serde(default)]
+    pub normalize_plus_addressing: bool,
+    #[serde(default)]
+    pub blocked_email_domains: Vec<String>,
+    #[serde(default)]
+    pub blocked_emails: Vec<String>,
+    /// None for states saved before the setting existed (restored as Other)
+    #[serde(default)]
+    pub manual_contact_default_source: Option<ContactSource>,
+    #[serde(default)]
+    pub require_contact_name: bool,
+    /// None for states saved before the setting existed (restored as the defaults)
+    #[serde(default)]
+    pub name_length_limits: Option<NameLengthLimits>,
+    #[serde(default)]
+    pub auto_churn_after_secs: Option<u64>,
+    #[serde(default)]
+    pub auto_status_from_deals: bool,
+    #[serde(default)]
+    pub deal_approval_threshold: Option<u64>,
+    #[serde(default)]
+    pub deal_close_approvals: Vec<(DealId, Vec<Principal>)>,
+    #[serde(default)]
+    pub migration_default_owner: Option<Principal>,
     pub deals: Vec<(DealId, Deal)>,
     pub next_deal_id: DealId,
+    /// Deals quarantined by the restore integrity pass
+    #[serde(default)]
+    pub orphaned_deals: Vec<Deal>,
+    #[serde(default)]
+    pub signup_deal_template: Option<SignupDealTemplate>,
+    #[serde(default)]
+    pub compact_signup_audit: bool,
+    #[serde(default)]
+    pub pipeline_stages: Vec<String>,
     pub transactions: Vec<(TransactionId, Transaction)>,
     pub next_transaction_id: TransactionId,
     pub feature_flags: Vec<(String, FeatureFlag)>,
     #[serde(default)]
+    pub protected_flag_keys: Vec<String>,
+    #[serde(default)]
     pub metrics_history: Vec<MetricsSnapshot>,
+    #[serde(default)]
+    pub metrics_retention: Option<MetricsRetention>,
     /// Audit log (FOS-5.6.10)
     #[serde(default)]
     pub audit_log: Vec<AuditLogEntry>,
     #[serde(default)]
     pub next_audit_log_id: u64,
+    #[serde(default)]
+    pub access_denials: Vec<AccessDenial>,
+    #[serde(default)]
+    pub flag_evaluations: Vec<FlagEvaluation>,
+    /// Last data migration applied; 0 for states saved before the marker existed
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default)]
+    pub maintenance_mode: bool,
+    /// None for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub low_cycles_threshold: Option<u64>,
+    #[serde(default)]
+    pub redact_pii_in_audit: bool,
+    /// 0 for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub max_audit_detail_len: u32,
+    #[serde(default)]
+    pub mirror_crm_to_activity_log: bool,
+    /// 0 for states saved before the setting existed (restored as the default)
+    #[serde(default)]
+    pub audit_log_high_watermark: u64,
+    #[serde(default)]
+    pub audit_export_needed: bool,
+    #[serde(default)]
+    pub audit_exported_through_id: u64,
+    #[serde(default)]
+    pub block_on_export: bool,
 }
 
 impl From<&State> for StableState {
@@ -909,17 +3021,56 @@ impl From<&State> for StableState {
             controllers: state.controllers.clone(),
             admins: state.admins.clone(),
             authorized_canisters: state.authorized_canisters.iter().map(|(k, v)| (k.clone(), *v)).collect(),
+            extra_canister_roles: state.extra_canister_roles.clone(),
+            authorized_canister_rate_limit: state.authorized_canister_rate_limit,
+            signup_rate_limit: state.signup_rate_limit,
+            default_page_size: state.default_page_size,
+            rate_limit_violations: state
+                .rate_limit_violations
+                .iter()
+                .map(|((caller, endpoint), count)| (*caller, endpoint.clone(), *count))
+                .collect(),
             admin_permissions: state.admin_permissions.iter().map(|(k, v)| (*k, v.clone())).collect(),
             contacts: state.contacts.iter().map(|(k, v)| (*k, v.clone())).collect(),
             next_contact_id: state.next_contact_id,
+            contact_tombstones: state.contact_tombstones.clone(),
+            normalize_plus_addressing: state.normalize_plus_addressing,
+            blocked_email_domains: state.blocked_email_domains.clone(),
+            blocked_emails: state.blocked_emails.clone(),
+            manual_contact_default_source: Some(state.manual_contact_default_source.clone()),
+            require_contact_name: state.require_contact_name,
+            name_length_limits: Some(state.name_length_limits.clone()),
+            auto_churn_after_secs: state.auto_churn_after_secs,
+            auto_status_from_deals: state.auto_status_from_deals,
+            deal_approval_threshold: state.deal_approval_threshold,
+            deal_close_approvals: state.deal_close_approvals.iter().map(|(k, v)| (*k, v.clone())).collect(),
+            migration_default_owner: state.migration_default_owner,
             deals: state.deals.iter().map(|(k, v)| (*k, v.clone())).collect(),
             next_deal_id: state.next_deal_id,
+            orphaned_deals: state.orphaned_deals.clone(),
+            signup_deal_template: state.signup_deal_template.clone(),
+            compact_signup_audit: state.compact_signup_audit,
+            pipeline_stages: state.pipeline_stages.clone(),
             transactions: state.transactions.iter().map(|(k, v)| (*k, v.clone())).collect(),
             next_transaction_id: state.next_transaction_id,
             feature_flags: state.feature_flags.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            protected_flag_keys: state.protected_flag_keys.clone(),
             metrics_history: state.metrics_history.clone(),
+            metrics_retention: state.metrics_retention.clone(),
             audit_log: state.audit_log.clone(),
             next_audit_log_id: state.next_audit_log_id,
+            access_denials: state.access_denials.clone(),
+            flag_evaluations: state.flag_evaluations.clone(),
+            schema_version: state.schema_version,
+            maintenance_mode: state.maintenance_mode,
+            low_cycles_threshold: Some(state.low_cycles_threshold),
+            redact_pii_in_audit: state.redact_pii_in_audit,
+            max_audit_detail_len: state.max_audit_detail_len,
+            mirror_crm_to_activity_log: state.mirror_crm_to_activity_log,
+            audit_log_high_watermark: state.audit_log_high_watermark,
+            audit_export_needed: state.audit_export_needed,
+            audit_exported_through_id: state.audit_exported_through_id,
+            block_on_export: state.block_on_export,
         }
     }
 }
@@ -930,23 +3081,84 @@ impl From<StableState> for State {
             controllers: stable.controllers,
             admins: stable.admins,
             authorized_canisters: stable.authorized_canisters.iter().cloned().collect(),
+            extra_canister_roles: stable.extra_canister_roles,
+            authorized_canister_rate_limit: if stable.authorized_canister_rate_limit == 0 {
+                DEFAULT_AUTHORIZED_CANISTER_RATE_LIMIT
+            } else {
+                stable.authorized_canister_rate_limit
+            },
+            signup_rate_limit: if stable.signup_rate_limit == 0 {
+                DEFAULT_SIGNUP_RATE_LIMIT
+            } else {
+                stable.signup_rate_limit
+            },
+            default_page_size: if stable.default_page_size == 0 {
+                DEFAULT_PAGE_SIZE
+            } else {
+                stable.default_page_size.min(MAX_PAGE_SIZE)
+            },
+            rate_limit_violations: stable
+                .rate_limit_violations
+                .into_iter()
+                .map(|(caller, endpoint, count)| ((caller, endpoint), count))
+                .collect(),
             admin_permissions: stable.admin_permissions.iter().cloned().collect(),
             contacts: stable.contacts.iter().cloned().collect(),
             next_contact_id: stable.next_contact_id,
+            contact_tombstones: stable.contact_tombstones,
+            normalize_plus_addressing: stable.normalize_plus_addressing,
+            blocked_email_domains: stable.blocked_email_domains,
+            blocked_emails: stable.blocked_emails,
+            manual_contact_default_source: stable.manual_contact_default_source.unwrap_or(ContactSource::Other),
+            require_contact_name: stable.require_contact_name,
+            name_length_limits: stable.name_length_limits.unwrap_or_default(),
+            auto_churn_after_secs: stable.auto_churn_after_secs,
+            auto_status_from_deals: stable.auto_status_from_deals,
+            deal_approval_threshold: stable.deal_approval_threshold,
+            deal_close_approvals: stable.deal_close_approvals.into_iter().collect(),
+            migration_default_owner: stable.migration_default_owner,
+            last_auto_churn_run: 0,
+            last_invariant_check: 0,
             deals: stable.deals.iter().cloned().collect(),
             next_deal_id: stable.next_deal_id,
+            orphaned_deals: stable.orphaned_deals,
+            signup_deal_template: stable.signup_deal_template,
+            compact_signup_audit: stable.compact_signup_audit,
+            pipeline_stages: stable.pipeline_stages,
             transactions: stable.transactions.iter().cloned().collect(),
             next_transaction_id: stable.next_transaction_id,
             feature_flags: stable.feature_flags.iter().cloned().collect(),
+            protected_flag_keys: stable.protected_flag_keys,
             metrics_history: stable.metrics_history,
+            metrics_retention: stable.metrics_retention,
             audit_log: stable.audit_log,
             next_audit_log_id: if stable.next_audit_log_id == 0 { 1 } else { stable.next_audit_log_id },
+            access_denials: stable.access_denials,
+            flag_evaluations: stable.flag_evaluations,
+            schema_version: stable.schema_version,
+            maintenance_mode: stable.maintenance_mode,
+            low_cycles_threshold: stable.low_cycles_threshold.unwrap_or(DEFAULT_LOW_CYCLES_THRESHOLD),
+            redact_pii_in_audit: stable.redact_pii_in_audit,
+            max_audit_detail_len: if stable.max_audit_detail_len == 0 {
+                DEFAULT_MAX_AUDIT_DETAIL_LEN
+            } else {
+                stable.max_audit_detail_len
+            },
+            mirror_crm_to_activity_log: stable.mirror_crm_to_activity_log,
+            audit_log_high_watermark: if stable.audit_log_high_watermark == 0 {
+                DEFAULT_AUDIT_LOG_HIGH_WATERMARK
+            } else {
+                stable.audit_log_high_watermark
+            },
+            audit_export_needed: stable.audit_export_needed,
+            audit_exported_through_id: stable.audit_exported_through_id,
+            block_on_export: stable.block_on_export,
             ..Default::default()
         };
 
         // Rebuild indexes
+        state.rebuild_email_index();
         for (id, contact) in &state.contacts {
-            state.contacts_by_email.insert(contact.email.to_lowercase(), *id);
             if let Some(ref user_id) = contact.user_id {
                 state.contacts_by_user.insert(user_id.clone(), *id);
             }
@@ -959,6 +3171,984 @@ impl From<StableState> for State {
                 .push(*id);
         }
 
+        // Integrity pass: a deal must reference an existing contact
+        state.quarantine_orphaned_deals();
+
         state
     }
 }
+
+// =============================================================================
+// Unit Tests
+// =============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_stable_state() -> StableState {
+        (&State::new()).into()
+    }
+
+    fn test_contact(id: ContactId) -> Contact {
+        Contact {
+            id,
+            user_id: None,
+            email: format!("contact{}@example.com", id),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: ContactSource::Other,
+            notes: None,
+            status: ContactStatus::Active,
+            owner_id: None,
+            team_id: None,
+            marketing_consent: None,
+            consent_updated_at: None,
+            last_activity_at: None,
+            tags: Vec::new(),
+            status_manually_set: false,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn test_deal(id: DealId, contact_id: ContactId) -> Deal {
+        Deal {
+            id,
+            contact_id,
+            name: format!("Deal {}", id),
+            value: None,
+            stage: DealStage::Lead,
+            notes: None,
+            expected_close_date: None,
+            owner_id: None,
+            created_by: None,
+            created_at: 0,
+            updated_at: 0,
+            closed_at: None,
+            comments: Vec::new(),
+            custom_stage: None,
+            reopen_count: 0,
+            stage_history: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    // -------------------------------------------------------------------------
+    // Restore Integrity Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_restore_quarantines_orphaned_deals() {
+        let mut stable = empty_stable_state();
+        stable.contacts = vec![(1, test_contact(1))];
+        stable.deals = vec![(1, test_deal(1, 1)), (2, test_deal(2, 99))];
+
+        let state = State::from(stable);
+
+        assert!(state.deals.contains_key(&1));
+        assert!(!state.deals.contains_key(&2), "Orphaned deal should be removed from deals");
+        assert_eq!(state.orphaned_deals.len(), 1);
+        assert_eq!(state.orphaned_deals[0].id, 2);
+        assert!(!state.deals_by_contact.contains_key(&99));
+        assert_eq!(state.deals_by_contact.get(&1), Some(&vec![1]));
+    }
+
+    // -------------------------------------------------------------------------
+    // Migration Version Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_migrations_skipped_at_current_schema_version() {
+        let admin = Principal::from_slice(&[1]);
+        let mut stable = empty_stable_state();
+        stable.admins = vec![admin];
+        stable.contacts = vec![(1, test_contact(1))];
+        stable.schema_version = SCHEMA_VERSION;
+
+        let mut state = State::from(stable);
+        let applied = state.run_migrations();
+
+        assert_eq!(applied, 0);
+        assert_eq!(state.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            state.contacts.get(&1).unwrap().owner_id,
+            None,
+            "Ownership migration must not re-run at the current schema version"
+        );
+    }
+
+    // -------------------------------------------------------------------------
+    // Contact Search Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_search_normalizes_whitespace_case_and_punctuation() {
+        let mut contact = test_contact(1);
+        contact.company = Some("Acme Corp".to_string());
+
+        assert!(contact_matches_search(&contact, "acme corp"));
+        assert!(contact_matches_search(&contact, "acmecorp"));
+        assert!(contact_matches_search(&contact, "ACME  CORP"));
+        assert!(contact_matches_search(&contact, "Acme Corp."));
+        assert!(!contact_matches_search(&contact, "globex"));
+    }
+
+    #[test]
+    fn test_search_folds_diacritics() {
+        let mut contact = test_contact(1);
+        contact.name = Some("Renée Müller".to_string());
+
+        assert!(contact_matches_search(&contact, "renee"));
+        assert!(contact_matches_search(&contact, "MULLER"));
+        assert!(contact_matches_search(&contact, "Renée"));
+    }
+
+    #[test]
+    fn test_parse_audit_log_entry_details() {
+        let entry = |details: Option<&str>| AuditLogEntry {
+            id: 1,
+            timestamp: 0,
+            actor: Principal::anonymous(),
+            action: "update_deal_stage".to_string(),
+            target_type: "deal".to_string(),
+            target_id: "1".to_string(),
+            details: details.map(str::to_string),
+        };
+
+        let parsed = parse_audit_log_entry(entry(Some(r#"{"new":{"stage":"Qualified"},"source":"crm"}"#)));
+        assert_eq!(
+            parsed.details,
+            vec![
+                ("new".to_string(), r#"{"stage":"Qualified"}"#.to_string()),
+                ("source".to_string(), "crm".to_string()),
+            ]
+        );
+
+        assert!(parse_audit_log_entry(entry(None)).details.is_empty());
+        assert_eq!(
+            parse_audit_log_entry(entry(Some("not json"))).details,
+            vec![("value".to_string(), "not json".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_search_reports_matched_fields() {
+        let mut contact = test_contact(1);
+        contact.name = Some("Acme Buyer".to_string());
+        contact.company = Some("Acme Corp".to_string());
+
+        assert_eq!(contact_search_matched_fields(&contact, "acme"), vec!["name", "company"]);
+        assert_eq!(contact_search_matched_fields(&contact, "contact1"), vec!["email"]);
+        assert!(contact_search_matched_fields(&contact, "globex").is_empty());
+    }
+
+    #[test]
+    fn test_redact_audit_details_masks_pii() {
+        let details = r#"{"old":{"name":"Jane Doe","company":"Acme"},"email":"jane@example.com"}"#;
+
+        let redacted = redact_audit_details(details, true);
+        assert!(!redacted.contains("jane@example.com"));
+        assert!(!redacted.contains("Jane Doe"));
+        assert!(redacted.contains("j***@example.com"));
+        assert!(redacted.contains("Acme"));
+
+        // Deal names survive; non-JSON details are left alone
+        assert!(redact_audit_details(r#"{"name":"Big deal"}"#, false).contains("Big deal"));
+        assert_eq!(redact_audit_details("not json", true), "not json");
+    }
+
+    #[test]
+    fn test_metrics_history_downsamples_old_snapshots() {
+        let snapshot = |day: u64| MetricsSnapshot {
+            total_users: day,
+            active_users_24h: 0,
+            active_users_7d: 0,
+            active_users_30d: 0,
+            total_captures: 0,
+            total_sprints: 0,
+            total_workspaces: 0,
+            timestamp: day * NANOSECONDS_PER_DAY,
+        };
+
+        let mut state = State::new();
+        for day in 0..200 {
+            state.record_metrics(snapshot(day), day * NANOSECONDS_PER_DAY);
+        }
+        let now = 199 * NANOSECONDS_PER_DAY;
+
+        // A far-future snapshot does not move the full-resolution window
+        let before = state.metrics_history.len();
+        state.record_metrics(snapshot(5_000), now);
+        assert_eq!(state.metrics_history.len(), before + 1);
+        state.metrics_history.pop();
+
+        // Days 109..=199 stay daily; days 0..=108 collapse into 16 weekly buckets
+        let cutoff = 109 * NANOSECONDS_PER_DAY;
+        let recent = state.metrics_history.iter().filter(|m| m.timestamp >= cutoff).count();
+        let old: Vec<u64> = state
+            .metrics_history
+            .iter()
+            .filter(|m| m.timestamp < cutoff)
+            .map(|m| m.total_users)
+            .collect();
+        assert_eq!(recent, 91);
+        assert_eq!(old.len(), 16);
+        assert_eq!(old.first(), Some(&6), "Oldest week is kept, not dropped");
+
+        // Tighter windows apply on the next compaction
+        state.metrics_retention = Some(MetricsRetention {
+            full_resolution_days: Some(30),
+            downsample_interval_days: Some(28),
+        });
+        state.compact_metrics_history(now);
+        assert!(state.metrics_history.len() < 107);
+        assert_eq!(state.metrics_history.last().map(|m| m.total_users), Some(199));
+
+        // Snapshots kept at daily resolution still count towards the hard cap
+        state.metrics_retention = Some(MetricsRetention {
+            full_resolution_days: None,
+            downsample_interval_days: Some(1),
+        });
+        for day in 200..1_000 {
+            state.record_metrics(snapshot(day), day * NANOSECONDS_PER_DAY);
+        }
+        assert_eq!(state.metrics_history.len(), MAX_METRICS_HISTORY);
+        assert!(state.metrics_history.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+    }
+
+    #[test]
+    fn test_financial_comparison_against_prior_window() {
+        let transaction = |id: TransactionId, transaction_type: TransactionType, amount: u64, date: Timestamp| Transaction {
+            id,
+            transaction_type,
+            category: TransactionCategory::Other,
+            amount,
+            currency: "USD".to_string(),
+            description: "Monthly activity".to_string(),
+            reference: None,
+            date,
+            created_at: date,
+            reverses: None,
+            receipt_hash: None,
+        };
+
+        let mut state = State::new();
+        // Previous window [1000, 1999]: income 1000, expenses 400, net 600
+        state.transactions.insert(1, transaction(1, TransactionType::Income, 1_000, 1_000));
+        state.transactions.insert(2, transaction(2, TransactionType::Expense, 400, 1_999));
+        // Current window [2000, 2999]: income 1500, expenses 300, net 1200
+        state.transactions.insert(3, transaction(3, TransactionType::Income, 1_500, 2_000));
+        state.transactions.insert(4, transaction(4, TransactionType::Expense, 300, 2_999));
+        // Outside both windows
+        state.transactions.insert(5, transaction(5, TransactionType::Income, 9_999, 999));
+
+        let comparison = state.get_financial_comparison(2_000, 2_999).unwrap();
+        assert_eq!((comparison.previous.period_start, comparison.previous.period_end), (1_000, 1_999));
+        assert_eq!(comparison.previous.total_income, 1_000);
+        assert_eq!(comparison.current.total_income, 1_500);
+        assert_eq!(comparison.income_change_pct, Some(50.0));
+        assert_eq!(comparison.expenses_change_pct, Some(-25.0));
+        assert_eq!(comparison.net_change_pct, Some(100.0));
+
+        // A window with no prior activity reports no percentage instead of dividing by zero
+        let comparison = state.get_financial_comparison(10_000, 10_999).unwrap();
+        assert_eq!(comparison.income_change_pct, None);
+        assert_eq!(comparison.net_change_pct, None);
+
+        assert!(state.get_financial_comparison(5, 1).is_err());
+    }
+
+    #[test]
+    fn test_cash_position_counts_only_transactions_up_to_cutoff() {
+        let transaction = |id: TransactionId, transaction_type: TransactionType, amount: u64, date: Timestamp| Transaction {
+            id,
+            transaction_type,
+            category: TransactionCategory::Other,
+            amount,
+            currency: "USD".to_string(),
+            description: "Ledger line".to_string(),
+            reference: None,
+            date,
+            created_at: date,
+            reverses: None,
+            receipt_hash: None,
+        };
+
+        let mut state = State::new();
+        state.transactions.insert(1, transaction(1, TransactionType::Income, 10_000, 100));
+        state.transactions.insert(2, transaction(2, TransactionType::Expense, 2_500, 200));
+        state.transactions.insert(3, transaction(3, TransactionType::Expense, 9_000, 300));
+        state.transactions.insert(4, transaction(4, TransactionType::Income, 1_000, 301));
+
+        assert_eq!(state.get_cash_position_as_of(99), 0);
+        assert_eq!(state.get_cash_position_as_of(200), 7_500, "Cutoff is inclusive");
+        assert_eq!(state.get_cash_position_as_of(300), -1_500);
+        assert_eq!(state.get_cash_position_as_of(Timestamp::MAX), -500);
+    }
+
+    #[test]
+    fn test_financial_summary_large_amounts_do_not_overflow() {
+        let transaction = |id: TransactionId, transaction_type: TransactionType, amount: u64| Transaction {
+            id,
+            transaction_type,
+            category: TransactionCategory::Other,
+            amount,
+            currency: "SAT".to_string(),
+            description: "Treasury movement".to_string(),
+            reference: None,
+            date: 100,
+            created_at: 100,
+            reverses: None,
+            receipt_hash: None,
+        };
+
+        let mut state = State::new();
+        state.transactions.insert(1, transaction(1, TransactionType::Income, u64::MAX - 1));
+        state.transactions.insert(2, transaction(2, TransactionType::Income, u64::MAX - 1));
+        state.transactions.insert(3, transaction(3, TransactionType::Expense, 5));
+
+        let summary = state.get_financial_summary(0, 200, false);
+        assert_eq!(summary.total_income, u64::MAX, "Total saturates instead of wrapping");
+        assert_eq!(summary.net, i64::MAX);
+        assert!(summary.net_truncated);
+        assert_eq!(summary.avg_transaction_amount, (((u64::MAX as u128 - 1) * 2 + 5) / 3) as u64);
+
+        // Within range the net is exact and not flagged
+        state.transactions.remove(&2);
+        state.transactions.insert(1, transaction(1, TransactionType::Income, 1_000));
+        let summary = state.get_financial_summary(0, 200, false);
+        assert_eq!(summary.net, 995);
+        assert!(!summary.net_truncated);
+    }
+
+    #[test]
+    fn test_oversized_audit_details_are_truncated() {
+        let state = State::new();
+        let max_len = state.max_audit_detail_len as usize;
+        let details = serde_json::json!({ "old": { "notes": "é".repeat(5000) } }).to_string();
+
+        let stored = truncate_audit_details(details, max_len);
+        assert_eq!(stored.chars().count(), max_len);
+        assert!(stored.ends_with(AUDIT_TRUNCATION_MARKER));
+
+        let short = r#"{"enabled":true}"#.to_string();
+        assert_eq!(truncate_audit_details(short.clone(), max_len), short);
+    }
+
+    #[test]
+    fn test_access_denials_keep_only_the_most_recent() {
+        let mut state = State::new();
+        for i in 0..(MAX_ACCESS_DENIALS as u64 + 10) {
+            state.record_access_denial(AccessDenial {
+                timestamp: i,
+                caller: Principal::anonymous(),
+                action: "add_admin".to_string(),
+                required: "controller".to_string(),
+            });
+        }
+
+        assert_eq!(state.access_denials.len(), MAX_ACCESS_DENIALS);
+        assert_eq!(state.access_denials[0].timestamp, 10, "Oldest denials are dropped first");
+        assert!(state.audit_log.is_empty(), "Denials never touch the audit log");
+        assert_eq!(state.get_access_denials(Some(1))[0].timestamp, MAX_ACCESS_DENIALS as u64 + 9);
+    }
+
+    #[test]
+    fn test_block_on_export_holds_unexported_audit_entries() {
+        let mut state = State::new();
+        for id in 1..=AUDIT_LOG_CAPACITY {
+            state.audit_log.push(AuditLogEntry {
+                id,
+                timestamp: id,
+                actor: Principal::anonymous(),
+                action: "update_contact".to_string(),
+                target_type: "contact".to_string(),
+                target_id: "1".to_string(),
+                details: None,
+            });
+        }
+        state.next_audit_log_id = AUDIT_LOG_CAPACITY + 1;
+        assert_eq!(state.unexported_audit_entries(), AUDIT_LOG_CAPACITY);
+        assert!(state.check_audit_capacity().is_ok(), "Blocking is off by default");
+
+        state.block_on_export = true;
+        let err = state.check_audit_capacity().unwrap_err();
+        assert!(err.contains("acknowledge_audit_export"), "Unexpected error: {}", err);
+
+        assert!(state.acknowledge_audit_export(AUDIT_LOG_CAPACITY + 1).is_err());
+        state.acknowledge_audit_export(AUDIT_LOG_OVERFLOW_DRAIN).unwrap();
+        assert!(state.check_audit_capacity().is_ok(), "Next drain only drops exported entries");
+        assert_eq!(state.unexported_audit_entries(), AUDIT_LOG_CAPACITY - AUDIT_LOG_OVERFLOW_DRAIN);
+        assert!(state.audit_export_needed, "Remainder is still at the watermark");
+
+        state.acknowledge_audit_export(AUDIT_LOG_CAPACITY / 2).unwrap();
+        assert!(!state.audit_export_needed);
+
+        state.acknowledge_audit_export(1).unwrap();
+        assert_eq!(state.audit_exported_through_id, AUDIT_LOG_CAPACITY / 2, "Never moves backwards");
+    }
+
+    #[test]
+    fn test_purge_keeps_unexported_entries_when_blocking_on_export() {
+        let mut state = State::new();
+        for id in 1..=10 {
+            state.audit_log.push(AuditLogEntry {
+                id,
+                timestamp: id,
+                actor: Principal::anonymous(),
+                action: "update_contact".to_string(),
+                target_type: "contact".to_string(),
+                target_id: "1".to_string(),
+                details: None,
+            });
+        }
+        state.next_audit_log_id = 11;
+        state.audit_log_high_watermark = 6;
+        state.audit_export_needed = true;
+        state.block_on_export = true;
+        state.audit_exported_through_id = 4;
+
+        assert_eq!(state.purge_audit_entries_before(9), 4, "Only exported entries are purged");
+        assert_eq!(state.audit_log.first().unwrap().id, 5);
+        assert!(state.audit_export_needed, "Six unexported entries are still at the watermark");
+
+        state.block_on_export = false;
+        assert_eq!(state.purge_audit_entries_before(9), 4);
+        assert_eq!(state.unexported_audit_entries(), 2);
+        assert!(!state.audit_export_needed, "Purging unexported entries recomputes the flag");
+    }
+
+    #[test]
+    fn test_import_of_v0_export_runs_ownership_migration() {
+        let admin = Principal::from_slice(&[1]);
+        let mut stable = empty_stable_state();
+        stable.admins = vec![admin];
+        stable.contacts = vec![(1, test_contact(1))];
+        stable.deals = vec![(1, test_deal(1, 1))];
+        stable.schema_version = 0;
+        let v0_export = candid::encode_args(("dao-admin".to_string(), stable)).unwrap();
+
+        let payload = strip_export_header(&v0_export).unwrap();
+        let (_, imported): (candid::Reserved, StableState) = candid::decode_args(payload).unwrap();
+        let (state, applied) = restore_imported_state(imported);
+
+        assert_eq!(applied, 1);
+        assert_eq!(state.schema_version, SCHEMA_VERSION);
+        assert_eq!(state.contacts.get(&1).unwrap().owner_id, Some(admin));
+        let deal = state.deals.get(&1).unwrap();
+        assert_eq!((deal.owner_id, deal.created_by), (Some(admin), Some(admin)));
+    }
+
+    #[test]
+    fn test_export_header_rejects_newer_version() {
+        let data = add_export_header(vec![1, 2, 3]);
+        assert_eq!(strip_export_header(&data).unwrap(), &[1, 2, 3]);
+
+        let mut bumped = data.clone();
+        bumped[4..8].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        let err = strip_export_header(&bumped).unwrap_err();
+        assert!(err.contains("newer than"), "Unexpected error: {}", err);
+
+        assert_eq!(strip_export_header(b"DIDL\x00").unwrap(), b"DIDL\x00", "Header-less legacy export is v0");
+        assert!(strip_export_header(b"XXXX").is_err());
+        assert!(strip_export_header(b"DAOX\x01").is_err());
+    }
+
+    #[test]
+    fn test_auto_churn_marks_inactive_contacts_and_throttles() {
+        let second = NANOSECONDS_PER_SECOND;
+        let mut state = State::new();
+        state.contacts.insert(1, Contact { updated_at: 10 * second, ..test_contact(1) });
+        state.contacts.insert(2, Contact { last_activity_at: Some(750 * second), ..test_contact(2) });
+        state.contacts.insert(3, Contact { status: ContactStatus::Inactive, ..test_contact(3) });
+
+        assert!(state.run_auto_churn(1_000 * second).is_empty(), "Disabled by default");
+
+        state.auto_churn_after_secs = Some(300);
+        assert_eq!(state.run_auto_churn(1_000 * second), vec![1]);
+        assert_eq!(state.contacts[&1].status, ContactStatus::Churned);
+        assert_eq!(state.contacts[&2].status, ContactStatus::Active, "Recent activity keeps it active");
+        assert_eq!(state.contacts[&3].status, ContactStatus::Inactive, "Only Active contacts churn");
+
+        // Within the throttle interval nothing runs, even once contact 2 goes stale
+        assert!(state.run_auto_churn(1_000 * second + AUTO_CHURN_INTERVAL_NS - 1).is_empty());
+        assert_eq!(state.run_auto_churn(1_000 * second + AUTO_CHURN_INTERVAL_NS), vec![2]);
+    }
+
+    #[test]
+    fn test_get_stage_velocity_averages_dwell_time() {
+        let controller = Principal::from_slice(&[1]);
+        let rep = Principal::from_slice(&[2]);
+        let mut state = State::new();
+        state.admins.push(rep);
+        state.admin_permissions.insert(rep, vec![AdminPermission::ViewOwnDeals]);
+        state.controllers.push(controller);
+
+        // Lead 100 -> Qualified 300 -> ClosedWon 600
+        let mut first = Deal {
+            stage_history: vec![StageChange { from: None, to: DealStage::Lead, timestamp: 100 }],
+            ..test_deal(1, 1)
+        };
+        first.set_stage(DealStage::Qualified, 300);
+        first.set_stage(DealStage::ClosedWon, 600);
+
+        // Lead 0 -> Qualified 400 -> ClosedLost 500, owned by the rep
+        let mut second = Deal {
+            owner_id: Some(rep),
+            stage_history: vec![StageChange { from: None, to: DealStage::Lead, timestamp: 0 }],
+            ..test_deal(2, 1)
+        };
+        second.set_stage(DealStage::Qualified, 400);
+        second.set_stage(DealStage::ClosedLost, 500);
+
+        // Open deals are not counted
+        let mut open = Deal {
+            stage_history: vec![StageChange { from: None, to: DealStage::Lead, timestamp: 0 }],
+            ..test_deal(3, 1)
+        };
+        open.set_stage(DealStage::Proposal, 10_000);
+
+        state.deals.insert(1, first);
+        state.deals.insert(2, second);
+        state.deals.insert(3, open);
+
+        assert_eq!(
+            state.get_stage_velocity(&controller),
+            vec![(DealStage::Lead, 300), (DealStage::Qualified, 200)]
+        );
+        assert_eq!(
+            state.get_stage_velocity(&rep),
+            vec![(DealStage::Lead, 400), (DealStage::Qualified, 100)],
+            "Row-level security limits the rep to their own deals"
+        );
+    }
+
+    #[test]
+    fn test_resolve_pagination_uses_default_page_size() {
+        let mut state = State::new();
+        assert_eq!(state.resolve_pagination(None).limit, Some(DEFAULT_PAGE_SIZE));
+
+        state.default_page_size = 10;
+        assert_eq!(state.resolve_pagination(None).limit, Some(10));
+        let explicit = PaginationParams { offset: Some(5), limit: None };
+        let resolved = state.resolve_pagination(Some(explicit));
+        assert_eq!((resolved.offset, resolved.limit), (Some(5), Some(10)));
+        let explicit = PaginationParams { offset: None, limit: Some(25) };
+        assert_eq!(state.resolve_pagination(Some(explicit)).limit, Some(25));
+
+        let mut stable = empty_stable_state();
+        stable.default_page_size = 0;
+        assert_eq!(State::from(stable).default_page_size, DEFAULT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_signup_deal_request_falls_back_from_overlong_template_name() {
+        let mut state = State::new();
+        state.signup_deal_template = Some(SignupDealTemplate {
+            name_prefix: Some("x".repeat(crate::validation::DEAL_NAME_MAX_LEN)),
+            ..Default::default()
+        });
+
+        let request = state.signup_deal_request(7);
+        assert_eq!(request.name, "New signup: Contact #7");
+        assert!(crate::validation::validate_create_deal(&request, &state.name_length_limits).is_ok());
+
+        state.signup_deal_template = Some(SignupDealTemplate {
+            name_prefix: Some("Inbound: ".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(state.signup_deal_request(7).name, "Inbound: Contact #7", "Valid prefixes are kept");
+
+        // The fallback itself must respect the runtime limits
+        state.name_length_limits.deal_name_max = 12;
+        assert_eq!(state.signup_deal_request(7).name, "Contact #7");
+        state.name_length_limits = NameLengthLimits { deal_name_min: 1, deal_name_max: 5, ..Default::default() };
+        assert_eq!(state.signup_deal_request(123_456).name, "#1234");
+        state.name_length_limits = NameLengthLimits { deal_name_min: 30, deal_name_max: 40, ..Default::default() };
+        let request = state.signup_deal_request(7);
+        assert_eq!(request.name.chars().count(), 30);
+        assert!(crate::validation::validate_create_deal(&request, &state.name_length_limits).is_ok());
+    }
+
+    #[test]
+    fn test_contact_status_derived_from_deal_outcomes() {
+        let mut state = State::new();
+        state.contacts.insert(1, test_contact(1));
+        state.deals.insert(1, Deal { stage: DealStage::ClosedLost, ..test_deal(1, 1) });
+        state.deals_by_contact.insert(1, vec![1]);
+
+        assert_eq!(state.derive_contact_status_from_deals(1, 100), None, "Off by default");
+
+        state.auto_status_from_deals = true;
+        assert_eq!(
+            state.derive_contact_status_from_deals(1, 100),
+            Some((ContactStatus::Active, ContactStatus::Churned))
+        );
+        assert_eq!(state.contacts[&1].status, ContactStatus::Churned);
+
+        // An open deal leaves the status alone; a win brings the contact back
+        state.deals.insert(2, test_deal(2, 1));
+        state.deals_by_contact.insert(1, vec![1, 2]);
+        assert_eq!(state.derive_contact_status_from_deals(1, 200), None);
+        state.deals.get_mut(&2).unwrap().stage = DealStage::ClosedWon;
+        assert_eq!(
+            state.derive_contact_status_from_deals(1, 300),
+            Some((ContactStatus::Churned, ContactStatus::Active))
+        );
+
+        // Manual overrides are respected
+        let contact = state.contacts.get_mut(&1).unwrap();
+        contact.status = ContactStatus::Inactive;
+        contact.status_manually_set = true;
+        state.deals.get_mut(&2).unwrap().stage = DealStage::ClosedLost;
+        assert_eq!(state.derive_contact_status_from_deals(1, 400), None);
+        assert_eq!(state.contacts[&1].status, ContactStatus::Inactive);
+    }
+
+    #[test]
+    fn test_get_my_stats_counts_own_records() {
+        let rep_admin = Principal::from_slice(&[7]);
+        let other = Principal::from_slice(&[8]);
+        let mut state = State::new();
+        state.admins.push(rep_admin);
+        state.admin_permissions.insert(
+            rep_admin,
+            vec![AdminPermission::ViewOwnContacts, AdminPermission::ViewOwnDeals],
+        );
+        state.contacts.insert(1, Contact { owner_id: Some(rep_admin), ..test_contact(1) });
+        state.contacts.insert(2, Contact { owner_id: Some(other), ..test_contact(2) });
+        state.deals.insert(1, Deal { owner_id: Some(rep_admin), value: Some(500), ..test_deal(1, 1) });
+        state.deals.insert(
+            2,
+            Deal { owner_id: Some(rep_admin), value: Some(900), stage: DealStage::ClosedWon, ..test_deal(2, 1) },
+        );
+        state.deals.insert(3, Deal { owner_id: Some(other), value: Some(700), ..test_deal(3, 2) });
+
+        assert_eq!(
+            state.get_my_stats(&rep_admin),
+            MyStats { contacts: 1, deals: 2, open_deals: 1, closed_deals: 1, open_pipeline_value: 500 }
+        );
+
+        state.controllers.push(other);
+        let all = state.get_my_stats(&other);
+        assert_eq!((all.contacts, all.deals, all.open_pipeline_value), (2, 3, 1200));
+    }
+
+    #[test]
+    fn test_rate_limit_violations_stay_bounded() {
+        let mut state = State::new();
+        let runaway = Principal::from_slice(&[1]);
+        state.rate_limit_violations.insert((runaway, "log_activity".to_string()), 500);
+        for i in 0..(MAX_RATE_LIMIT_VIOLATIONS as u32 + 50) {
+            let caller = Principal::from_slice(&i.to_be_bytes());
+            state.rate_limit_violations.insert((caller, "log_activity".to_string()), 1);
+        }
+
+        state.prune_rate_limit_violations();
+
+        assert_eq!(state.rate_limit_violations.len(), MAX_RATE_LIMIT_VIOLATIONS - MAX_RATE_LIMIT_VIOLATIONS / 10);
+        assert_eq!(state.rate_limit_violations.get(&(runaway, "log_activity".to_string())), Some(&500));
+    }
+
+    #[test]
+    fn test_set_pipeline_stages_clears_removed_stages() {
+        let mut state = State::new();
+        state.deals.insert(1, Deal { custom_stage: Some("Pilot".to_string()), ..test_deal(1, 1) });
+        state.deals.insert(2, Deal { custom_stage: Some("Contract".to_string()), ..test_deal(2, 1) });
+        state.deals.insert(3, test_deal(3, 1));
+
+        let cleared = state.set_pipeline_stages(vec!["Discovery".to_string(), "Contract".to_string()], 42);
+
+        assert_eq!(cleared, vec![1]);
+        assert_eq!(state.deals[&1].custom_stage, None);
+        assert_eq!(state.deals[&1].updated_at, 42);
+        assert_eq!(state.deals[&2].custom_stage, Some("Contract".to_string()));
+        assert!(state.check_pipeline_stage(None).is_ok(), "Omitting the stage keeps the current one");
+        assert!(state.check_pipeline_stage(Some("Pilot")).is_err());
+    }
+
+    #[test]
+    fn test_high_value_close_requires_second_approver() {
+        let owner = Principal::from_slice(&[7]);
+        let manager = Principal::from_slice(&[8]);
+        let mut state = State::new();
+        state.deals.insert(1, Deal { value: Some(50_000), ..test_deal(1, 1) });
+        let deal = state.deals[&1].clone();
+        let won = DealStage::ClosedWon;
+
+        assert!(state.check_deal_close_approval(&deal, &won, deal.value, &owner).is_ok(), "Off by default");
+
+        state.deal_approval_threshold = Some(50_000);
+        assert!(state.check_deal_close_approval(&deal, &won, Some(49_999), &owner).is_ok());
+        assert!(state.check_deal_close_approval(&deal, &DealStage::ClosedLost, deal.value, &owner).is_ok());
+        assert!(state.check_deal_close_approval(&deal, &won, deal.value, &owner).is_err());
+
+        state.approve_deal_close(1, owner).unwrap();
+        assert!(state.check_deal_close_approval(&deal, &won, deal.value, &owner).is_err(), "Self-approval does not count");
+
+        assert_eq!(state.approve_deal_close(1, manager).unwrap(), vec![owner, manager]);
+        assert!(state.check_deal_close_approval(&deal, &won, deal.value, &owner).is_ok());
+        assert!(
+            state.check_deal_close_approval(&deal, &won, Some(500_000), &owner).is_err(),
+            "Approval covers only the value it was given for"
+        );
+
+        let restored = State::from(StableState::from(&state));
+        assert_eq!(restored.deal_close_approvals.get(&1), Some(&vec![owner, manager]));
+        assert_eq!(restored.deal_approval_threshold, Some(50_000));
+
+        state.contacts.insert(1, test_contact(1));
+        state.deals_by_contact.insert(1, vec![1]);
+        state.delete_contact(1).unwrap();
+        assert!(state.deal_close_approvals.is_empty(), "Cascade-deleted deals drop their approvals");
+    }
+
+    #[test]
+    fn test_migrate_ownership_prefers_migration_default_owner() {
+        let first_admin = Principal::from_slice(&[7]);
+        let chosen = Principal::from_slice(&[9]);
+        let mut state = State::new();
+        state.admins.push(first_admin);
+        state.admins.push(chosen);
+        state.contacts.insert(1, test_contact(1));
+        state.deals.insert(1, test_deal(1, 1));
+
+        let outsider = Principal::from_slice(&[8]);
+        assert!(state.set_migration_default_owner(Some(outsider)).is_err(), "Owner must be an admin");
+        state.set_migration_default_owner(Some(chosen)).unwrap();
+
+        assert!(state.migrate_ownership());
+        assert_eq!(state.contacts[&1].owner_id, Some(chosen));
+        assert_eq!(state.deals[&1].owner_id, Some(chosen));
+        assert_eq!(state.deals[&1].created_by, Some(chosen));
+
+        state.contacts.insert(2, test_contact(2));
+        assert_eq!(state.assign_unowned_to(None), Ok((1, 0)));
+        assert_eq!(state.contacts[&2].owner_id, Some(chosen));
+
+        let restored = State::from(StableState::from(&state));
+        assert_eq!(restored.migration_default_owner, Some(chosen));
+
+        state.remove_admin(&chosen);
+        assert_eq!(state.migration_default_owner, None);
+        assert_eq!(state.migration_owner(), Some(first_admin), "Falls back to the first remaining admin");
+    }
+
+    #[test]
+    fn test_assign_unowned_to_fixes_owner_less_records() {
+        let admin = Principal::from_slice(&[7]);
+        let mut state = State::new();
+        state.admins.push(admin);
+        state.contacts.insert(1, test_contact(1));
+        state.contacts.insert(2, Contact { owner_id: Some(admin), ..test_contact(2) });
+        state.deals.insert(1, test_deal(1, 1));
+
+        assert_eq!(state.list_unowned_contacts(), vec![1]);
+
+        let outsider = Principal::from_slice(&[8]);
+        assert!(state.assign_unowned_to(Some(outsider)).is_err(), "Owner must be an admin");
+
+        assert_eq!(state.assign_unowned_to(None), Ok((1, 1)), "Defaults to the first admin");
+        assert!(state.list_unowned_contacts().is_empty());
+        assert_eq!(state.deals[&1].owner_id, Some(admin));
+        assert_eq!(state.deals[&1].created_by, Some(admin));
+    }
+
+    // -------------------------------------------------------------------------
+    // Feature Flag Rollout Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_permille_rollout_enrolls_about_half_a_percent() {
+        let mut state = State::new();
+        state.feature_flags.insert(
+            "canary".to_string(),
+            FeatureFlag {
+                key: "canary".to_string(),
+                enabled: true,
+                description: None,
+                percentage: Some(100),
+                allowed_principals: Vec::new(),
+                excluded_principals: Vec::new(),
+                updated_at: 0,
+                permille: Some(5),
+                audit_evaluations: false,
+            },
+        );
+
+        // Deterministic pseudo-random 29-byte (self-authenticating sized) principals
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let total = 20_000;
+        let enrolled = (0..total)
+            .filter(|_| {
+                let bytes: Vec<u8> = (0..29)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+                        (seed >> 56) as u8
+                    })
+                    .collect();
+                state.is_feature_enabled("canary", &Principal::from_slice(&bytes))
+            })
+            .count();
+
+        // 0.5% of 20,000 is 100; `permille` wins over the 100% `percentage`
+        assert!((50..=150).contains(&enrolled), "enrolled {} of {}", enrolled, total);
+    }
+
+    // -------------------------------------------------------------------------
+    // Contact Change Feed Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_get_contact_changes_since_classifies_changes() {
+        let rep = Principal::from_slice(&[2]);
+        let mut state = State::new();
+        state.admins.push(rep);
+        state.admin_permissions.insert(rep, vec![AdminPermission::ViewOwnContacts]);
+
+        state.contacts.insert(1, Contact { owner_id: Some(rep), created_at: 10, updated_at: 10, ..test_contact(1) });
+        state.contacts.insert(2, Contact { owner_id: Some(rep), created_at: 10, updated_at: 150, ..test_contact(2) });
+        state.contacts.insert(3, Contact { owner_id: Some(rep), created_at: 120, updated_at: 120, ..test_contact(3) });
+        state.contacts.insert(4, Contact { created_at: 130, updated_at: 130, ..test_contact(4) });
+        let deleted = Contact { owner_id: Some(rep), ..test_contact(5) };
+        state.record_contact_tombstone(&deleted, 140);
+        state.record_contact_tombstone(&test_contact(6), 50);
+
+        let changes: Vec<(&str, ContactId)> = state
+            .get_contact_changes_since(100, &rep)
+            .unwrap()
+            .iter()
+            .map(|change| match change {
+                ContactChange::Created(c) => ("created", c.id),
+                ContactChange::Updated(c) => ("updated", c.id),
+                ContactChange::Deleted { id, .. } => ("deleted", *id),
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![("created", 3), ("deleted", 5), ("updated", 2)],
+            "Contact 4 belongs to someone else and tombstone 6 predates the baseline"
+        );
+
+        // Once the tombstone list is full, older baselines may have missed deletions
+        for i in 0..MAX_CONTACT_TOMBSTONES as u64 {
+            state.record_contact_tombstone(&test_contact(1_000 + i), 200 + i);
+        }
+        let err = state.get_contact_changes_since(100, &rep).unwrap_err();
+        assert!(err.starts_with("Resync required"), "{}", err);
+        assert!(state.get_contact_changes_since(200, &rep).is_ok());
+    }
+
+    // -------------------------------------------------------------------------
+    // Index Consistency Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_check_invariants_reports_desynced_indexes() {
+        let mut state = State::new();
+        let contact = Contact { user_id: Some("user-1".to_string()), ..test_contact(1) };
+        state.contacts_by_email.insert(state.email_index_key(&contact.email), 1);
+        state.contacts_by_user.insert("user-1".to_string(), 1);
+        state.contacts.insert(1, contact);
+        state.deals.insert(1, test_deal(1, 1));
+        state.deals_by_contact.insert(1, vec![1]);
+        assert!(state.check_invariants().is_empty());
+
+        state.contacts_by_email.insert("ghost@example.com".to_string(), 99);
+        state.deals_by_contact.get_mut(&1).unwrap().push(7);
+
+        let problems = state.check_invariants();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("ghost@example.com") && p.contains("missing contact 99")));
+        assert!(problems.iter().any(|p| p.contains("missing deal 7")));
+    }
+
+    #[test]
+    fn test_has_deal_for_contact_uses_index() {
+        let mut state = State::new();
+        state.contacts.insert(1, test_contact(1));
+        assert!(!state.has_deal_for_contact(1));
+
+        state.deals.insert(1, test_deal(1, 1));
+        state.deals_by_contact.insert(1, vec![1]);
+        assert!(state.has_deal_for_contact(1));
+
+        state.deals_by_contact.insert(1, vec![]);
+        assert!(!state.has_deal_for_contact(1));
+    }
+
+    // -------------------------------------------------------------------------
+    // Email Normalization Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_plus_addressing_refused_when_emails_would_collide() {
+        let mut state = State::new();
+        state.contacts.insert(1, Contact { email: "a+x@ex.com".to_string(), ..test_contact(1) });
+        state.contacts.insert(2, Contact { email: "a@ex.com".to_string(), ..test_contact(2) });
+        state.rebuild_email_index();
+
+        let err = state.set_normalize_plus_addressing(true).unwrap_err();
+        assert!(err.contains("a@ex.com") && err.contains("[1, 2]"), "{}", err);
+        assert!(!state.normalize_plus_addressing);
+        assert_eq!(state.contacts_by_email.len(), 2);
+        assert!(state.check_invariants().is_empty());
+
+        // A restored state can still carry the collision: it is reported, not dropped silently
+        state.normalize_plus_addressing = true;
+        state.rebuild_email_index();
+        assert_eq!(state.contacts_by_email.get("a@ex.com"), Some(&1));
+        let problems = state.check_invariants();
+        assert_eq!(problems, vec!["contacts [1, 2] share email index key 'a@ex.com'".to_string()]);
+    }
+
+    // -------------------------------------------------------------------------
+    // Deal Permission Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_can_edit_deal_requires_ownership_for_edit_own() {
+        let rep = Principal::from_slice(&[2]);
+        let manager = Principal::from_slice(&[3]);
+        let mut state = State::new();
+        state.admin_permissions.insert(rep, vec![AdminPermission::EditOwnDeals]);
+        state.admin_permissions.insert(manager, vec![AdminPermission::EditAllDeals]);
+
+        let owned = Deal { owner_id: Some(rep), ..test_deal(1, 1) };
+        let other = Deal { owner_id: Some(manager), ..test_deal(2, 1) };
+        assert!(state.can_edit_deal(&rep, &owned));
+        assert!(!state.can_edit_deal(&rep, &other));
+        assert!(state.can_edit_deal(&manager, &owned));
+        assert!(!state.can_edit_deal(&Principal::anonymous(), &owned));
+    }
+
+    // -------------------------------------------------------------------------
+    // Orphaned Deal Tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn test_delete_orphaned_deals_removes_deals_without_contact() {
+        let mut state = State::new();
+        state.contacts.insert(1, test_contact(1));
+        state.contacts.insert(2, test_contact(2));
+        state.deals.insert(1, test_deal(1, 1));
+        state.deals.insert(2, test_deal(2, 2));
+        state.deals_by_contact.insert(1, vec![1]);
+        state.deals_by_contact.insert(2, vec![2]);
+        assert!(state.find_orphaned_deal_ids().is_empty());
+
+        // Simulate a contact dropped without cascading (e.g. by a merge or partial import)
+        state.contacts.remove(&2);
+        assert_eq!(state.find_orphaned_deal_ids(), vec![2]);
+
+        let removed = state.delete_orphaned_deals();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, 2);
+        assert!(state.find_orphaned_deal_ids().is_empty());
+        assert!(state.deals.contains_key(&1));
+        assert!(!state.deals_by_contact.contains_key(&2));
+    }
+}
@@ -56,6 +56,21 @@ pub struct Contact {
     /// Team ID for future team-based filtering
     #[serde(default)]
     pub team_id: Option<String>,
+    /// Marketing opt-in; `None` means the contact was never asked
+    #[serde(default)]
+    pub marketing_consent: Option<bool>,
+    /// When `marketing_consent` last changed
+    #[serde(default)]
+    pub consent_updated_at: Option<Timestamp>,
+    /// Last `log_activity` event for the linked platform user
+    #[serde(default)]
+    pub last_activity_at: Option<Timestamp>,
+    /// Normalized (trimmed, lowercase), sorted labels set with `set_contact_tags`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Status was set explicitly via `update_contact`; deal outcomes no longer re-derive it
+    #[serde(default)]
+    pub status_manually_set: bool,
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
 }
@@ -71,6 +86,14 @@ pub struct CreateContactRequest {
     pub interest_area: Option<String>,
     pub source: Option<ContactSource>,
     pub notes: Option<String>,
+    /// Backdated creation time for data migration (honored for controllers only)
+    #[serde(default)]
+    pub created_at_override: Option<Timestamp>,
+    /// Admin to assign the record to instead of the caller (honored for controllers only)
+    #[serde(default)]
+    pub owner_id: Option<Principal>,
+    #[serde(default)]
+    pub marketing_consent: Option<bool>,
 }
 
 /// Request to update a contact
@@ -84,6 +107,35 @@ pub struct UpdateContactRequest {
     pub interest_area: Option<String>,
     pub notes: Option<String>,
     pub status: Option<ContactStatus>,
+    /// Optional fields to reset to `None` (a `None` value above means "unchanged")
+    #[serde(default)]
+    pub clear_fields: Option<Vec<ContactField>>,
+    #[serde(default)]
+    pub marketing_consent: Option<bool>,
+}
+
+impl UpdateContactRequest {
+    /// True if the request would modify at least one field
+    pub fn has_changes(&self) -> bool {
+        self.name.is_some()
+            || self.company.is_some()
+            || self.job_title.is_some()
+            || self.interest_area.is_some()
+            || self.notes.is_some()
+            || self.status.is_some()
+            || self.clear_fields.as_ref().is_some_and(|f| !f.is_empty())
+            || self.marketing_consent.is_some()
+    }
+}
+
+/// Optional contact fields that `UpdateContactRequest::clear_fields` can reset
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ContactField {
+    Name,
+    Company,
+    JobTitle,
+    InterestArea,
+    Notes,
 }
 
 // =============================================================================
@@ -102,6 +154,23 @@ pub enum DealStage {
     ClosedLost,
 }
 
+impl DealStage {
+    /// All stages in pipeline order
+    pub const ALL: [DealStage; 6] = [
+        DealStage::Lead,
+        DealStage::Qualified,
+        DealStage::Proposal,
+        DealStage::Negotiation,
+        DealStage::ClosedWon,
+        DealStage::ClosedLost,
+    ];
+
+    /// Whether the stage ends the deal (won or lost)
+    pub fn is_closed(&self) -> bool {
+        matches!(self, DealStage::ClosedWon | DealStage::ClosedLost)
+    }
+}
+
 /// Deal record
 /// @see AC-5.6.10.1 - Row-level security: deals have owner_id for filtering
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -109,6 +178,7 @@ pub struct Deal {
     pub id: DealId,
     pub contact_id: ContactId,
     pub name: String,
+    /// Deal value in cents (same unit as transaction amounts)
     pub value: Option<u64>,
     pub stage: DealStage,
     pub notes: Option<String>,
@@ -122,6 +192,115 @@ pub struct Deal {
     pub created_by: Option<Principal>,
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
+    /// When the deal entered ClosedWon/ClosedLost; cleared on reopen
+    /// Unlike `updated_at`, later edits do not move it
+    #[serde(default)]
+    pub closed_at: Option<Timestamp>,
+    /// Append-only, timestamped comments (oldest first); `notes` holds the summary
+    #[serde(default)]
+    pub comments: Vec<DealComment>,
+    /// Position in the controller-configured pipeline (`set_pipeline_stages`)
+    /// `stage` remains the built-in lifecycle used for open/closed and win-rate logic
+    #[serde(default)]
+    pub custom_stage: Option<String>,
+    /// Times the deal moved from ClosedWon/ClosedLost back to an open stage
+    #[serde(default)]
+    pub reopen_count: u32,
+    /// Every stage the deal entered, oldest first (empty for deals created before tracking)
+    #[serde(default)]
+    pub stage_history: Vec<StageChange>,
+    /// Off-canister documents (proposals, contracts) linked by `add_deal_attachment`
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// External document reference on a deal; `url` is https:// or ipfs:// and unique per deal
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub url: String,
+    pub added_by: Principal,
+    pub added_at: Timestamp,
+}
+
+/// Most recent stage changes kept per deal; the oldest are dropped beyond this
+pub const MAX_STAGE_HISTORY: usize = 100;
+
+/// One entry in `Deal::stage_history`; `from` is `None` for the initial stage
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct StageChange {
+    pub from: Option<DealStage>,
+    pub to: DealStage,
+    pub timestamp: Timestamp,
+}
+
+/// Comment appended to a deal by `add_deal_comment`
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct DealComment {
+    pub author: Principal,
+    pub text: String,
+    pub timestamp: Timestamp,
+}
+
+impl Deal {
+    /// Move the deal to `stage`, stamping `closed_at` on close and clearing it on reopen
+    /// Reopening a closed deal also bumps `reopen_count`; actual changes are appended to
+    /// `stage_history`, which keeps the last `MAX_STAGE_HISTORY` entries
+    pub fn set_stage(&mut self, stage: DealStage, now: Timestamp) {
+        if stage != self.stage {
+            self.stage_history.push(StageChange {
+                from: Some(self.stage.clone()),
+                to: stage.clone(),
+                timestamp: now,
+            });
+            if self.stage_history.len() > MAX_STAGE_HISTORY {
+                let excess = self.stage_history.len() - MAX_STAGE_HISTORY;
+                self.stage_history.drain(0..excess);
+            }
+        }
+        if !stage.is_closed() {
+            if self.stage.is_closed() {
+                self.reopen_count = self.reopen_count.saturating_add(1);
+            }
+            self.closed_at = None;
+        } else if stage != self.stage {
+            self.closed_at = Some(now);
+        }
+        self.stage = stage;
+    }
+
+    /// Timestamp used to place a closed deal in a reporting window
+    /// Falls back to `updated_at` for deals closed before `closed_at` existed
+    pub fn close_time(&self) -> Option<Timestamp> {
+        if !self.stage.is_closed() {
+            return None;
+        }
+        Some(self.closed_at.unwrap_or(self.updated_at))
+    }
+
+    /// Deal value formatted as dollars with two decimals (e.g. "500.00")
+    pub fn value_display_dollars(&self) -> Option<String> {
+        self.value.map(format_cents_as_dollars)
+    }
+}
+
+/// Format an amount in cents as a dollar string with two decimals
+pub fn format_cents_as_dollars(cents: u64) -> String {
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Deal with derived display values, so UIs don't re-derive them inconsistently
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct DealView {
+    pub deal: Deal,
+    pub value_display_dollars: Option<String>,
+}
+
+impl From<Deal> for DealView {
+    fn from(deal: Deal) -> Self {
+        let value_display_dollars = deal.value_display_dollars();
+        DealView { deal, value_display_dollars }
+    }
 }
 
 /// Request to create a deal
@@ -129,9 +308,48 @@ pub struct Deal {
 pub struct CreateDealRequest {
     pub contact_id: ContactId,
     pub name: String,
+    /// Deal value in cents
     pub value: Option<u64>,
     pub notes: Option<String>,
     pub expected_close_date: Option<Timestamp>,
+    /// Backdated creation time for data migration (honored for controllers only)
+    #[serde(default)]
+    pub created_at_override: Option<Timestamp>,
+    /// Admin to assign the record to instead of the caller (honored for controllers only)
+    #[serde(default)]
+    pub owner_id: Option<Principal>,
+}
+
+/// Template for the deal auto-created by `create_contact_from_signup`
+/// Unset fields fall back to the built-in defaults
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, Default)]
+pub struct SignupDealTemplate {
+    /// Prepended to "Contact #<id>" (default "New signup: ")
+    pub name_prefix: Option<String>,
+    pub default_value: Option<u64>,
+    pub notes: Option<String>,
+}
+
+/// Runtime name-length policy for contacts and deals (`set_name_length_limits`)
+/// Defaults to the compile-time constants in `validation`
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct NameLengthLimits {
+    pub contact_name_min: u32,
+    pub contact_name_max: u32,
+    pub deal_name_min: u32,
+    pub deal_name_max: u32,
+}
+
+impl Default for NameLengthLimits {
+    fn default() -> Self {
+        use crate::validation::*;
+        Self {
+            contact_name_min: CONTACT_NAME_MIN_LEN as u32,
+            contact_name_max: CONTACT_NAME_MAX_LEN as u32,
+            deal_name_min: DEAL_NAME_MIN_LEN as u32,
+            deal_name_max: DEAL_NAME_MAX_LEN as u32,
+        }
+    }
 }
 
 /// Request to update a deal
@@ -140,10 +358,26 @@ pub struct CreateDealRequest {
 pub struct UpdateDealRequest {
     pub id: DealId,
     pub name: Option<String>,
+    /// Deal value in cents
     pub value: Option<u64>,
     pub stage: Option<DealStage>,
     pub notes: Option<String>,
     pub expected_close_date: Option<Timestamp>,
+    /// Move to a stage of the configured pipeline; omitted keeps the current one
+    #[serde(default)]
+    pub custom_stage: Option<String>,
+}
+
+impl UpdateDealRequest {
+    /// True if the request would modify at least one field
+    pub fn has_changes(&self) -> bool {
+        self.name.is_some()
+            || self.value.is_some()
+            || self.stage.is_some()
+            || self.notes.is_some()
+            || self.expected_close_date.is_some()
+            || self.custom_stage.is_some()
+    }
 }
 
 // =============================================================================
@@ -183,6 +417,12 @@ pub struct Transaction {
     pub reference: Option<String>,
     pub date: Timestamp,
     pub created_at: Timestamp,
+    /// Original transaction this one reverses (e.g. a refund of income)
+    #[serde(default)]
+    pub reverses: Option<TransactionId>,
+    /// Lowercase hex SHA-256 of the receipt document; set at creation and never changed
+    #[serde(default)]
+    pub receipt_hash: Option<String>,
 }
 
 /// Request to create a transaction
@@ -195,6 +435,19 @@ pub struct CreateTransactionRequest {
     pub description: String,
     pub reference: Option<String>,
     pub date: Option<Timestamp>,
+    /// Original transaction being reversed; must exist and be of the opposite type
+    #[serde(default)]
+    pub reverses: Option<TransactionId>,
+    /// SHA-256 of the receipt document as 64 hex characters
+    #[serde(default)]
+    pub receipt_hash: Option<String>,
+}
+
+/// Outcome of `bulk_create_transactions`: ids created, and (row index, error) for rejected rows
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct BulkImportResult {
+    pub created: Vec<TransactionId>,
+    pub errors: Vec<(u32, String)>,
 }
 
 // =============================================================================
@@ -223,6 +476,16 @@ pub struct MetricsSnapshot {
     pub timestamp: Timestamp,
 }
 
+/// Retention windows for `metrics_history` compaction
+/// Unset fields fall back to the built-in defaults
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, Default)]
+pub struct MetricsRetention {
+    /// Snapshots newer than this many days are kept at full resolution (default 90)
+    pub full_resolution_days: Option<u32>,
+    /// Older snapshots are downsampled to one per this many days (default 7)
+    pub downsample_interval_days: Option<u32>,
+}
+
 // =============================================================================
 // Feature Flags
 // =============================================================================
@@ -235,7 +498,36 @@ pub struct FeatureFlag {
     pub description: Option<String>,
     pub percentage: Option<u8>,
     pub allowed_principals: Vec<Principal>,
+    /// Principals that never see the flag, regardless of percentage rollout
+    #[serde(default)]
+    pub excluded_principals: Vec<Principal>,
     pub updated_at: Timestamp,
+    /// Rollout in tenths of a percent (0-1000); takes precedence over `percentage`
+    #[serde(default)]
+    pub permille: Option<u16>,
+    /// Record true evaluations made through `check_feature_audited` (for money-moving features)
+    #[serde(default)]
+    pub audit_evaluations: bool,
+}
+
+/// A true evaluation of a flag with `audit_evaluations` on, via `check_feature_audited`
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct FlagEvaluation {
+    pub timestamp: Timestamp,
+    pub caller: Principal,
+    pub key: String,
+}
+
+impl FeatureFlag {
+    /// Public view of the flag without the principal lists or description
+    pub fn redacted(&self) -> FeatureFlag {
+        FeatureFlag {
+            description: None,
+            allowed_principals: Vec::new(),
+            excluded_principals: Vec::new(),
+            ..self.clone()
+        }
+    }
 }
 
 /// Request to set feature flag
@@ -246,6 +538,17 @@ pub struct SetFeatureFlagRequest {
     pub description: Option<String>,
     pub percentage: Option<u8>,
     pub allowed_principals: Option<Vec<Principal>>,
+    #[serde(default)]
+    pub excluded_principals: Option<Vec<Principal>>,
+    /// Optimistic concurrency: reject the write unless the stored flag's `updated_at` matches
+    #[serde(default)]
+    pub expected_updated_at: Option<Timestamp>,
+    /// Turn evaluation auditing on or off; `None` keeps the flag's current setting
+    #[serde(default)]
+    pub audit_evaluations: Option<bool>,
+    /// Rollout in tenths of a percent (0-1000), e.g. 5 for a 0.5% canary
+    #[serde(default)]
+    pub permille: Option<u16>,
 }
 
 // =============================================================================
@@ -258,13 +561,66 @@ pub struct ContactFilter {
     pub status: Option<ContactStatus>,
     pub source: Option<ContactSource>,
     pub search: Option<String>,
+    /// Restrict to contacts owned by this principal (requires ViewAllContacts)
+    #[serde(default)]
+    pub owner_id: Option<Principal>,
+    /// Report which fields matched `search` (used by `get_contact_matches`)
+    #[serde(default)]
+    pub with_match_info: Option<bool>,
+}
+
+/// Contact search result with the fields that contained the search term
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct ContactMatch {
+    pub contact: Contact,
+    /// Any of "email", "name", "company"
+    pub matched_fields: Vec<String>,
 }
 
-/// Deal filter
+/// Record of a deleted contact kept for `get_contact_changes_since`
+/// `owner_id` lets row-level security apply to deletions too
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct ContactTombstone {
+    pub id: ContactId,
+    pub owner_id: Option<Principal>,
+    pub deleted_at: Timestamp,
+}
+
+/// One entry of a contact change feed, applied by clients as a patch
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub enum ContactChange {
+    /// Contact created after `since`
+    Created(Contact),
+    /// Contact that existed at `since` and was modified since
+    Updated(Contact),
+    /// Contact deleted after `since`
+    Deleted { id: ContactId, deleted_at: Timestamp },
+}
+
+/// Results of `global_search`, one capped list per entity
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct SearchResults {
+    pub contacts: Vec<Contact>,
+    pub deals: Vec<Deal>,
+}
+
+/// Deal filter; set fields combine with AND
 #[derive(Clone, Debug, CandidType, Deserialize, Default)]
 pub struct DealFilter {
     pub stage: Option<DealStage>,
+    /// Match deals in any of these stages (ANDed with `stage` when both are set)
+    #[serde(default)]
+    pub stages: Option<Vec<DealStage>>,
     pub contact_id: Option<ContactId>,
+    /// Restrict to deals owned by this principal (requires ViewAllDeals)
+    #[serde(default)]
+    pub owner_id: Option<Principal>,
+    /// Inclusive lower bound on `value` in cents; deals without a value count as 0
+    #[serde(default)]
+    pub min_value: Option<u64>,
+    /// Inclusive upper bound on `value` in cents; deals without a value count as 0
+    #[serde(default)]
+    pub max_value: Option<u64>,
 }
 
 /// Transaction filter
@@ -274,8 +630,23 @@ pub struct TransactionFilter {
     pub category: Option<TransactionCategory>,
     pub from_date: Option<Timestamp>,
     pub to_date: Option<Timestamp>,
+    /// Order by `date` (ties broken by id) instead of id order
+    #[serde(default)]
+    pub sort_by_date: Option<bool>,
+    /// With `sort_by_date`, newest first; ignored otherwise
+    #[serde(default)]
+    pub descending: Option<bool>,
+    /// Only transactions whose `reference` starts with this prefix (case-sensitive)
+    #[serde(default)]
+    pub reference_prefix: Option<String>,
 }
 
+/// Page size used when a request does not specify a limit
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+
+/// Largest page any list endpoint will return; larger limits are clamped
+pub const MAX_PAGE_SIZE: u64 = 200;
+
 /// Pagination params
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct PaginationParams {
@@ -287,7 +658,7 @@ impl Default for PaginationParams {
     fn default() -> Self {
         Self {
             offset: Some(0),
-            limit: Some(50),
+            limit: Some(DEFAULT_PAGE_SIZE),
         }
     }
 }
@@ -301,17 +672,126 @@ pub struct PaginatedResponse<T> {
     pub limit: u64,
 }
 
+impl<T> PaginatedResponse<T> {
+    /// Slice one page out of `items`
+    /// `limit` is clamped to `MAX_PAGE_SIZE` and `offset` to the number of items;
+    /// the clamped values are echoed back so clients know what they got
+    pub fn paginate(items: Vec<T>, pagination: &PaginationParams) -> Self {
+        let total = items.len() as u64;
+        let limit = pagination.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let offset = pagination.offset.unwrap_or(0).min(total);
+
+        let items: Vec<T> = items
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+
+        PaginatedResponse {
+            items,
+            total,
+            offset,
+            limit,
+        }
+    }
+}
+
 /// Financial summary
 #[derive(Clone, Debug, CandidType, Serialize)]
 pub struct FinancialSummary {
     pub total_income: u64,
     pub total_expenses: u64,
+    /// Income minus expenses, clamped to the `i64` range
     pub net: i64,
+    /// True when the exact net did not fit in `i64` and `net` was clamped
+    pub net_truncated: bool,
     pub mrr: u64,
+    pub income_count: u64,
+    pub expense_count: u64,
+    /// Mean amount across income and expense transactions; 0 when there are none
+    pub avg_transaction_amount: u64,
     pub period_start: Timestamp,
     pub period_end: Timestamp,
 }
 
+/// A period's financial summary next to the equal-length period just before it
+/// `*_change_pct` is the percentage change from `previous` (e.g. 25.0 for +25%),
+/// or None when the previous value was zero
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct FinancialComparison {
+    pub current: FinancialSummary,
+    pub previous: FinancialSummary,
+    pub income_change_pct: Option<f64>,
+    pub expenses_change_pct: Option<f64>,
+    /// Relative to the magnitude of the previous net, so a smaller loss is positive
+    pub net_change_pct: Option<f64>,
+}
+
+/// Percentage change from `previous` to `current`; None when `previous` is zero
+pub fn percent_change(previous: f64, current: f64) -> Option<f64> {
+    if previous == 0.0 {
+        None
+    } else {
+        Some((current - previous) / previous.abs() * 100.0)
+    }
+}
+
+/// Won/lost tally for closed deals
+/// `win_rate` is ClosedWon / (ClosedWon + ClosedLost) in 0.0..=1.0,
+/// or None when no deals were closed in the window
+#[derive(Clone, Debug, CandidType, Serialize, PartialEq)]
+pub struct WinRate {
+    pub won: u64,
+    pub lost: u64,
+    pub win_rate: Option<f64>,
+}
+
+impl WinRate {
+    pub fn new(won: u64, lost: u64) -> Self {
+        let closed = won + lost;
+        let win_rate = if closed == 0 {
+            None
+        } else {
+            Some(won as f64 / closed as f64)
+        };
+        WinRate { won, lost, win_rate }
+    }
+}
+
+/// Deal win rate report, overall and per owner
+/// Deals count as closed at their `closed_at` timestamp
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct WinRateReport {
+    pub overall: WinRate,
+    /// Per-owner breakdown; deals without an owner are grouped under None
+    pub by_owner: Vec<(Option<Principal>, WinRate)>,
+    pub period_start: Option<Timestamp>,
+    pub period_end: Option<Timestamp>,
+}
+
+/// Personal dashboard counts for `get_my_stats`
+/// Records owned by the caller, or all records for holders of ViewAllContacts/ViewAllDeals;
+/// `open_pipeline_value` is the summed value of open deals in cents
+#[derive(Clone, Debug, CandidType, Serialize, PartialEq)]
+pub struct MyStats {
+    pub contacts: u64,
+    pub deals: u64,
+    pub open_deals: u64,
+    pub closed_deals: u64,
+    pub open_pipeline_value: u64,
+}
+
+/// Per-owner deal board row for `get_deals_by_owner`
+/// Values are in cents; deals without an owner are grouped under the anonymous principal
+#[derive(Clone, Debug, CandidType, Serialize, PartialEq)]
+pub struct OwnerDealSummary {
+    pub owner: Principal,
+    pub open_count: u64,
+    pub won_count: u64,
+    pub open_value: u64,
+    pub won_value: u64,
+}
+
 // =============================================================================
 // Admin Permissions (FOS-5.6.10)
 // =============================================================================
@@ -340,6 +820,28 @@ pub enum AdminPermission {
     ViewAuditLogs,
 }
 
+impl AdminPermission {
+    /// Every permission, in declaration order (the implicit set held by controllers)
+    pub fn all() -> Vec<AdminPermission> {
+        vec![
+            AdminPermission::ViewOwnContacts,
+            AdminPermission::ViewAllContacts,
+            AdminPermission::EditOwnContacts,
+            AdminPermission::EditAllContacts,
+            AdminPermission::DeleteOwnContacts,
+            AdminPermission::DeleteAllContacts,
+            AdminPermission::ViewOwnDeals,
+            AdminPermission::ViewAllDeals,
+            AdminPermission::EditOwnDeals,
+            AdminPermission::EditAllDeals,
+            AdminPermission::DeleteOwnDeals,
+            AdminPermission::DeleteAllDeals,
+            AdminPermission::ManageFeatureFlags,
+            AdminPermission::ViewAuditLogs,
+        ]
+    }
+}
+
 /// Audit log entry for tracking admin actions
 /// @see AC-5.6.10.4 - CRM audit logging
 /// @see AC-5.6.10.5 - Feature flag audit logging
@@ -353,3 +855,361 @@ pub struct AuditLogEntry {
     pub target_id: String,
     pub details: Option<String>,
 }
+
+/// A rejected `require_controller` / `require_admin` check
+/// Kept in its own bounded buffer rather than the audit log
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AccessDenial {
+    pub timestamp: Timestamp,
+    pub caller: Principal,
+    /// Endpoint name the caller attempted
+    pub action: String,
+    /// Role the endpoint required: "controller" or "admin"
+    pub required: String,
+}
+
+/// Every action recorded in the audit log; `as_str` is the stored `AuditLogEntry::action`
+/// Using the enum at call sites keeps action names consistent and filterable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    AcknowledgeAuditExport,
+    AddCanisterRole,
+    AddDealAttachment,
+    AddDealComment,
+    ApproveDealClose,
+    AssignUnownedTo,
+    AutoChurnContact,
+    AutoDeriveContactStatus,
+    BulkCreateTransactions,
+    CascadeDeleteDeal,
+    CreateContact,
+    CreateContactFromSignup,
+    CreateDeal,
+    CreateDealFromSignup,
+    DeleteContact,
+    DeleteDeal,
+    DeleteFeatureFlag,
+    DeleteOrphanedDeal,
+    EnrichContactFromSignup,
+    PurgeAuditEntries,
+    RemoveDealAttachment,
+    RenameTag,
+    SeedDefaultFeatureFlags,
+    SetAuditExportPolicy,
+    SetAuthorizedCanisterRateLimit,
+    SetAutoChurnAfterSecs,
+    SetAutoStatusFromDeals,
+    SetCompactSignupAudit,
+    SetContactTags,
+    SetDealApprovalThreshold,
+    SetDefaultPageSize,
+    SetEmailBlocklist,
+    SetFeatureFlag,
+    SetFeatureFlags,
+    SetLowCyclesThreshold,
+    SetMaintenanceMode,
+    SetManualContactDefaultSource,
+    SetMaxAuditDetailLen,
+    SetMetricsRetention,
+    SetMigrationDefaultOwner,
+    SetMirrorCrmToActivityLog,
+    SetNameLengthLimits,
+    SetNormalizePlusAddressing,
+    SetPipelineStages,
+    SetProtectedFlagKeys,
+    SetRedactPiiInAudit,
+    SetRequireContactName,
+    SetSignupDealTemplate,
+    SetSignupRateLimit,
+    UpdateContact,
+    UpdateDeal,
+    UpdateDealStage,
+    UpdateMarketingConsent,
+}
+
+impl AuditAction {
+    /// All actions, in alphabetical order of their names
+    pub const ALL: &'static [AuditAction] = &[
+        AuditAction::AcknowledgeAuditExport,
+        AuditAction::AddCanisterRole,
+        AuditAction::AddDealAttachment,
+        AuditAction::AddDealComment,
+        AuditAction::ApproveDealClose,
+        AuditAction::AssignUnownedTo,
+        AuditAction::AutoChurnContact,
+        AuditAction::AutoDeriveContactStatus,
+        AuditAction::BulkCreateTransactions,
+        AuditAction::CascadeDeleteDeal,
+        AuditAction::CreateContact,
+        AuditAction::CreateContactFromSignup,
+        AuditAction::CreateDeal,
+        AuditAction::CreateDealFromSignup,
+        AuditAction::DeleteContact,
+        AuditAction::DeleteDeal,
+        AuditAction::DeleteFeatureFlag,
+        AuditAction::DeleteOrphanedDeal,
+        AuditAction::EnrichContactFromSignup,
+        AuditAction::PurgeAuditEntries,
+        AuditAction::RemoveDealAttachment,
+        AuditAction::RenameTag,
+        AuditAction::SeedDefaultFeatureFlags,
+        AuditAction::SetAuditExportPolicy,
+        AuditAction::SetAuthorizedCanisterRateLimit,
+        AuditAction::SetAutoChurnAfterSecs,
+        AuditAction::SetAutoStatusFromDeals,
+        AuditAction::SetCompactSignupAudit,
+        AuditAction::SetContactTags,
+        AuditAction::SetDealApprovalThreshold,
+        AuditAction::SetDefaultPageSize,
+        AuditAction::SetEmailBlocklist,
+        AuditAction::SetFeatureFlag,
+        AuditAction::SetFeatureFlags,
+        AuditAction::SetLowCyclesThreshold,
+        AuditAction::SetMaintenanceMode,
+        AuditAction::SetManualContactDefaultSource,
+        AuditAction::SetMaxAuditDetailLen,
+        AuditAction::SetMetricsRetention,
+        AuditAction::SetMigrationDefaultOwner,
+        AuditAction::SetMirrorCrmToActivityLog,
+        AuditAction::SetNameLengthLimits,
+        AuditAction::SetNormalizePlusAddressing,
+        AuditAction::SetPipelineStages,
+        AuditAction::SetProtectedFlagKeys,
+        AuditAction::SetRedactPiiInAudit,
+        AuditAction::SetRequireContactName,
+        AuditAction::SetSignupDealTemplate,
+        AuditAction::SetSignupRateLimit,
+        AuditAction::UpdateContact,
+        AuditAction::UpdateDeal,
+        AuditAction::UpdateDealStage,
+        AuditAction::UpdateMarketingConsent,
+    ];
+
+    /// Action name as stored in the audit log (snake_case)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::AcknowledgeAuditExport => "acknowledge_audit_export",
+            AuditAction::AddCanisterRole => "add_canister_role",
+            AuditAction::AddDealAttachment => "add_deal_attachment",
+            AuditAction::AddDealComment => "add_deal_comment",
+            AuditAction::ApproveDealClose => "approve_deal_close",
+            AuditAction::AssignUnownedTo => "assign_unowned_to",
+            AuditAction::AutoChurnContact => "auto_churn_contact",
+            AuditAction::AutoDeriveContactStatus => "auto_derive_contact_status",
+            AuditAction::BulkCreateTransactions => "bulk_create_transactions",
+            AuditAction::CascadeDeleteDeal => "cascade_delete_deal",
+            AuditAction::CreateContact => "create_contact",
+            AuditAction::CreateContactFromSignup => "create_contact_from_signup",
+            AuditAction::CreateDeal => "create_deal",
+            AuditAction::CreateDealFromSignup => "create_deal_from_signup",
+            AuditAction::DeleteContact => "delete_contact",
+            AuditAction::DeleteDeal => "delete_deal",
+            AuditAction::DeleteFeatureFlag => "delete_feature_flag",
+            AuditAction::DeleteOrphanedDeal => "delete_orphaned_deal",
+            AuditAction::EnrichContactFromSignup => "enrich_contact_from_signup",
+            AuditAction::PurgeAuditEntries => "purge_audit_entries",
+            AuditAction::RemoveDealAttachment => "remove_deal_attachment",
+            AuditAction::RenameTag => "rename_tag",
+            AuditAction::SeedDefaultFeatureFlags => "seed_default_feature_flags",
+            AuditAction::SetAuditExportPolicy => "set_audit_export_policy",
+            AuditAction::SetAuthorizedCanisterRateLimit => "set_authorized_canister_rate_limit",
+            AuditAction::SetAutoChurnAfterSecs => "set_auto_churn_after_secs",
+            AuditAction::SetAutoStatusFromDeals => "set_auto_status_from_deals",
+            AuditAction::SetCompactSignupAudit => "set_compact_signup_audit",
+            AuditAction::SetContactTags => "set_contact_tags",
+            AuditAction::SetDealApprovalThreshold => "set_deal_approval_threshold",
+            AuditAction::SetDefaultPageSize => "set_default_page_size",
+            AuditAction::SetEmailBlocklist => "set_email_blocklist",
+            AuditAction::SetFeatureFlag => "set_feature_flag",
+            AuditAction::SetFeatureFlags => "set_feature_flags",
+            AuditAction::SetLowCyclesThreshold => "set_low_cycles_threshold",
+            AuditAction::SetMaintenanceMode => "set_maintenance_mode",
+            AuditAction::SetManualContactDefaultSource => "set_manual_contact_default_source",
+            AuditAction::SetMaxAuditDetailLen => "set_max_audit_detail_len",
+            AuditAction::SetMetricsRetention => "set_metrics_retention",
+            AuditAction::SetMigrationDefaultOwner => "set_migration_default_owner",
+            AuditAction::SetMirrorCrmToActivityLog => "set_mirror_crm_to_activity_log",
+            AuditAction::SetNameLengthLimits => "set_name_length_limits",
+            AuditAction::SetNormalizePlusAddressing => "set_normalize_plus_addressing",
+            AuditAction::SetPipelineStages => "set_pipeline_stages",
+            AuditAction::SetProtectedFlagKeys => "set_protected_flag_keys",
+            AuditAction::SetRedactPiiInAudit => "set_redact_pii_in_audit",
+            AuditAction::SetRequireContactName => "set_require_contact_name",
+            AuditAction::SetSignupDealTemplate => "set_signup_deal_template",
+            AuditAction::SetSignupRateLimit => "set_signup_rate_limit",
+            AuditAction::UpdateContact => "update_contact",
+            AuditAction::UpdateDeal => "update_deal",
+            AuditAction::UpdateDealStage => "update_deal_stage",
+            AuditAction::UpdateMarketingConsent => "update_marketing_consent",
+        }
+    }
+
+    /// Activity name mirrored into `activity_log` for CRM mutations, None for everything else
+    pub fn crm_activity(&self) -> Option<&'static str> {
+        match self {
+            AuditAction::CreateContact | AuditAction::CreateContactFromSignup => Some("contact_created"),
+            AuditAction::UpdateContact
+            | AuditAction::UpdateMarketingConsent
+            | AuditAction::EnrichContactFromSignup
+            | AuditAction::SetContactTags
+            | AuditAction::AutoChurnContact
+            | AuditAction::AutoDeriveContactStatus => Some("contact_updated"),
+            AuditAction::DeleteContact => Some("contact_deleted"),
+            AuditAction::CreateDeal | AuditAction::CreateDealFromSignup => Some("deal_created"),
+            AuditAction::UpdateDeal
+            | AuditAction::UpdateDealStage
+            | AuditAction::AddDealComment
+            | AuditAction::AddDealAttachment
+            | AuditAction::RemoveDealAttachment => Some("deal_updated"),
+            AuditAction::DeleteDeal | AuditAction::CascadeDeleteDeal | AuditAction::DeleteOrphanedDeal => {
+                Some("deal_deleted")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Audit log entry with `details` decoded from its JSON string
+/// Each top-level detail key maps to its value as JSON text (strings unquoted)
+#[derive(Clone, Debug, CandidType, Serialize)]
+pub struct ParsedAuditLogEntry {
+    pub id: u64,
+    pub timestamp: Timestamp,
+    pub actor: Principal,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub details: Vec<(String, String)>,
+}
+
+// =============================================================================
+// Unit Tests
+// =============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_cents_as_dollars() {
+        assert_eq!(format_cents_as_dollars(0), "0.00");
+        assert_eq!(format_cents_as_dollars(5), "0.05");
+        assert_eq!(format_cents_as_dollars(50_000), "500.00");
+        assert_eq!(format_cents_as_dollars(123_456), "1234.56");
+    }
+
+    fn test_deal() -> Deal {
+        Deal {
+            id: 1,
+            contact_id: 1,
+            name: "Deal".to_string(),
+            value: Some(1_000_000_000),
+            stage: DealStage::Lead,
+            notes: None,
+            expected_close_date: None,
+            owner_id: None,
+            created_by: None,
+            created_at: 0,
+            updated_at: 0,
+            closed_at: None,
+            comments: Vec::new(),
+            custom_stage: None,
+            reopen_count: 0,
+            stage_history: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_deal_view_value_display_dollars() {
+        let deal = test_deal();
+
+        let view = DealView::from(deal.clone());
+        assert_eq!(view.value_display_dollars, Some("10000000.00".to_string()));
+
+        let no_value = DealView::from(Deal { value: None, ..deal });
+        assert_eq!(no_value.value_display_dollars, None);
+    }
+
+    #[test]
+    fn test_deal_set_stage_tracks_closed_at() {
+        let mut deal = test_deal();
+
+        deal.set_stage(DealStage::ClosedWon, 100);
+        assert_eq!(deal.closed_at, Some(100));
+
+        // Re-applying the same closed stage keeps the original close time
+        deal.set_stage(DealStage::ClosedWon, 200);
+        assert_eq!(deal.closed_at, Some(100));
+
+        deal.set_stage(DealStage::Negotiation, 300);
+        assert_eq!(deal.closed_at, None, "Reopening clears closed_at");
+        assert_eq!(deal.close_time(), None);
+        assert_eq!(deal.reopen_count, 1);
+
+        // Moving between open stages is not a reopen
+        deal.set_stage(DealStage::Proposal, 400);
+        assert_eq!(deal.reopen_count, 1);
+
+        let timestamps: Vec<Timestamp> = deal.stage_history.iter().map(|c| c.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 300, 400], "Re-applying a stage is not a change");
+        assert_eq!(deal.stage_history[0].from, Some(DealStage::Lead));
+
+        for t in 0..(MAX_STAGE_HISTORY as u64) {
+            let stage = if t % 2 == 0 { DealStage::Lead } else { DealStage::Proposal };
+            deal.set_stage(stage, 1_000 + t);
+        }
+        assert_eq!(deal.stage_history.len(), MAX_STAGE_HISTORY);
+        assert_eq!(deal.stage_history.last().map(|c| c.timestamp), Some(1_000 + MAX_STAGE_HISTORY as u64 - 1));
+    }
+
+    #[test]
+    fn test_paginate_clamps_limit_and_offset() {
+        let items: Vec<u64> = (0..500).collect();
+
+        let page = PaginatedResponse::paginate(
+            items.clone(),
+            &PaginationParams { offset: None, limit: Some(100_000) },
+        );
+        assert_eq!(page.limit, MAX_PAGE_SIZE);
+        assert_eq!(page.items.len() as u64, MAX_PAGE_SIZE);
+        assert_eq!(page.total, 500);
+
+        let page = PaginatedResponse::paginate(
+            items,
+            &PaginationParams { offset: Some(u64::MAX), limit: None },
+        );
+        assert_eq!(page.offset, 500);
+        assert_eq!(page.limit, DEFAULT_PAGE_SIZE);
+        assert!(page.items.is_empty());
+    }
+
+    #[test]
+    fn test_audit_action_names_are_unique_snake_case() {
+        let names: Vec<&str> = AuditAction::ALL.iter().map(|a| a.as_str()).collect();
+
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, names, "Action names must be unique and ALL kept in sorted order");
+
+        for name in names {
+            assert!(
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+                "Action name '{}' is not snake_case",
+                name
+            );
+        }
+        assert_eq!(AuditAction::CreateContact.as_str(), "create_contact");
+        assert_eq!(AuditAction::UpdateDealStage.as_str(), "update_deal_stage");
+    }
+
+    #[test]
+    fn test_crm_activity_covers_only_contact_and_deal_mutations() {
+        assert_eq!(AuditAction::CreateContact.crm_activity(), Some("contact_created"));
+        assert_eq!(AuditAction::UpdateDealStage.crm_activity(), Some("deal_updated"));
+        assert_eq!(AuditAction::CascadeDeleteDeal.crm_activity(), Some("deal_deleted"));
+        assert_eq!(AuditAction::SetFeatureFlag.crm_activity(), None);
+        assert_eq!(AuditAction::AcknowledgeAuditExport.crm_activity(), None);
+    }
+}
@@ -11,6 +11,7 @@
 //! @see AC-5.6.11.3 - Deal forms validate amount >= 0 and required fields
 //! @see AC-5.6.11.4 - Transaction forms validate amount with min/max limits
 
+use candid::Principal;
 use regex::Regex;
 use std::sync::LazyLock;
 
@@ -26,7 +27,7 @@ pub const MAX_TRANSACTION_AMOUNT: u64 = 100_000_000;
 pub const MAX_DEAL_VALUE: u64 = 1_000_000_000;
 
 // Field length limits (matching frontend schemas)
-/// Contact name: min 2, max 100 characters
+/// Contact name: min 2, max 100 characters (default `NameLengthLimits`)
 pub const CONTACT_NAME_MIN_LEN: usize = 2;
 pub const CONTACT_NAME_MAX_LEN: usize = 100;
 
@@ -36,13 +37,38 @@ pub const CONTACT_COMPANY_MAX_LEN: usize = 200;
 /// Contact notes: max 5000 characters
 pub const CONTACT_NOTES_MAX_LEN: usize = 5000;
 
-/// Deal name: min 3, max 200 characters
+/// Deal name: min 3, max 200 characters (default `NameLengthLimits`)
 pub const DEAL_NAME_MIN_LEN: usize = 3;
 pub const DEAL_NAME_MAX_LEN: usize = 200;
 
+/// Upper bound for runtime-configured name lengths (`NameLengthLimits`)
+pub const NAME_LENGTH_LIMIT_CEILING: u32 = 1000;
+
 /// Deal notes: max 5000 characters
 pub const DEAL_NOTES_MAX_LEN: usize = 5000;
 
+/// Deal comment: 1-2000 characters
+pub const DEAL_COMMENT_MAX_LEN: usize = 2000;
+
+/// Deal attachments: at most 20 per deal; name 1-200 characters, URL up to 2000
+pub const MAX_DEAL_ATTACHMENTS: usize = 20;
+pub const ATTACHMENT_NAME_MAX_LEN: usize = 200;
+pub const ATTACHMENT_URL_MAX_LEN: usize = 2000;
+
+/// Feature flag key: 1-100 characters of [A-Za-z0-9_-]
+pub const FEATURE_FLAG_KEY_MAX_LEN: usize = 100;
+
+/// Feature flag description: max 500 characters
+pub const FEATURE_FLAG_DESCRIPTION_MAX_LEN: usize = 500;
+
+/// Contact tags: at most 20 per contact, each 1-50 characters
+pub const MAX_CONTACT_TAGS: usize = 20;
+pub const CONTACT_TAG_MAX_LEN: usize = 50;
+
+/// Custom pipeline: at most 20 stages of 1-50 characters
+pub const MAX_PIPELINE_STAGES: usize = 20;
+pub const PIPELINE_STAGE_NAME_MAX_LEN: usize = 50;
+
 /// Transaction description: max 1000 characters
 pub const TRANSACTION_DESC_MAX_LEN: usize = 1000;
 
@@ -124,20 +150,20 @@ pub fn validate_optional_string_length(
 // Contact Validation
 // =============================================================================
 
-use crate::types::CreateContactRequest;
+use crate::types::{CreateContactRequest, NameLengthLimits};
 
 /// Validate a CreateContactRequest
 /// @see AC-5.6.11.1, AC-5.6.11.2
-pub fn validate_create_contact(request: &CreateContactRequest) -> Result<(), String> {
+pub fn validate_create_contact(request: &CreateContactRequest, limits: &NameLengthLimits) -> Result<(), String> {
     // Email is required and must be valid format
     validate_email(&request.email)?;
 
-    // Name: optional, but if provided must be 2-100 chars
+    // Name: optional, but if provided must be within the configured limits (default 2-100 chars)
     validate_optional_string_length(
         &request.name,
         "Name",
-        Some(CONTACT_NAME_MIN_LEN),
-        CONTACT_NAME_MAX_LEN,
+        Some(limits.contact_name_min as usize),
+        limits.contact_name_max as usize,
     )?;
 
     // Company: optional, max 200 chars
@@ -179,17 +205,17 @@ pub fn validate_create_contact(request: &CreateContactRequest) -> Result<(), Str
 // Contact Update Validation
 // =============================================================================
 
-use crate::types::UpdateContactRequest;
+use crate::types::{ContactField, UpdateContactRequest};
 
 /// Validate an UpdateContactRequest
 /// @see AC-5.6.11.1, AC-5.6.11.2
-pub fn validate_update_contact(request: &UpdateContactRequest) -> Result<(), String> {
-    // Name: if provided, must be 2-100 chars
+pub fn validate_update_contact(request: &UpdateContactRequest, limits: &NameLengthLimits) -> Result<(), String> {
+    // Name: if provided, must be within the configured limits (default 2-100 chars)
     validate_optional_string_length(
         &request.name,
         "Name",
-        Some(CONTACT_NAME_MIN_LEN),
-        CONTACT_NAME_MAX_LEN,
+        Some(limits.contact_name_min as usize),
+        limits.contact_name_max as usize,
     )?;
 
     // Company: if provided, max 200 chars
@@ -224,9 +250,69 @@ pub fn validate_update_contact(request: &UpdateContactRequest) -> Result<(), Str
         CONTACT_NOTES_MAX_LEN,
     )?;
 
+    // A field cannot be set and cleared in the same request
+    for field in request.clear_fields.iter().flatten() {
+        let is_set = match field {
+            ContactField::Name => request.name.is_some(),
+            ContactField::Company => request.company.is_some(),
+            ContactField::JobTitle => request.job_title.is_some(),
+            ContactField::InterestArea => request.interest_area.is_some(),
+            ContactField::Notes => request.notes.is_some(),
+        };
+        if is_set {
+            return Err(format!("Cannot both set and clear {:?}", field));
+        }
+    }
+
+    Ok(())
+}
+
+/// Enforce the controller's `require_contact_name` setting on create:
+/// a non-blank name must be given
+pub fn validate_required_contact_name(request: &CreateContactRequest) -> Result<(), String> {
+    if request.name.as_deref().is_none_or(|n| n.trim().is_empty()) {
+        return Err("Name is required".to_string());
+    }
+    Ok(())
+}
+
+/// Enforce the controller's `require_contact_name` setting on update:
+/// the name may be left unchanged but not blanked or cleared
+pub fn validate_required_contact_name_on_update(request: &UpdateContactRequest) -> Result<(), String> {
+    let blanked = request.name.as_deref().is_some_and(|n| n.trim().is_empty());
+    let cleared = request.clear_fields.iter().flatten().any(|f| *f == ContactField::Name);
+    if blanked || cleared {
+        return Err("Name is required".to_string());
+    }
     Ok(())
 }
 
+/// Canonical form of a contact tag: trimmed and lowercased
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalize a single tag and check its length
+pub fn validate_tag(tag: &str) -> Result<String, String> {
+    let tag = normalize_tag(tag);
+    if tag.is_empty() {
+        return Err("Tags cannot be empty".to_string());
+    }
+    validate_string_length(&tag, "Tag", None, CONTACT_TAG_MAX_LEN)?;
+    Ok(tag)
+}
+
+/// Normalize a contact's tag list: validated, deduplicated and sorted
+pub fn normalize_contact_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized = tags.iter().map(|t| validate_tag(t)).collect::<Result<Vec<_>, _>>()?;
+    normalized.sort();
+    normalized.dedup();
+    if normalized.len() > MAX_CONTACT_TAGS {
+        return Err(format!("A contact can have at most {} tags", MAX_CONTACT_TAGS));
+    }
+    Ok(normalized)
+}
+
 // =============================================================================
 // Deal Validation
 // =============================================================================
@@ -235,13 +321,13 @@ use crate::types::{CreateDealRequest, UpdateDealRequest};
 
 /// Validate a CreateDealRequest
 /// @see AC-5.6.11.1, AC-5.6.11.3
-pub fn validate_create_deal(request: &CreateDealRequest) -> Result<(), String> {
-    // Name is required, 3-200 chars
+pub fn validate_create_deal(request: &CreateDealRequest, limits: &NameLengthLimits) -> Result<(), String> {
+    // Name is required, within the configured limits (default 3-200 chars)
     validate_string_length(
         &request.name,
         "Deal name",
-        Some(DEAL_NAME_MIN_LEN),
-        DEAL_NAME_MAX_LEN,
+        Some(limits.deal_name_min as usize),
+        limits.deal_name_max as usize,
     )?;
 
     // Value: optional, but must not exceed max if provided
@@ -268,13 +354,13 @@ pub fn validate_create_deal(request: &CreateDealRequest) -> Result<(), String> {
 
 /// Validate an UpdateDealRequest
 /// @see AC-5.6.11.1, AC-5.6.11.3
-pub fn validate_update_deal(request: &UpdateDealRequest) -> Result<(), String> {
-    // Name: if provided, must be 3-200 chars
+pub fn validate_update_deal(request: &UpdateDealRequest, limits: &NameLengthLimits) -> Result<(), String> {
+    // Name: if provided, must be within the configured limits (default 3-200 chars)
     validate_optional_string_length(
         &request.name,
         "Deal name",
-        Some(DEAL_NAME_MIN_LEN),
-        DEAL_NAME_MAX_LEN,
+        Some(limits.deal_name_min as usize),
+        limits.deal_name_max as usize,
     )?;
 
     // Value: if provided, must not exceed max
@@ -298,6 +384,76 @@ pub fn validate_update_deal(request: &UpdateDealRequest) -> Result<(), String> {
     Ok(())
 }
 
+/// Validate runtime name-length limits: each min at least 1, min <= max <= ceiling
+pub fn validate_name_length_limits(limits: &NameLengthLimits) -> Result<(), String> {
+    for (field, min, max) in [
+        ("Contact name", limits.contact_name_min, limits.contact_name_max),
+        ("Deal name", limits.deal_name_min, limits.deal_name_max),
+    ] {
+        if min == 0 {
+            return Err(format!("{} minimum length must be at least 1", field));
+        }
+        if min > max {
+            return Err(format!("{} minimum length cannot exceed its maximum", field));
+        }
+        if max > NAME_LENGTH_LIMIT_CEILING {
+            return Err(format!("{} maximum length cannot exceed {}", field, NAME_LENGTH_LIMIT_CEILING));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a custom pipeline: bounded size, non-blank and unique stage names
+pub fn validate_pipeline_stages(stages: &[String]) -> Result<(), String> {
+    if stages.len() > MAX_PIPELINE_STAGES {
+        return Err(format!("A pipeline can have at most {} stages", MAX_PIPELINE_STAGES));
+    }
+    for (i, stage) in stages.iter().enumerate() {
+        if stage.trim().is_empty() {
+            return Err("Pipeline stage names cannot be empty".to_string());
+        }
+        validate_string_length(stage, "Pipeline stage", None, PIPELINE_STAGE_NAME_MAX_LEN)?;
+        if stages[..i].contains(stage) {
+            return Err(format!("Duplicate pipeline stage '{}'", stage));
+        }
+    }
+    Ok(())
+}
+
+/// Reject the anonymous principal as the target of an admin, controller, permission or canister grant
+pub fn validate_grantable_principal(principal: &Principal) -> Result<(), String> {
+    if *principal == Principal::anonymous() {
+        return Err("Cannot grant privileges to the anonymous principal".to_string());
+    }
+    Ok(())
+}
+
+/// Validate the text of a deal comment
+pub fn validate_deal_comment(text: &str) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("Comment cannot be empty".to_string());
+    }
+    validate_string_length(text, "Comment", None, DEAL_COMMENT_MAX_LEN)
+}
+
+/// Validate a deal attachment: non-blank name, https:// or ipfs:// URL without whitespace
+pub fn validate_deal_attachment(name: &str, url: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Attachment name cannot be empty".to_string());
+    }
+    validate_string_length(name, "Attachment name", None, ATTACHMENT_NAME_MAX_LEN)?;
+    validate_string_length(url, "Attachment URL", None, ATTACHMENT_URL_MAX_LEN)?;
+
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("ipfs://"))
+        .ok_or("Attachment URL must start with https:// or ipfs://")?;
+    if rest.is_empty() || url.chars().any(char::is_whitespace) {
+        return Err("Invalid attachment URL".to_string());
+    }
+    Ok(())
+}
+
 // =============================================================================
 // Transaction Validation
 // =============================================================================
@@ -342,9 +498,86 @@ pub fn validate_create_transaction(request: &CreateTransactionRequest) -> Result
         }
     }
 
+    // Receipt hash: if provided, a SHA-256 digest as 64 hex characters
+    if let Some(ref hash) = request.receipt_hash {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Receipt hash must be a 64-character hex SHA-256 digest".to_string());
+        }
+    }
+
     Ok(())
 }
 
+// =============================================================================
+// Feature Flag Validation
+// =============================================================================
+
+use crate::types::SetFeatureFlagRequest;
+
+/// Validate a SetFeatureFlagRequest
+/// Keys are map keys and audit target ids, so they are kept short and plain
+pub fn validate_set_feature_flag(request: &SetFeatureFlagRequest) -> Result<(), String> {
+    validate_string_length(&request.key, "Flag key", Some(1), FEATURE_FLAG_KEY_MAX_LEN)?;
+    if !request
+        .key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("Flag key may only contain letters, digits, '_' and '-'".to_string());
+    }
+
+    validate_optional_string_length(
+        &request.description,
+        "Description",
+        None,
+        FEATURE_FLAG_DESCRIPTION_MAX_LEN,
+    )?;
+
+    if request.permille.is_some_and(|p| p > 1000) {
+        return Err("Permille must be between 0 and 1000".to_string());
+    }
+
+    Ok(())
+}
+
+// =============================================================================
+// Data Quality Warnings
+// =============================================================================
+// Advisory messages for the UI; unlike validation these never block a write.
+
+use crate::types::{Contact, Deal, Timestamp};
+
+/// Data-quality nudges for a contact
+pub fn contact_warnings(contact: &Contact) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if contact.name.is_none() {
+        warnings.push("Contact has no name".to_string());
+    }
+    if contact.company.is_none() {
+        warnings.push("Contact has no company".to_string());
+    }
+    if contact.job_title.is_none() {
+        warnings.push("Contact has no job title".to_string());
+    }
+    warnings
+}
+
+/// Data-quality nudges for a deal; `now` is used to spot overdue open deals
+pub fn deal_warnings(deal: &Deal, now: Timestamp) -> Vec<String> {
+    let mut warnings = Vec::new();
+    match deal.expected_close_date {
+        None => warnings.push("Deal has no expected close date".to_string()),
+        Some(date) if date < now && !deal.stage.is_closed() => {
+            warnings.push("Deal is past its expected close date".to_string());
+        }
+        Some(_) => {}
+    }
+    if deal.value.is_none() {
+        warnings.push("Deal has no value".to_string());
+    }
+    warnings
+}
+
 // =============================================================================
 // Unit Tests
 // =============================================================================
@@ -421,8 +654,11 @@ mod tests {
             interest_area: Some("Technology".to_string()),
             source: None,
             notes: None,
+            created_at_override: None,
+            owner_id: None,
+            marketing_consent: None,
         };
-        assert!(validate_create_contact(&request).is_ok());
+        assert!(validate_create_contact(&request, &NameLengthLimits::default()).is_ok());
     }
 
     #[test]
@@ -436,8 +672,11 @@ mod tests {
             interest_area: None,
             source: None,
             notes: None,
+            created_at_override: None,
+            owner_id: None,
+            marketing_consent: None,
         };
-        let result = validate_create_contact(&request);
+        let result = validate_create_contact(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("email"));
     }
@@ -453,8 +692,11 @@ mod tests {
             interest_area: None,
             source: None,
             notes: None,
+            created_at_override: None,
+            owner_id: None,
+            marketing_consent: None,
         };
-        let result = validate_create_contact(&request);
+        let result = validate_create_contact(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Name"));
     }
@@ -470,8 +712,11 @@ mod tests {
             interest_area: None,
             source: None,
             notes: Some("x".repeat(5001)), // Too long
+            created_at_override: None,
+            owner_id: None,
+            marketing_consent: None,
         };
-        let result = validate_create_contact(&request);
+        let result = validate_create_contact(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Notes"));
     }
@@ -490,8 +735,10 @@ mod tests {
             interest_area: Some("Technology".to_string()),
             notes: Some("Updated notes".to_string()),
             status: None,
+            clear_fields: None,
+            marketing_consent: None,
         };
-        assert!(validate_update_contact(&request).is_ok());
+        assert!(validate_update_contact(&request, &NameLengthLimits::default()).is_ok());
     }
 
     #[test]
@@ -504,8 +751,10 @@ mod tests {
             interest_area: None,
             notes: None,
             status: None,
+            clear_fields: None,
+            marketing_consent: None,
         };
-        let result = validate_update_contact(&request);
+        let result = validate_update_contact(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Name"));
     }
@@ -520,8 +769,10 @@ mod tests {
             interest_area: None,
             notes: Some("x".repeat(5001)), // Too long
             status: None,
+            clear_fields: None,
+            marketing_consent: None,
         };
-        let result = validate_update_contact(&request);
+        let result = validate_update_contact(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Notes"));
     }
@@ -537,8 +788,28 @@ mod tests {
             interest_area: None,
             notes: None,
             status: None,
+            clear_fields: None,
+            marketing_consent: None,
+        };
+        assert!(validate_update_contact(&request, &NameLengthLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn test_update_contact_set_and_clear_same_field() {
+        let request = UpdateContactRequest {
+            id: 1,
+            name: None,
+            company: Some("Acme Corp".to_string()),
+            job_title: None,
+            interest_area: None,
+            notes: None,
+            status: None,
+            clear_fields: Some(vec![ContactField::Company]),
+            marketing_consent: None,
         };
-        assert!(validate_update_contact(&request).is_ok());
+        let result = validate_update_contact(&request, &NameLengthLimits::default());
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Company"));
     }
 
     // -------------------------------------------------------------------------
@@ -553,8 +824,10 @@ mod tests {
             value: Some(100_000), // $1,000.00
             notes: Some("Important deal".to_string()),
             expected_close_date: None,
+            created_at_override: None,
+            owner_id: None,
         };
-        assert!(validate_create_deal(&request).is_ok());
+        assert!(validate_create_deal(&request, &NameLengthLimits::default()).is_ok());
     }
 
     #[test]
@@ -565,12 +838,42 @@ mod tests {
             value: None,
             notes: None,
             expected_close_date: None,
+            created_at_override: None,
+            owner_id: None,
         };
-        let result = validate_create_deal(&request);
+        let result = validate_create_deal(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Deal name"));
     }
 
+    #[test]
+    fn test_deal_comment_length() {
+        assert!(validate_deal_comment("Called, follow up next week").is_ok());
+        assert!(validate_deal_comment("   ").is_err());
+        assert!(validate_deal_comment(&"a".repeat(DEAL_COMMENT_MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_validate_deal_attachment() {
+        assert!(validate_deal_attachment("Proposal", "https://example.com/p.pdf").is_ok());
+        assert!(validate_deal_attachment("Contract", "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi").is_ok());
+
+        assert!(validate_deal_attachment("Proposal", "http://example.com/p.pdf").is_err());
+        assert!(validate_deal_attachment("Proposal", "javascript:alert(1)").is_err());
+        assert!(validate_deal_attachment("Proposal", "https://").is_err());
+        assert!(validate_deal_attachment("Proposal", "https://example.com/a b").is_err());
+        assert!(validate_deal_attachment("  ", "https://example.com/p.pdf").is_err());
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let stages = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(validate_pipeline_stages(&stages(&["Discovery", "Pilot", "Contract"])).is_ok());
+        assert!(validate_pipeline_stages(&[]).is_ok());
+        assert!(validate_pipeline_stages(&stages(&["Pilot", " "])).is_err());
+        assert!(validate_pipeline_stages(&stages(&["Pilot", "Pilot"])).unwrap_err().contains("Duplicate"));
+    }
+
     #[test]
     fn test_deal_value_exceeds_max() {
         let request = CreateDealRequest {
@@ -579,8 +882,10 @@ mod tests {
             value: Some(MAX_DEAL_VALUE + 1), // Exceeds max
             notes: None,
             expected_close_date: None,
+            created_at_override: None,
+            owner_id: None,
         };
-        let result = validate_create_deal(&request);
+        let result = validate_create_deal(&request, &NameLengthLimits::default());
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("cannot exceed"));
     }
@@ -599,6 +904,8 @@ mod tests {
             description: "Monthly subscription".to_string(),
             reference: Some("INV-001".to_string()),
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         assert!(validate_create_transaction(&request).is_ok());
     }
@@ -613,6 +920,8 @@ mod tests {
             description: "Large expense".to_string(),
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         let result = validate_create_transaction(&request);
         assert!(result.is_err());
@@ -629,6 +938,8 @@ mod tests {
             description: "".to_string(), // Empty
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         let result = validate_create_transaction(&request);
         assert!(result.is_err());
@@ -645,6 +956,8 @@ mod tests {
             description: "Test".to_string(),
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         let result = validate_create_transaction(&request);
         assert!(result.is_err());
@@ -659,6 +972,8 @@ mod tests {
             description: "Test".to_string(),
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         assert!(validate_create_transaction(&request2).is_err());
     }
@@ -673,9 +988,108 @@ mod tests {
             description: "x".repeat(1001), // Too long
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         let result = validate_create_transaction(&request);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Description"));
     }
+
+    #[test]
+    fn test_transaction_receipt_hash() {
+        let request = |receipt_hash: &str| CreateTransactionRequest {
+            transaction_type: TransactionType::Expense,
+            category: TransactionCategory::Other,
+            amount: 1000,
+            currency: None,
+            description: "Hosting invoice".to_string(),
+            reference: None,
+            date: None,
+            reverses: None,
+            receipt_hash: Some(receipt_hash.to_string()),
+        };
+        let valid = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert!(validate_create_transaction(&request(valid)).is_ok());
+        assert!(validate_create_transaction(&request(&valid.to_uppercase())).is_ok());
+
+        assert!(validate_create_transaction(&request(&valid[..63])).is_err());
+        assert!(validate_create_transaction(&request(&valid.replace('e', "g"))).is_err());
+    }
+
+    #[test]
+    fn test_required_contact_name() {
+        let mut request = CreateContactRequest {
+            user_id: None,
+            email: "test@example.com".to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+            created_at_override: None,
+            owner_id: None,
+            marketing_consent: None,
+        };
+        assert!(validate_required_contact_name(&request).is_err());
+        request.name = Some("   ".to_string());
+        assert!(validate_required_contact_name(&request).is_err());
+        request.name = Some("Ada".to_string());
+        assert!(validate_required_contact_name(&request).is_ok());
+
+        let mut update = UpdateContactRequest {
+            id: 1,
+            name: None,
+            company: Some("Acme".to_string()),
+            job_title: None,
+            interest_area: None,
+            notes: None,
+            status: None,
+            clear_fields: None,
+            marketing_consent: None,
+        };
+        assert!(validate_required_contact_name_on_update(&update).is_ok(), "Unchanged name is fine");
+        update.clear_fields = Some(vec![ContactField::Name]);
+        assert!(validate_required_contact_name_on_update(&update).is_err());
+    }
+
+    #[test]
+    fn test_normalize_contact_tags() {
+        let tags = vec![" VIP ".to_string(), "vip".to_string(), "Beta".to_string()];
+        assert_eq!(normalize_contact_tags(&tags), Ok(vec!["beta".to_string(), "vip".to_string()]));
+
+        assert!(normalize_contact_tags(&["  ".to_string()]).is_err());
+        assert!(normalize_contact_tags(&["x".repeat(CONTACT_TAG_MAX_LEN + 1)]).is_err());
+    }
+
+    #[test]
+    fn test_configured_name_limits_are_applied() {
+        let request = CreateDealRequest {
+            contact_id: 1,
+            name: "Q".to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+            created_at_override: None,
+            owner_id: None,
+        };
+        assert!(validate_create_deal(&request, &NameLengthLimits::default()).is_err());
+
+        let limits = NameLengthLimits { deal_name_min: 1, ..NameLengthLimits::default() };
+        assert!(validate_name_length_limits(&limits).is_ok());
+        assert!(validate_create_deal(&request, &limits).is_ok());
+
+        assert!(validate_name_length_limits(&NameLengthLimits { deal_name_min: 0, ..limits.clone() }).is_err());
+        assert!(validate_name_length_limits(&NameLengthLimits { contact_name_min: 200, ..limits.clone() }).is_err());
+        assert!(validate_name_length_limits(&NameLengthLimits { deal_name_max: 5000, ..limits }).is_err());
+    }
+
+    #[test]
+    fn test_anonymous_principal_is_not_grantable() {
+        let err = validate_grantable_principal(&Principal::anonymous()).unwrap_err();
+        assert_eq!(err, "Cannot grant privileges to the anonymous principal");
+        assert!(validate_grantable_principal(&Principal::management_canister()).is_ok());
+        assert!(validate_grantable_principal(&Principal::self_authenticating([1])).is_ok());
+    }
 }
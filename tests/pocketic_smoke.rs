@@ -63,6 +63,22 @@ struct ContactFilter {
     status: Option<ContactStatus>,
     source: Option<ContactSource>,
     search: Option<String>,
+    owner_id: Option<Principal>,
+    with_match_info: Option<bool>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct ContactMatch {
+    contact: Contact,
+    matched_fields: Vec<String>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct PaginatedContactMatchResponse {
+    items: Vec<ContactMatch>,
+    total: u64,
+    offset: u64,
+    limit: u64,
 }
 
 // CRM - Deal Types
@@ -101,7 +117,11 @@ struct CreateDealRequest {
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
 struct DealFilter {
     stage: Option<DealStage>,
+    stages: Option<Vec<DealStage>>,
     contact_id: Option<ContactId>,
+    owner_id: Option<Principal>,
+    min_value: Option<u64>,
+    max_value: Option<u64>,
 }
 
 // Finance - Transaction Types
@@ -134,6 +154,8 @@ struct Transaction {
     reference: Option<String>,
     date: Timestamp,
     created_at: Timestamp,
+    reverses: Option<TransactionId>,
+    receipt_hash: Option<String>,
 }
 
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
@@ -145,6 +167,23 @@ struct CreateTransactionRequest {
     description: String,
     reference: Option<String>,
     date: Option<Timestamp>,
+    reverses: Option<TransactionId>,
+    receipt_hash: Option<String>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, PartialEq)]
+struct MyStats {
+    contacts: u64,
+    deals: u64,
+    open_deals: u64,
+    closed_deals: u64,
+    open_pipeline_value: u64,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize)]
+struct BulkImportResult {
+    created: Vec<TransactionId>,
+    errors: Vec<(u32, String)>,
 }
 
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
@@ -153,6 +192,9 @@ struct TransactionFilter {
     category: Option<TransactionCategory>,
     from_date: Option<Timestamp>,
     to_date: Option<Timestamp>,
+    sort_by_date: Option<bool>,
+    descending: Option<bool>,
+    reference_prefix: Option<String>,
 }
 
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
@@ -160,7 +202,11 @@ struct FinancialSummary {
     total_income: u64,
     total_expenses: u64,
     net: i64,
+    net_truncated: bool,
     mrr: u64,
+    income_count: u64,
+    expense_count: u64,
+    avg_transaction_amount: u64,
     period_start: Timestamp,
     period_end: Timestamp,
 }
@@ -173,9 +219,17 @@ struct FeatureFlag {
     description: Option<String>,
     percentage: Option<u8>,
     allowed_principals: Vec<Principal>,
+    excluded_principals: Vec<Principal>,
     updated_at: Timestamp,
 }
 
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct FlagEvaluation {
+    timestamp: u64,
+    caller: Principal,
+    key: String,
+}
+
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
 struct SetFeatureFlagRequest {
     key: String,
@@ -183,6 +237,10 @@ struct SetFeatureFlagRequest {
     description: Option<String>,
     percentage: Option<u8>,
     allowed_principals: Option<Vec<Principal>>,
+    excluded_principals: Option<Vec<Principal>>,
+    expected_updated_at: Option<Timestamp>,
+    audit_evaluations: Option<bool>,
+    permille: Option<u16>,
 }
 
 // Analytics - Metrics Types
@@ -230,6 +288,20 @@ struct PaginatedDealResponse {
     limit: u64,
 }
 
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct PaginatedContactChangeResponse {
+    items: Vec<ContactChange>,
+    total: u64,
+    offset: u64,
+    limit: u64,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct SearchResults {
+    contacts: Vec<Contact>,
+    deals: Vec<Deal>,
+}
+
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
 struct PaginatedTransactionResponse {
     items: Vec<Transaction>,
@@ -263,6 +335,10 @@ struct ContactV2 {
     status: ContactStatus,
     owner_id: Option<Principal>,
     team_id: Option<String>,
+    marketing_consent: Option<bool>,
+    consent_updated_at: Option<Timestamp>,
+    last_activity_at: Option<Timestamp>,
+    tags: Vec<String>,
     created_at: Timestamp,
     updated_at: Timestamp,
 }
@@ -281,6 +357,26 @@ struct DealV2 {
     created_by: Option<Principal>,
     created_at: Timestamp,
     updated_at: Timestamp,
+    closed_at: Option<Timestamp>,
+    comments: Vec<DealComment>,
+    custom_stage: Option<String>,
+    reopen_count: u32,
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, PartialEq)]
+struct Attachment {
+    name: String,
+    url: String,
+    added_by: Principal,
+    added_at: Timestamp,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, PartialEq)]
+struct DealComment {
+    author: Principal,
+    text: String,
+    timestamp: Timestamp,
 }
 
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
@@ -318,6 +414,15 @@ enum AdminPermission {
     ViewAuditLogs,
 }
 
+/// Rejected access attempt
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct AccessDenial {
+    timestamp: u64,
+    caller: Principal,
+    action: String,
+    required: String,
+}
+
 /// Audit log entry
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
 struct AuditLogEntry {
@@ -330,6 +435,17 @@ struct AuditLogEntry {
     details: Option<String>,
 }
 
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct ParsedAuditLogEntry {
+    id: u64,
+    timestamp: Timestamp,
+    actor: Principal,
+    action: String,
+    target_type: String,
+    target_id: String,
+    details: Vec<(String, String)>,
+}
+
 /// Request to update a contact
 #[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
 struct UpdateContactRequest {
@@ -340,6 +456,24 @@ struct UpdateContactRequest {
     interest_area: Option<String>,
     notes: Option<String>,
     status: Option<ContactStatus>,
+    clear_fields: Option<Vec<ContactField>>,
+    marketing_consent: Option<bool>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+enum ContactChange {
+    Created(ContactV2),
+    Updated(ContactV2),
+    Deleted { id: ContactId, deleted_at: Timestamp },
+}
+
+#[derive(Clone, Copy, Debug, candid::CandidType, Deserialize, Serialize, PartialEq)]
+enum ContactField {
+    Name,
+    Company,
+    JobTitle,
+    InterestArea,
+    Notes,
 }
 
 /// Request to update a deal
@@ -351,6 +485,113 @@ struct UpdateDealRequest {
     stage: Option<DealStage>,
     notes: Option<String>,
     expected_close_date: Option<Timestamp>,
+    custom_stage: Option<String>,
+}
+
+/// CreateContactRequest including fields added after the original API
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
+struct CreateContactRequestV2 {
+    user_id: Option<String>,
+    email: String,
+    name: Option<String>,
+    company: Option<String>,
+    job_title: Option<String>,
+    interest_area: Option<String>,
+    source: Option<ContactSource>,
+    notes: Option<String>,
+    created_at_override: Option<Timestamp>,
+    owner_id: Option<Principal>,
+    marketing_consent: Option<bool>,
+}
+
+/// CreateDealRequest including fields added after the original API
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
+struct CreateDealRequestV2 {
+    contact_id: ContactId,
+    name: String,
+    value: Option<u64>,
+    notes: Option<String>,
+    expected_close_date: Option<Timestamp>,
+    created_at_override: Option<Timestamp>,
+    owner_id: Option<Principal>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, PartialEq)]
+struct NameLengthLimits {
+    contact_name_min: u32,
+    contact_name_max: u32,
+    deal_name_min: u32,
+    deal_name_max: u32,
+}
+
+/// Metrics history retention windows
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
+struct MetricsRetention {
+    full_resolution_days: Option<u32>,
+    downsample_interval_days: Option<u32>,
+}
+
+/// Template for signup auto-deals
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, Default)]
+struct SignupDealTemplate {
+    name_prefix: Option<String>,
+    default_value: Option<u64>,
+    notes: Option<String>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct WinRate {
+    won: u64,
+    lost: u64,
+    win_rate: Option<f64>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct WinRateReport {
+    overall: WinRate,
+    by_owner: Vec<(Option<Principal>, WinRate)>,
+    period_start: Option<Timestamp>,
+    period_end: Option<Timestamp>,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize, PartialEq)]
+struct OwnerDealSummary {
+    owner: Principal,
+    open_count: u64,
+    won_count: u64,
+    open_value: u64,
+    won_value: u64,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize, Serialize)]
+struct HealthDetailed {
+    status: String,
+    cycle_balance: u64,
+    low_cycles: bool,
+    low_cycles_threshold: u64,
+    maintenance_mode: bool,
+    schema_version: u32,
+    audit_export_needed: bool,
+    audit_log_len: u64,
+    audit_exported_through_id: u64,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize)]
+struct CollectionSize {
+    count: u64,
+    approx_bytes: u64,
+}
+
+#[derive(Clone, Debug, candid::CandidType, Deserialize)]
+struct StateSizes {
+    contacts: CollectionSize,
+    deals: CollectionSize,
+    transactions: CollectionSize,
+    audit_log: CollectionSize,
+    activity_log: CollectionSize,
+    metrics_history: CollectionSize,
+    feature_flags: CollectionSize,
+    total_approx_bytes: u64,
 }
 
 // ============================================================================
@@ -404,6 +645,58 @@ fn non_admin_principal() -> Principal {
     Principal::from_text("2vxsx-fae").unwrap()
 }
 
+/// Create a distinct self-authenticating principal for multi-admin tests
+fn test_principal(seed: u8) -> Principal {
+    Principal::self_authenticating([seed])
+}
+
+/// Add `admin` as an admin and grant it the given permissions
+fn add_admin_with_permissions(
+    pic: &PocketIc,
+    canister_id: Principal,
+    controller: Principal,
+    admin: Principal,
+    permissions: &[AdminPermission],
+) {
+    let response = pic
+        .update_call(canister_id, controller, "add_admin", encode_one(admin).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should be able to add admin");
+
+    for permission in permissions {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "grant_permission",
+                encode_args((admin, permission.clone())).unwrap(),
+            )
+            .unwrap();
+        let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        assert!(result.is_ok(), "Controller should be able to grant permissions");
+    }
+}
+
+/// Create a contact with just an email, returning it
+fn create_test_contact(pic: &PocketIc, canister_id: Principal, caller: Principal, email: &str) -> ContactV2 {
+    let request = CreateContactRequest {
+        user_id: None,
+        email: email.to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+    let response = pic
+        .update_call(canister_id, caller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    result.expect("Should create contact")
+}
+
 // ============================================================================
 // Task 1: Setup & Health Tests (AC: 3.1.8.7)
 // ============================================================================
@@ -786,6 +1079,8 @@ fn test_get_contacts_with_filters() {
         source: Some(ContactSource::Marketing),
         status: None,
         search: None,
+        owner_id: None,
+        with_match_info: None,
     };
 
     let response = pic
@@ -901,6 +1196,10 @@ fn test_create_contact_from_signup_creates_deal() {
     let filter = DealFilter {
         contact_id: Some(contact.id),
         stage: None,
+        stages: None,
+        owner_id: None,
+        min_value: None,
+        max_value: None,
     };
 
     let deals_response = pic
@@ -1232,6 +1531,10 @@ fn test_get_deals_with_filters() {
     let filter = DealFilter {
         contact_id: Some(contact.id),
         stage: None,
+        stages: None,
+        owner_id: None,
+        min_value: None,
+        max_value: None,
     };
 
     let response = pic
@@ -1341,6 +1644,8 @@ fn test_create_transaction_income() {
         description: "Monthly subscription".to_string(),
         reference: Some("SUB-001".to_string()),
         date: Some(1704067200), // 2024-01-01
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -1371,6 +1676,8 @@ fn test_create_transaction_expense() {
         description: "Server costs".to_string(),
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -1406,6 +1713,8 @@ fn test_get_transactions_with_filters() {
             description: "Test transaction".to_string(),
             reference: None,
             date: None,
+            reverses: None,
+            receipt_hash: None,
         };
         pic.update_call(
             canister_id,
@@ -1422,6 +1731,9 @@ fn test_get_transactions_with_filters() {
         category: None,
         from_date: None,
         to_date: None,
+        sort_by_date: None,
+        descending: None,
+        reference_prefix: None,
     };
 
     let response = pic
@@ -1459,6 +1771,8 @@ fn test_get_financial_summary() {
             description: "Income".to_string(),
             reference: None,
             date: Some(base_time + 86400), // +1 day
+            reverses: None,
+            receipt_hash: None,
         };
         pic.update_call(
             canister_id,
@@ -1479,6 +1793,8 @@ fn test_get_financial_summary() {
             description: "Expense".to_string(),
             reference: None,
             date: Some(base_time + 86400),
+            reverses: None,
+            receipt_hash: None,
         };
         pic.update_call(
             canister_id,
@@ -1521,6 +1837,10 @@ fn test_set_feature_flag() {
         description: Some("Enable new dashboard UI".to_string()),
         percentage: None,
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     let response = pic
@@ -1547,6 +1867,10 @@ fn test_get_feature_flag() {
         description: Some("Beta testing".to_string()),
         percentage: Some(50),
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     pic.update_call(
@@ -1602,6 +1926,10 @@ fn test_is_feature_enabled_true() {
         description: None,
         percentage: None,
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     pic.update_call(
@@ -1635,6 +1963,10 @@ fn test_is_feature_enabled_false() {
         description: None,
         percentage: None,
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     pic.update_call(
@@ -1669,6 +2001,10 @@ fn test_feature_flag_percentage_rollout() {
         description: None,
         percentage: Some(100), // 100% rollout
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     pic.update_call(
@@ -1705,6 +2041,10 @@ fn test_feature_flag_principal_whitelist() {
         description: None,
         percentage: None,
         allowed_principals: Some(vec![allowed]),
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     pic.update_call(
@@ -1715,11 +2055,11 @@ fn test_feature_flag_principal_whitelist() {
     )
     .unwrap();
 
-    // Get the flag and verify whitelist
+    // Get the flag and verify whitelist (admins see the full config)
     let response = pic
         .query_call(
             canister_id,
-            Principal::anonymous(),
+            controller,
             "get_feature_flag",
             encode_one("whitelist_feature".to_string()).unwrap(),
         )
@@ -1742,6 +2082,10 @@ fn test_list_feature_flags() {
             description: None,
             percentage: None,
             allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
         };
         pic.update_call(
             canister_id,
@@ -1913,6 +2257,8 @@ fn test_get_admin_stats() {
         description: "Stats tx".to_string(),
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
     pic.update_call(
         canister_id,
@@ -1929,6 +2275,10 @@ fn test_get_admin_stats() {
         description: None,
         percentage: None,
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
     pic.update_call(
         canister_id,
@@ -2457,6 +2807,9 @@ fn test_log_activity_anonymous_rejected() {
 #[test]
 fn test_log_activity_rate_limit() {
     let (pic, canister_id, controller) = setup();
+    // Controllers are exempt from rate limiting, so use a regular admin
+    let admin = test_principal(20);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[]);
 
     // log_activity has a rate limit of 100 calls per minute
     // Make 100 calls which should all succeed
@@ -2464,7 +2817,7 @@ fn test_log_activity_rate_limit() {
         let response = pic
             .update_call(
                 canister_id,
-                controller,
+                admin,
                 "log_activity",
                 encode_args((
                     format!("user-{}", i),
@@ -2483,7 +2836,7 @@ fn test_log_activity_rate_limit() {
     let response = pic
         .update_call(
             canister_id,
-            controller,
+            admin,
             "log_activity",
             encode_args(("user-101".to_string(), "rate_test".to_string(), None::<String>)).unwrap(),
         )
@@ -2500,13 +2853,16 @@ fn test_log_activity_rate_limit() {
 #[test]
 fn test_log_activity_rate_limit_resets_after_window() {
     let (pic, canister_id, controller) = setup();
+    // Controllers are exempt from rate limiting, so use a regular admin
+    let admin = test_principal(21);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[]);
 
     // Make 100 calls to hit the rate limit
     for i in 0..100 {
         let response = pic
             .update_call(
                 canister_id,
-                controller,
+                admin,
                 "log_activity",
                 encode_args((
                     format!("user-{}", i),
@@ -2525,7 +2881,7 @@ fn test_log_activity_rate_limit_resets_after_window() {
     let response = pic
         .update_call(
             canister_id,
-            controller,
+            admin,
             "log_activity",
             encode_args(("user-blocked".to_string(), "rate_test".to_string(), None::<String>)).unwrap(),
         )
@@ -2543,7 +2899,7 @@ fn test_log_activity_rate_limit_resets_after_window() {
     let response = pic
         .update_call(
             canister_id,
-            controller,
+            admin,
             "log_activity",
             encode_args(("user-after-window".to_string(), "rate_test".to_string(), None::<String>)).unwrap(),
         )
@@ -3128,6 +3484,10 @@ fn test_feature_flag_requires_permission() {
         description: Some("Test flag".to_string()),
         percentage: None,
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     let response = pic
@@ -3155,6 +3515,10 @@ fn test_feature_flag_audit_logging() {
         description: Some("Audit test flag".to_string()),
         percentage: Some(50),
         allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
     };
 
     let _ = pic
@@ -3226,6 +3590,8 @@ fn test_update_contact_with_permission() {
         interest_area: None,
         notes: None,
         status: None,
+        clear_fields: None,
+        marketing_consent: None,
     };
 
     let response = pic
@@ -3306,6 +3672,72 @@ fn test_delete_contact_creates_audit_log() {
     assert_eq!(logs[0].action, "delete_contact");
 }
 
+#[test]
+fn test_delete_contact_audit_details_truncated_at_configured_length() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_max_audit_detail_len",
+            encode_one(64u32).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    let contact_id = create_test_contact(&pic, canister_id, controller, "truncate-audit@example.com").id;
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "delete_contact",
+            encode_one(contact_id).unwrap(),
+        )
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                None::<String>,
+                Some("config".to_string()),
+                None::<Principal>,
+                Some(10u64),
+            )).unwrap(),
+        )
+        .unwrap();
+    let entries: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let logs = entries.unwrap();
+    assert!(logs
+        .iter()
+        .any(|e| e.action == "set_max_audit_detail_len" && e.target_id == "max_audit_detail_len"));
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("delete_contact".to_string()),
+                None::<String>,
+                None::<Principal>,
+                Some(10u64),
+            )).unwrap(),
+        )
+        .unwrap();
+    let entries: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let logs = entries.unwrap();
+    let details = logs[0].details.clone().expect("delete_contact should record details");
+    assert_eq!(details.chars().count(), 64);
+    assert!(details.ends_with("…(truncated)"));
+}
+
 // =============================================================================
 // FOS-5.6.10: AC-5.6.10.4 - Audit logging for CRM operations from signup flow
 // =============================================================================
@@ -3613,6 +4045,8 @@ fn test_update_contact_name_too_short_rejected() {
         interest_area: None,
         notes: None,
         status: None,
+        clear_fields: None,
+        marketing_consent: None,
     };
 
     let response = pic
@@ -3668,6 +4102,8 @@ fn test_update_contact_notes_too_long_rejected() {
         interest_area: None,
         notes: Some("x".repeat(5001)), // Exceeds 5000 char limit
         status: None,
+        clear_fields: None,
+        marketing_consent: None,
     };
 
     let response = pic
@@ -3723,6 +4159,8 @@ fn test_update_contact_valid_request_succeeds() {
         interest_area: None,
         notes: Some("Updated notes".to_string()),
         status: None,
+        clear_fields: None,
+        marketing_consent: None,
     };
 
     let response = pic
@@ -3954,6 +4392,7 @@ fn test_update_deal_value_exceeds_max_rejected() {
         stage: None,
         notes: None,
         expected_close_date: None,
+        custom_stage: None,
     };
 
     let response = pic
@@ -3985,6 +4424,8 @@ fn test_create_transaction_amount_exceeds_max_rejected() {
         description: "Large transaction".to_string(),
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4016,6 +4457,8 @@ fn test_create_transaction_empty_description_rejected() {
         description: "".to_string(), // Empty description
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4047,6 +4490,8 @@ fn test_create_transaction_description_too_long_rejected() {
         description: "x".repeat(1001), // Exceeds 1000 char limit
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4074,6 +4519,8 @@ fn test_create_transaction_invalid_currency_rejected() {
         description: "Test transaction".to_string(),
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4105,6 +4552,8 @@ fn test_create_transaction_valid_request_succeeds() {
         description: "Monthly subscription".to_string(),
         reference: Some("INV-001".to_string()),
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4132,6 +4581,8 @@ fn test_create_transaction_at_max_amount_succeeds() {
         description: "Maximum amount transaction".to_string(),
         reference: None,
         date: None,
+        reverses: None,
+        receipt_hash: None,
     };
 
     let response = pic
@@ -4146,3 +4597,4714 @@ fn test_create_transaction_at_max_amount_succeeds() {
     let result: Result<Transaction, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
     assert!(result.is_ok(), "Should accept transaction at exactly max amount");
 }
+
+// ============================================================================
+// Email Normalization Tests (plus-address dedup)
+// ============================================================================
+
+#[test]
+fn test_normalize_plus_addressing_rejects_duplicate() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_normalize_plus_addressing",
+            encode_one(true).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should be able to enable normalization");
+
+    let make_request = |email: &str| CreateContactRequest {
+        user_id: None,
+        email: email.to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "create_contact",
+            encode_one(make_request("a+x@ex.com")).unwrap(),
+        )
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let contact = result.expect("First contact should be created");
+    assert_eq!(contact.email, "a+x@ex.com", "Original email should be stored");
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "create_contact",
+            encode_one(make_request("a@ex.com")).unwrap(),
+        )
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Normalized duplicate should be rejected");
+    assert!(result.unwrap_err().contains("already exists"));
+
+    // Lookup by the bare address resolves to the plus-addressed contact
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contact_by_email",
+            encode_one("a@ex.com".to_string()).unwrap(),
+        )
+        .unwrap();
+    let result: Result<Option<Contact>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(result.unwrap().map(|c| c.id), Some(contact.id));
+}
+
+// ============================================================================
+// Owner Filter Tests
+// ============================================================================
+
+#[test]
+fn test_controller_filters_contacts_by_owner() {
+    let (pic, canister_id, controller) = setup();
+    let admin = test_principal(1);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[AdminPermission::ViewOwnContacts]);
+
+    let admin_contact = create_test_contact(&pic, canister_id, admin, "owned-by-admin@example.com");
+    create_test_contact(&pic, canister_id, controller, "owned-by-controller@example.com");
+
+    let filter = ContactFilter {
+        owner_id: Some(admin),
+        ..Default::default()
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts",
+            encode_args((Some(filter.clone()), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactV2Response, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let page = result.expect("Controller should be able to filter by owner");
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].id, admin_contact.id);
+    assert_eq!(page.items[0].owner_id, Some(admin));
+
+    // Without ViewAllContacts, the owner filter is an authorization error
+    let response = pic
+        .query_call(
+            canister_id,
+            admin,
+            "get_contacts",
+            encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactV2Response, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Non-privileged admin should not filter by owner");
+}
+
+// ============================================================================
+// Batched Activity Logging Tests
+// ============================================================================
+
+#[test]
+fn test_log_activities_batch_uses_single_rate_limit_token() {
+    let (pic, canister_id, controller) = setup();
+    // Controllers are exempt from rate limiting, so use a regular admin
+    let admin = test_principal(22);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[]);
+
+    let events: Vec<(String, String, Option<String>)> = (0..10)
+        .map(|i| (format!("user-{}", i), "page_view".to_string(), None))
+        .collect();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            admin,
+            "log_activities",
+            encode_one(events).unwrap(),
+        )
+        .unwrap();
+    let result: Result<u32, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(result, Ok(10), "All 10 events should be stored");
+
+    // The batch consumed one token, so 99 single calls still fit in the window
+    for i in 0..99 {
+        let response = pic
+            .update_call(
+                canister_id,
+                admin,
+                "log_activity",
+                encode_args((format!("user-{}", i), "rate_test".to_string(), None::<String>)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        assert!(result.is_ok(), "Call {} should succeed within rate limit", i);
+    }
+
+    let response = pic
+        .update_call(
+            canister_id,
+            admin,
+            "log_activity",
+            encode_args(("user-over".to_string(), "rate_test".to_string(), None::<String>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Token budget should now be exhausted");
+}
+
+// ============================================================================
+// Creation Timestamp Override Tests (data migration)
+// ============================================================================
+
+#[test]
+fn test_created_at_override_honored_for_controller_only() {
+    let (pic, canister_id, controller) = setup();
+    let admin = test_principal(1);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[]);
+
+    let backdated: Timestamp = 1_600_000_000_000_000_000; // Sep 2020
+
+    let request = CreateContactRequestV2 {
+        email: "historical@example.com".to_string(),
+        created_at_override: Some(backdated),
+        ..Default::default()
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let contact = result.expect("Controller should create backdated contact");
+    assert_eq!(contact.created_at, backdated, "Controller override should be preserved");
+
+    let deal_request = CreateDealRequestV2 {
+        contact_id: contact.id,
+        name: "Historical deal".to_string(),
+        created_at_override: Some(backdated),
+        ..Default::default()
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(deal_request).unwrap())
+        .unwrap();
+    let result: Result<DealV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(result.expect("Controller should create backdated deal").created_at, backdated);
+
+    let request = CreateContactRequestV2 {
+        email: "admin-backdate@example.com".to_string(),
+        created_at_override: Some(backdated),
+        ..Default::default()
+    };
+    let response = pic
+        .update_call(canister_id, admin, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let contact = result.expect("Admin should still create the contact");
+    assert_ne!(contact.created_at, backdated, "Admin override should be ignored");
+}
+
+// ============================================================================
+// Signup Deal Template Tests
+// ============================================================================
+
+#[test]
+fn test_signup_deal_template_sets_default_value() {
+    let (pic, canister_id, controller) = setup();
+    let user_service_principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service_principal)).unwrap(),
+    )
+    .unwrap();
+
+    let template = SignupDealTemplate {
+        name_prefix: Some("Inbound: ".to_string()),
+        default_value: Some(5000),
+        notes: None,
+    };
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_signup_deal_template",
+            encode_one(Some(template)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should set signup deal template");
+
+    let oversized = SignupDealTemplate {
+        name_prefix: None,
+        default_value: Some(u64::MAX),
+        notes: None,
+    };
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_signup_deal_template",
+            encode_one(Some(oversized)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap_err().contains("Invalid signup deal template"));
+
+    let request = CreateContactRequest {
+        user_id: Some("user-template".to_string()),
+        email: "template@example.com".to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: Some(ContactSource::Signup),
+        notes: None,
+    };
+    let response = pic
+        .update_call(
+            canister_id,
+            user_service_principal,
+            "create_contact_from_signup",
+            encode_one(request).unwrap(),
+        )
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let contact = result.expect("Signup should succeed");
+
+    let filter = DealFilter {
+        contact_id: Some(contact.id),
+        ..Default::default()
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_deals",
+            encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedDealResponse, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let deals = result.unwrap().items;
+    assert_eq!(deals.len(), 1);
+    assert_eq!(deals[0].value, Some(5000));
+    assert_eq!(deals[0].name, format!("Inbound: Contact #{}", contact.id));
+}
+
+// ============================================================================
+// No-op Update Tests
+// ============================================================================
+
+#[test]
+fn test_update_contact_with_no_changes_is_noop() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "noop@example.com");
+
+    pic.advance_time(std::time::Duration::from_secs(5));
+    pic.tick();
+
+    let request = UpdateContactRequest {
+        id: contact.id,
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        notes: None,
+        status: None,
+        clear_fields: None,
+        marketing_consent: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "update_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let updated = result.expect("No-op update should succeed");
+    assert_eq!(updated.updated_at, contact.updated_at, "updated_at should not change");
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("update_contact".to_string()),
+                None::<String>,
+                None::<Principal>,
+                None::<u64>,
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+    let entries: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(entries.unwrap().is_empty(), "No-op update should not be audited");
+}
+
+// ============================================================================
+// Admin Permission Overview Tests
+// ============================================================================
+
+#[test]
+fn test_list_admins_with_permissions() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(1);
+    let manager = test_principal(2);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::ViewOwnContacts]);
+    add_admin_with_permissions(
+        &pic,
+        canister_id,
+        controller,
+        manager,
+        &[AdminPermission::ViewAllContacts, AdminPermission::ViewAuditLogs],
+    );
+
+    let response = pic
+        .query_call(canister_id, controller, "list_admins_with_permissions", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<Vec<(Principal, Vec<AdminPermission>)>, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    let overview = result.expect("Controller should list admins with permissions");
+
+    let perms_of = |p: Principal| overview.iter().find(|(who, _)| *who == p).map(|(_, perms)| perms.clone());
+    assert_eq!(perms_of(rep), Some(vec![AdminPermission::ViewOwnContacts]));
+    assert_eq!(
+        perms_of(manager),
+        Some(vec![AdminPermission::ViewAllContacts, AdminPermission::ViewAuditLogs])
+    );
+    assert_eq!(perms_of(controller).map(|p| p.len()), Some(14), "Controller holds every permission");
+
+    // Admins cannot see the overview
+    let response = pic
+        .query_call(canister_id, rep, "list_admins_with_permissions", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<Vec<(Principal, Vec<AdminPermission>)>, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err());
+}
+
+// ============================================================================
+// Transaction Reversal Tests
+// ============================================================================
+
+#[test]
+fn test_transaction_reversal_link_and_net_summary() {
+    let (pic, canister_id, controller) = setup();
+    let base_time = 1704067200u64; // 2024-01-01
+
+    let create = |request: CreateTransactionRequest| -> Result<Transaction, String> {
+        let response = pic
+            .update_call(canister_id, controller, "create_transaction", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    let tx = |transaction_type, amount, reverses| CreateTransactionRequest {
+        transaction_type,
+        category: TransactionCategory::Service,
+        amount,
+        currency: None,
+        description: "Consulting".to_string(),
+        reference: None,
+        date: Some(base_time + 86400),
+        reverses,
+        receipt_hash: None,
+    };
+
+    let income = create(tx(TransactionType::Income, 10000, None)).expect("Should create income");
+    create(tx(TransactionType::Income, 4000, None)).expect("Should create second income");
+
+    // A refund of income must be an expense
+    let same_type = create(tx(TransactionType::Income, 10000, Some(income.id)));
+    assert!(same_type.is_err(), "Reversal of the same type should be rejected");
+    let missing = create(tx(TransactionType::Expense, 10000, Some(9999)));
+    assert!(missing.is_err(), "Reversal of an unknown transaction should be rejected");
+
+    let refund = create(tx(TransactionType::Expense, 10000, Some(income.id))).expect("Should create refund");
+    assert_eq!(refund.reverses, Some(income.id));
+
+    let summary = |net_of_reversals: Option<bool>| -> FinancialSummary {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_financial_summary",
+                encode_args((base_time, base_time + 604800, net_of_reversals)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<FinancialSummary, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.expect("Should get financial summary")
+    };
+
+    let gross = summary(None);
+    assert_eq!(gross.total_income, 14000);
+    assert_eq!(gross.total_expenses, 10000);
+
+    let netted = summary(Some(true));
+    assert_eq!(netted.total_income, 4000, "Reversed income should be netted out");
+    assert_eq!(netted.total_expenses, 0, "Refund should not be booked as an expense");
+    assert_eq!(netted.net, 4000);
+}
+
+// ============================================================================
+// Default Feature Flag Seeding Tests
+// ============================================================================
+
+#[test]
+fn test_seed_default_feature_flags_is_idempotent() {
+    let (pic, canister_id, controller) = setup();
+
+    let seed = || -> Result<u32, String> {
+        let response = pic
+            .update_call(canister_id, controller, "seed_default_feature_flags", encode_one(()).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let created = seed().expect("Controller should seed default flags");
+    assert!(created > 0, "First seed should create the starter flags");
+
+    let response = pic
+        .query_call(canister_id, controller, "list_feature_flags", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<Vec<FeatureFlag>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let flags = result.unwrap();
+    assert_eq!(flags.len() as u32, created);
+    assert!(flags.iter().any(|f| f.key == "new_dashboard" && !f.enabled));
+    assert!(flags.iter().any(|f| f.key == "beta_reports" && !f.enabled));
+
+    assert_eq!(seed().unwrap(), 0, "Second seed should create nothing");
+
+    // Non-controllers cannot seed
+    let response = pic
+        .update_call(canister_id, non_admin_principal(), "seed_default_feature_flags", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<u32, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err());
+}
+
+// ============================================================================
+// Feature Flag Redaction Tests
+// ============================================================================
+
+#[test]
+fn test_get_feature_flag_redacts_whitelist_for_non_admins() {
+    let (pic, canister_id, controller) = setup();
+    let beta_tester = test_principal(7);
+
+    let request = SetFeatureFlagRequest {
+        key: "beta_reports".to_string(),
+        enabled: true,
+        description: Some("Internal rollout".to_string()),
+        percentage: None,
+        allowed_principals: Some(vec![beta_tester]),
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    pic.update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+        .unwrap();
+
+    let get_flag = |caller: Principal| -> FeatureFlag {
+        let response = pic
+            .query_call(canister_id, caller, "get_feature_flag", encode_one("beta_reports".to_string()).unwrap())
+            .unwrap();
+        let flag: Option<FeatureFlag> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        flag.expect("Flag should exist")
+    };
+
+    let public = get_flag(Principal::anonymous());
+    assert!(public.enabled);
+    assert!(public.allowed_principals.is_empty(), "Anonymous callers must not see the whitelist");
+    assert_eq!(public.description, None);
+
+    let full = get_flag(controller);
+    assert_eq!(full.allowed_principals, vec![beta_tester]);
+    assert_eq!(full.description, Some("Internal rollout".to_string()));
+}
+
+// ============================================================================
+// Per-Endpoint Rate Limit Tests
+// ============================================================================
+
+#[test]
+fn test_rate_limit_buckets_are_per_endpoint() {
+    let (pic, canister_id, controller) = setup();
+    let user_service_principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service_principal)).unwrap(),
+    )
+    .unwrap();
+
+    // Authorized canisters get a higher limit; bring it down to the endpoint default
+    pic.update_call(
+        canister_id,
+        controller,
+        "set_authorized_canister_rate_limit",
+        encode_one(100u32).unwrap(),
+    )
+    .unwrap();
+
+    // Exhaust the log_activity bucket for the user-service principal
+    for i in 0..100 {
+        let response = pic
+            .update_call(
+                canister_id,
+                user_service_principal,
+                "log_activity",
+                encode_args((format!("user-{}", i), "rate_test".to_string(), None::<String>)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        assert!(result.is_ok(), "Call {} should succeed within rate limit", i);
+    }
+    let response = pic
+        .update_call(
+            canister_id,
+            user_service_principal,
+            "log_activity",
+            encode_args(("user-over".to_string(), "rate_test".to_string(), None::<String>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "log_activity bucket should be exhausted");
+
+    // Signups draw from their own bucket and are unaffected
+    let request = CreateContactRequest {
+        user_id: Some("user-rate".to_string()),
+        email: "rate@example.com".to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: Some(ContactSource::Signup),
+        notes: None,
+    };
+    let response = pic
+        .update_call(
+            canister_id,
+            user_service_principal,
+            "create_contact_from_signup",
+            encode_one(request).unwrap(),
+        )
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Signup should not be blocked by the log_activity bucket");
+}
+
+// ============================================================================
+// Deal Win Rate Tests
+// ============================================================================
+
+#[test]
+fn test_get_win_rate() {
+    let (pic, canister_id, controller) = setup();
+
+    let get_win_rate = || -> WinRateReport {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_win_rate",
+                encode_args((None::<Timestamp>, None::<Timestamp>)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<WinRateReport, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.expect("Admin should get win rate")
+    };
+
+    // No closed deals yet
+    assert_eq!(get_win_rate().overall.win_rate, None);
+
+    let contact = create_test_contact(&pic, canister_id, controller, "winrate@example.com");
+    let stages = [
+        DealStage::ClosedWon,
+        DealStage::ClosedWon,
+        DealStage::ClosedWon,
+        DealStage::ClosedLost,
+        DealStage::Negotiation,
+    ];
+    for (i, stage) in stages.into_iter().enumerate() {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: format!("Deal {}", i),
+            value: Some(1000),
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+        pic.update_call(
+            canister_id,
+            controller,
+            "update_deal_stage",
+            encode_args((deal.id, stage)).unwrap(),
+        )
+        .unwrap();
+    }
+
+    let report = get_win_rate();
+    assert_eq!(report.overall.won, 3);
+    assert_eq!(report.overall.lost, 1);
+    assert_eq!(report.overall.win_rate, Some(0.75), "3 won of 4 closed should be 75%");
+    assert_eq!(report.by_owner.len(), 1);
+    assert_eq!(report.by_owner[0].0, Some(controller));
+    assert_eq!(report.by_owner[0].1.win_rate, Some(0.75));
+}
+
+// ============================================================================
+// Signup Contact Enrichment Tests
+// ============================================================================
+
+#[test]
+fn test_signup_enriches_existing_contact() {
+    let (pic, canister_id, controller) = setup();
+    let user_service_principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service_principal)).unwrap(),
+    )
+    .unwrap();
+
+    // Marketing adds the contact manually before the user signs up
+    let request = CreateContactRequest {
+        user_id: None,
+        email: "early@example.com".to_string(),
+        name: Some("Early Bird".to_string()),
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: Some(ContactSource::Other),
+        notes: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let existing: Contact = decode_one::<Result<Contact, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let signup = CreateContactRequest {
+        user_id: Some("user-early".to_string()),
+        email: "early@example.com".to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: Some(ContactSource::Signup),
+        notes: None,
+    };
+    let signup_contact = |request: CreateContactRequest| -> Contact {
+        let response = pic
+            .update_call(
+                canister_id,
+                user_service_principal,
+                "create_contact_from_signup",
+                encode_one(request).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<Contact, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Signup should succeed for an existing email")
+    };
+
+    let enriched = signup_contact(signup.clone());
+    assert_eq!(enriched.id, existing.id, "Signup should link to the existing contact");
+    assert_eq!(enriched.user_id, Some("user-early".to_string()));
+    assert_eq!(enriched.source, ContactSource::Signup);
+    assert_eq!(enriched.name, Some("Early Bird".to_string()));
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let contacts: PaginatedContactResponse =
+        decode_one::<Result<PaginatedContactResponse, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+    assert_eq!(contacts.total, 1, "No duplicate contact should be created");
+
+    // A repeated signup does not create a second deal
+    signup_contact(signup);
+    let filter = DealFilter {
+        contact_id: Some(existing.id),
+        stage: None,
+        stages: None,
+        owner_id: None,
+        min_value: None,
+        max_value: None,
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_deals",
+            encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let deals: PaginatedDealResponse =
+        decode_one::<Result<PaginatedDealResponse, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+    assert_eq!(deals.total, 1, "Only one auto-created deal per contact");
+}
+
+// ============================================================================
+// Pagination Clamping Tests
+// ============================================================================
+
+#[test]
+fn test_pagination_limit_is_clamped() {
+    let (pic, canister_id, controller) = setup();
+    create_test_contact(&pic, canister_id, controller, "page@example.com");
+
+    let pagination = PaginationParams {
+        offset: Some(0),
+        limit: Some(100_000),
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, Some(pagination))).unwrap(),
+        )
+        .unwrap();
+    let page: PaginatedContactResponse =
+        decode_one::<Result<PaginatedContactResponse, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+
+    assert_eq!(page.limit, 200, "Limit should be clamped to MAX_PAGE_SIZE");
+    assert_eq!(page.total, 1);
+}
+
+// ============================================================================
+// Deal Close Timestamp Tests
+// ============================================================================
+
+#[test]
+fn test_deal_closed_at_survives_later_edits() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "close@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Closing deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(deal.closed_at, None);
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "update_deal_stage",
+            encode_args((deal.id, DealStage::ClosedWon)).unwrap(),
+        )
+        .unwrap();
+    let closed: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    let closed_at = closed.closed_at.expect("Closing should stamp closed_at");
+
+    pic.advance_time(std::time::Duration::from_secs(3600));
+
+    let update = UpdateDealRequest {
+        id: deal.id,
+        name: None,
+        value: None,
+        stage: None,
+        notes: Some("Follow-up note".to_string()),
+        expected_close_date: None,
+        custom_stage: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "update_deal", encode_one(update).unwrap())
+        .unwrap();
+    let edited: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(edited.closed_at, Some(closed_at), "Note edit must not move closed_at");
+    assert!(edited.updated_at > closed_at, "updated_at should reflect the note edit");
+}
+
+// ============================================================================
+// Maintenance Mode Tests
+// ============================================================================
+
+#[test]
+fn test_maintenance_mode_blocks_admin_writes_only() {
+    let (pic, canister_id, controller) = setup();
+    let admin = test_principal(3);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[AdminPermission::ViewOwnContacts]);
+
+    let set_maintenance = |enabled: bool| {
+        let response = pic
+            .update_call(canister_id, controller, "set_maintenance_mode", encode_one(enabled).unwrap())
+            .unwrap();
+        let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.expect("Controller should toggle maintenance mode");
+    };
+    let create_contact_as_admin = |email: &str| -> Result<Contact, String> {
+        let request = CreateContactRequest {
+            user_id: None,
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, admin, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    set_maintenance(true);
+
+    let result = create_contact_as_admin("blocked@example.com");
+    assert_eq!(result.unwrap_err(), "Canister in maintenance mode");
+
+    // Controller endpoints keep working
+    let response = pic
+        .update_call(canister_id, controller, "add_admin", encode_one(test_principal(4)).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller add_admin should work in maintenance mode");
+
+    // Queries keep working
+    let response = pic
+        .query_call(
+            canister_id,
+            admin,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactResponse, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    set_maintenance(false);
+    assert!(create_contact_as_admin("allowed@example.com").is_ok());
+}
+
+// ============================================================================
+// Transaction Date Ordering Tests
+// ============================================================================
+
+#[test]
+fn test_get_transactions_sorted_by_date() {
+    let (pic, canister_id, controller) = setup();
+    let base_time = 1704067200u64; // 2024-01-01
+
+    // Created in id order, dated out of order
+    for day in [3u64, 1, 2, 1] {
+        let request = CreateTransactionRequest {
+            transaction_type: TransactionType::Income,
+            category: TransactionCategory::Other,
+            amount: 100 * day,
+            currency: None,
+            description: format!("Day {}", day),
+            reference: None,
+            date: Some(base_time + day * 86400),
+            reverses: None,
+            receipt_hash: None,
+        };
+        pic.update_call(canister_id, controller, "create_transaction", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let list_sorted = |sort_by_date: bool, descending: bool| -> Vec<Transaction> {
+        let filter = TransactionFilter {
+            sort_by_date: Some(sort_by_date),
+            descending: Some(descending),
+            ..Default::default()
+        };
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_transactions",
+                encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<PaginatedTransactionResponse, String> =
+            decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.unwrap().items
+    };
+    let list = |descending: bool| list_sorted(true, descending);
+
+    let ascending = list(false);
+    let order: Vec<(Timestamp, TransactionId)> = ascending.iter().map(|t| (t.date, t.id)).collect();
+    let mut expected = order.clone();
+    expected.sort();
+    assert_eq!(order, expected, "Transactions should be in date order, ties by id");
+    assert_eq!(ascending[0].id, 2, "Earliest date with the lowest id comes first");
+
+    let descending: Vec<TransactionId> = list(true).iter().map(|t| t.id).collect();
+    let mut reversed: Vec<TransactionId> = ascending.iter().map(|t| t.id).collect();
+    reversed.reverse();
+    assert_eq!(descending, reversed);
+
+    // Without sort_by_date the listing stays in id order
+    let ids: Vec<TransactionId> = list_sorted(false, true).iter().map(|t| t.id).collect();
+    let mut sorted_ids = ids.clone();
+    sorted_ids.sort();
+    assert_eq!(ids, sorted_ids, "descending alone should not reorder");
+}
+
+// ============================================================================
+// Stale Contact Follow-up Tests
+// ============================================================================
+
+#[test]
+fn test_get_stale_contacts_excludes_contacts_with_open_deals() {
+    let (pic, canister_id, controller) = setup();
+    let dormant = create_test_contact(&pic, canister_id, controller, "dormant@example.com");
+    let engaged = create_test_contact(&pic, canister_id, controller, "engaged@example.com");
+
+    pic.advance_time(std::time::Duration::from_secs(2 * 86400));
+
+    let request = CreateDealRequest {
+        contact_id: engaged.id,
+        name: "Open deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    pic.update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_stale_contacts",
+            encode_args((86400u64, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactResponse, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let page = result.expect("Admin should list stale contacts");
+
+    let ids: Vec<ContactId> = page.items.iter().map(|c| c.id).collect();
+    assert_eq!(ids, vec![dormant.id], "Only the dormant contact needs follow-up");
+}
+
+// ============================================================================
+// Authorized Canister Role Validation Tests
+// ============================================================================
+
+#[test]
+fn test_register_authorized_canister_rejects_unknown_role() {
+    let (pic, canister_id, controller) = setup();
+    let service = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    let register = |role: &str| -> Result<(), String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "register_authorized_canister",
+                encode_args((role.to_string(), service)).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let err = register("user-serivce").unwrap_err();
+    assert!(err.contains("Unknown canister role"), "Unexpected error: {}", err);
+    assert!(err.contains("user-service"), "Error should list the valid roles");
+
+    assert!(register("user-service").is_ok());
+
+    // Controllers can extend the allowlist
+    assert!(register("billing-service").is_err());
+    pic.update_call(canister_id, controller, "add_canister_role", encode_one("billing-service".to_string()).unwrap())
+        .unwrap();
+    assert!(register("billing-service").is_ok());
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("add_canister_role".to_string()),
+                None::<String>,
+                None::<Principal>,
+                Some(10u64),
+            )).unwrap(),
+        )
+        .unwrap();
+    let entries: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let logs = entries.unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].target_type, "canister_role");
+    assert_eq!(logs[0].target_id, "billing-service");
+}
+
+// ============================================================================
+// Financial Summary Count & Average Tests
+// ============================================================================
+
+#[test]
+fn test_financial_summary_counts_and_average() {
+    let (pic, canister_id, controller) = setup();
+    let base_time = 1704067200u64; // 2024-01-01
+
+    let summary = |from: Timestamp, to: Timestamp| -> FinancialSummary {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_financial_summary",
+                encode_args((from, to, None::<bool>)).unwrap(),
+            )
+            .unwrap();
+        let result: Result<FinancialSummary, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.expect("Should get financial summary")
+    };
+
+    // Empty window: average guarded against zero transactions
+    let empty = summary(base_time, base_time + 604800);
+    assert_eq!(empty.income_count + empty.expense_count, 0);
+    assert_eq!(empty.avg_transaction_amount, 0);
+
+    let transactions = [
+        (TransactionType::Income, 3000, base_time + 86400),
+        (TransactionType::Income, 5000, base_time + 86400),
+        (TransactionType::Expense, 1000, base_time + 86400),
+        // Outside the one-week window
+        (TransactionType::Income, 99000, base_time + 30 * 86400),
+    ];
+    for (transaction_type, amount, date) in transactions {
+        let request = CreateTransactionRequest {
+            transaction_type,
+            category: TransactionCategory::Other,
+            amount,
+            currency: None,
+            description: "Count test".to_string(),
+            reference: None,
+            date: Some(date),
+            reverses: None,
+            receipt_hash: None,
+        };
+        pic.update_call(canister_id, controller, "create_transaction", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let week = summary(base_time, base_time + 604800);
+    assert_eq!(week.income_count, 2);
+    assert_eq!(week.expense_count, 1);
+    assert_eq!(week.avg_transaction_amount, 3000, "(3000 + 5000 + 1000) / 3");
+}
+
+// ============================================================================
+// Deal Comment Tests
+// ============================================================================
+
+#[test]
+fn test_add_deal_comments_in_order() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(5);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::EditAllDeals]);
+
+    let contact = create_test_contact(&pic, canister_id, controller, "comments@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Commented deal".to_string(),
+        value: None,
+        notes: Some("Summary".to_string()),
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let comment = |caller: Principal, text: &str| -> Result<DealV2, String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                caller,
+                "add_deal_comment",
+                encode_args((deal.id, text.to_string())).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    comment(controller, "Intro call went well").expect("Controller should comment");
+    let updated = comment(rep, "Sent proposal").expect("Rep with EditAllDeals should comment");
+
+    let authored: Vec<(Principal, &str)> = updated
+        .comments
+        .iter()
+        .map(|c| (c.author, c.text.as_str()))
+        .collect();
+    assert_eq!(authored, vec![(controller, "Intro call went well"), (rep, "Sent proposal")]);
+    assert!(updated.comments[0].timestamp <= updated.comments[1].timestamp);
+    assert_eq!(updated.notes, Some("Summary".to_string()), "Notes are kept as the summary");
+
+    assert!(comment(controller, "   ").is_err(), "Empty comments are rejected");
+}
+
+// ============================================================================
+// Contact Search Match Info Tests
+// ============================================================================
+
+#[test]
+fn test_get_contact_matches_reports_matched_fields() {
+    let (pic, canister_id, controller) = setup();
+
+    let request = CreateContactRequest {
+        user_id: None,
+        email: "jane@example.com".to_string(),
+        name: Some("Jane Doe".to_string()),
+        company: Some("Globex Corporation".to_string()),
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+    pic.update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+
+    let filter = ContactFilter {
+        search: Some("globex".to_string()),
+        with_match_info: Some(true),
+        ..Default::default()
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contact_matches",
+            encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactMatchResponse, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    let page = result.expect("Admin should search contacts");
+
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].contact.email, "jane@example.com");
+    assert_eq!(page.items[0].matched_fields, vec!["company".to_string()]);
+}
+
+// ============================================================================
+// Rate Limit Exemption Tests
+// ============================================================================
+
+#[test]
+fn test_controller_exempt_from_rate_limit() {
+    let (pic, canister_id, controller) = setup();
+    let admin = test_principal(23);
+    add_admin_with_permissions(&pic, canister_id, controller, admin, &[]);
+
+    let log = |caller: Principal, i: u32| -> Result<(), String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                caller,
+                "log_activity",
+                encode_args((format!("user-{}", i), "rate_test".to_string(), None::<String>)).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    for i in 0..101 {
+        assert!(log(controller, i).is_ok(), "Controller call {} should not be rate limited", i);
+    }
+
+    for i in 0..100 {
+        assert!(log(admin, i).is_ok(), "Admin call {} should succeed within rate limit", i);
+    }
+    assert!(log(admin, 100).is_err(), "Admin should be held to the base limit");
+}
+
+// ============================================================================
+// Parsed Audit Log Tests
+// ============================================================================
+
+#[test]
+fn test_get_audit_log_parsed_feature_flag_details() {
+    let (pic, canister_id, controller) = setup();
+
+    for enabled in [false, true] {
+        let request = SetFeatureFlagRequest {
+            key: "audit_parsed".to_string(),
+            enabled,
+            description: None,
+            percentage: Some(10),
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
+        };
+        pic.update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log_parsed",
+            encode_args((
+                Some("set_feature_flag".to_string()),
+                None::<String>,
+                None::<Principal>,
+                Some(10u64),
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+    let result: Result<Vec<ParsedAuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let entries = result.expect("Controller should read parsed audit log");
+    assert_eq!(entries.len(), 2);
+
+    // Newest first: the second update records the first as its old value
+    let keys: Vec<&str> = entries[0].details.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(keys, vec!["new", "old"]);
+
+    let value_of = |key: &str| -> serde_json::Value {
+        let text = &entries[0].details.iter().find(|(k, _)| k == key).unwrap().1;
+        serde_json::from_str(text).expect("Detail values should be valid JSON")
+    };
+    assert_eq!(value_of("new")["enabled"], serde_json::json!(true));
+    assert_eq!(value_of("old")["enabled"], serde_json::json!(false));
+    assert_eq!(value_of("old")["percentage"], serde_json::json!(10));
+}
+
+// ============================================================================
+// Cycle Balance Health Tests
+// ============================================================================
+
+#[test]
+fn test_cycle_balance_reporting() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .query_call(canister_id, controller, "get_cycle_balance", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let balance = result.expect("Admin should read cycle balance");
+    assert!(balance > 0, "Balance should be non-zero after add_cycles");
+    assert!(balance <= 2_000_000_000_000, "Balance cannot exceed the cycles added");
+
+    // A threshold above the balance surfaces the warning
+    pic.update_call(
+        canister_id,
+        controller,
+        "set_low_cycles_threshold",
+        encode_one(10_000_000_000_000u64).unwrap(),
+    )
+    .unwrap();
+    let response = pic
+        .query_call(canister_id, controller, "health_detailed", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<HealthDetailed, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let health = result.expect("Admin should read detailed health");
+    assert!(health.cycle_balance > 0);
+    assert!(health.low_cycles);
+
+    // Non-admins cannot read the balance
+    let response = pic
+        .query_call(canister_id, non_admin_principal(), "get_cycle_balance", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err());
+}
+
+// ============================================================================
+// Cascade Deletion Audit Tests
+// ============================================================================
+
+#[test]
+fn test_delete_contact_audits_cascaded_deals() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "cascade@example.com");
+
+    let mut deal_ids = Vec::new();
+    for name in ["First deal", "Second deal"] {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: name.to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+        deal_ids.push(deal.id.to_string());
+    }
+
+    let response = pic
+        .update_call(canister_id, controller, "delete_contact", encode_one(contact.id).unwrap())
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should delete contact");
+
+    let audit = |action: &str| -> Vec<AuditLogEntry> {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_audit_log",
+                encode_args((Some(action.to_string()), None::<String>, None::<Principal>, Some(10u64))).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    let contact_entries = audit("delete_contact");
+    assert_eq!(contact_entries.len(), 1);
+    assert_eq!(contact_entries[0].target_id, contact.id.to_string());
+
+    let mut cascaded: Vec<String> = audit("cascade_delete_deal").into_iter().map(|e| e.target_id).collect();
+    cascaded.sort();
+    deal_ids.sort();
+    assert_eq!(cascaded, deal_ids, "Each cascaded deal deletion should be audited");
+}
+
+// ============================================================================
+// Feature Flag Exclusion Tests
+// ============================================================================
+
+#[test]
+fn test_feature_flag_excluded_principal_overrides_rollout() {
+    let (pic, canister_id, controller) = setup();
+    let excluded = test_principal(30);
+
+    let request = SetFeatureFlagRequest {
+        key: "vip_safe_experiment".to_string(),
+        enabled: true,
+        description: None,
+        percentage: Some(100),
+        allowed_principals: None,
+        excluded_principals: Some(vec![excluded]),
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    let enabled_for = |caller: Principal| -> bool {
+        let response = pic
+            .query_call(
+                canister_id,
+                caller,
+                "is_feature_enabled",
+                encode_one("vip_safe_experiment".to_string()).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert!(!enabled_for(excluded), "Excluded principal must be disabled even at 100% rollout");
+    assert!(enabled_for(Principal::anonymous()), "Other principals stay enabled");
+    assert!(enabled_for(test_principal(31)), "Other principals stay enabled");
+}
+
+// ============================================================================
+// Combined Contact + Deal Creation Tests
+// ============================================================================
+
+#[test]
+fn test_create_deal_with_new_contact_rolls_back_on_invalid_deal() {
+    let (pic, canister_id, controller) = setup();
+
+    let contact = CreateContactRequest {
+        user_id: None,
+        email: "lead@example.com".to_string(),
+        name: Some("New Lead".to_string()),
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+
+    // Deal name below the 3-char minimum
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "create_deal_with_new_contact",
+            encode_args((contact.clone(), "ab".to_string(), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(Contact, Deal), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Invalid deal name should be rejected");
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedContactResponse, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(result.unwrap().total, 0, "No contact should be left behind");
+
+    // The same lead succeeds with a valid deal name
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "create_deal_with_new_contact",
+            encode_args((contact, "First order".to_string(), Some(50_000u64))).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(Contact, Deal), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let (created, deal) = result.expect("Valid lead should be created");
+    assert_eq!(deal.contact_id, created.id);
+    assert_eq!(deal.value, Some(50_000));
+}
+
+// ============================================================================
+// Contact Field Clearing Tests
+// ============================================================================
+
+#[test]
+fn test_update_contact_clear_fields() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "clearme@example.com");
+
+    let update = |request: UpdateContactRequest| -> ContactV2 {
+        let response = pic
+            .update_call(canister_id, controller, "update_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Update should succeed")
+    };
+
+    let updated = update(UpdateContactRequest {
+        id: contact.id,
+        name: None,
+        company: Some("Acme Corp".to_string()),
+        job_title: None,
+        interest_area: None,
+        notes: None,
+        status: None,
+        clear_fields: None,
+        marketing_consent: None,
+    });
+    assert_eq!(updated.company, Some("Acme Corp".to_string()));
+
+    let cleared = update(UpdateContactRequest {
+        id: contact.id,
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        notes: None,
+        status: None,
+        clear_fields: Some(vec![ContactField::Company]),
+        marketing_consent: None,
+    });
+    assert_eq!(cleared.company, None, "Company should be cleared");
+}
+
+// ============================================================================
+// Deal Value Range Filter Tests
+// ============================================================================
+
+#[test]
+fn test_get_deals_value_range_filter() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "values@example.com");
+
+    // Values in cents: $1k, $50k, $75k, $200k, and one without a value
+    for (name, value) in [
+        ("Small deal", Some(100_000u64)),
+        ("Threshold deal", Some(5_000_000)),
+        ("Mid deal", Some(7_500_000)),
+        ("Large deal", Some(20_000_000)),
+        ("Unpriced deal", None),
+    ] {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: name.to_string(),
+            value,
+            notes: None,
+            expected_close_date: None,
+        };
+        pic.update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let query = |min_value: Option<u64>, max_value: Option<u64>| -> Vec<String> {
+        let filter = DealFilter {
+            min_value,
+            max_value,
+            ..Default::default()
+        };
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_deals",
+                encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+            )
+            .unwrap();
+        let page: PaginatedDealResponse = decode_one::<Result<PaginatedDealResponse, String>>(
+            &unwrap_wasm_result(response),
+        )
+        .unwrap()
+        .unwrap();
+        let mut names: Vec<String> = page.items.into_iter().map(|d| d.name).collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(query(Some(5_000_000), None), vec!["Large deal", "Mid deal", "Threshold deal"]);
+    assert_eq!(query(Some(5_000_000), Some(10_000_000)), vec!["Mid deal", "Threshold deal"]);
+    assert_eq!(query(None, Some(100_000)), vec!["Small deal", "Unpriced deal"]);
+}
+
+#[test]
+fn test_get_deals_stages_filter_matches_any() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "stages@example.com");
+
+    for (name, stage) in [
+        ("Lead deal", None),
+        ("Proposal deal", Some(DealStage::Proposal)),
+        ("Negotiation deal", Some(DealStage::Negotiation)),
+    ] {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: name.to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+        if let Some(stage) = stage {
+            pic.update_call(
+                canister_id,
+                controller,
+                "update_deal_stage",
+                encode_args((deal.id, stage)).unwrap(),
+            )
+            .unwrap();
+        }
+    }
+
+    let query = |filter: DealFilter| -> Vec<String> {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_deals",
+                encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+            )
+            .unwrap();
+        let page: PaginatedDealResponse = decode_one::<Result<PaginatedDealResponse, String>>(
+            &unwrap_wasm_result(response),
+        )
+        .unwrap()
+        .unwrap();
+        let mut names: Vec<String> = page.items.into_iter().map(|d| d.name).collect();
+        names.sort();
+        names
+    };
+
+    let late_stage = Some(vec![DealStage::Proposal, DealStage::Negotiation]);
+    assert_eq!(
+        query(DealFilter { stages: late_stage.clone(), ..Default::default() }),
+        vec!["Negotiation deal", "Proposal deal"]
+    );
+
+    // Still ANDed with the single-stage field
+    assert_eq!(
+        query(DealFilter {
+            stage: Some(DealStage::Proposal),
+            stages: late_stage,
+            ..Default::default()
+        }),
+        vec!["Proposal deal"]
+    );
+}
+
+// ============================================================================
+// Single-Record Row-Level Security Tests
+// ============================================================================
+
+#[test]
+fn test_get_contact_and_deal_apply_row_level_security() {
+    let (pic, canister_id, controller) = setup();
+    let own_viewer = test_principal(40);
+    add_admin_with_permissions(
+        &pic,
+        canister_id,
+        controller,
+        own_viewer,
+        &[AdminPermission::ViewOwnContacts, AdminPermission::ViewOwnDeals],
+    );
+
+    let own_contact = create_test_contact(&pic, canister_id, own_viewer, "mine@example.com");
+    let other_contact = create_test_contact(&pic, canister_id, controller, "theirs@example.com");
+
+    let get_contact = |id: ContactId| -> Option<ContactV2> {
+        let response = pic
+            .query_call(canister_id, own_viewer, "get_contact", encode_one(id).unwrap())
+            .unwrap();
+        decode_one::<Result<Option<ContactV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+    assert!(get_contact(own_contact.id).is_some(), "Owner should see own contact");
+    assert!(get_contact(other_contact.id).is_none(), "ViewOwnContacts must not read another admin's contact");
+
+    let request = CreateDealRequest {
+        contact_id: other_contact.id,
+        name: "Controller deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let response = pic
+        .query_call(canister_id, own_viewer, "get_deal", encode_one(deal.id).unwrap())
+        .unwrap();
+    let result: Result<Option<Deal>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap().is_none(), "ViewOwnDeals must not read another admin's deal");
+}
+
+#[test]
+fn test_rep_cannot_read_managers_deal_by_id() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(41);
+    let manager = test_principal(42);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::ViewOwnDeals]);
+    add_admin_with_permissions(&pic, canister_id, controller, manager, &[AdminPermission::ViewAllDeals]);
+
+    let contact = create_test_contact(&pic, canister_id, manager, "managed@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Manager deal".to_string(),
+        value: Some(1_000_000),
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, manager, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let get_deal = |caller: Principal| -> Option<Deal> {
+        let response = pic
+            .query_call(canister_id, caller, "get_deal", encode_one(deal.id).unwrap())
+            .unwrap();
+        decode_one::<Result<Option<Deal>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    assert!(get_deal(rep).is_none(), "Rep must not read the manager's deal by id");
+    assert!(get_deal(manager).is_some(), "ViewAllDeals holder sees the deal");
+    assert!(get_deal(controller).is_some(), "Controller sees the deal");
+}
+
+#[test]
+fn test_rep_cannot_look_up_another_admins_contact_by_email() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(43);
+    let manager = test_principal(44);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::ViewOwnContacts]);
+    add_admin_with_permissions(&pic, canister_id, controller, manager, &[AdminPermission::ViewAllContacts]);
+
+    let contact = create_test_contact(&pic, canister_id, controller, "vip@example.com");
+
+    let lookup = |caller: Principal| -> Option<ContactV2> {
+        let response = pic
+            .query_call(canister_id, caller, "get_contact_by_email", encode_one("vip@example.com".to_string()).unwrap())
+            .unwrap();
+        decode_one::<Result<Option<ContactV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    assert!(lookup(rep).is_none(), "ViewOwnContacts must not resolve another admin's email");
+    assert_eq!(lookup(manager).map(|c| c.id), Some(contact.id), "ViewAllContacts holder gets the contact");
+}
+
+// ============================================================================
+// Audit PII Redaction Tests
+// ============================================================================
+
+#[test]
+fn test_audit_pii_redaction_masks_deleted_contact_email() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .update_call(canister_id, controller, "set_redact_pii_in_audit", encode_one(true).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should enable redaction");
+
+    let contact = create_test_contact(&pic, canister_id, controller, "private.person@example.com");
+    let response = pic
+        .update_call(canister_id, controller, "delete_contact", encode_one(contact.id).unwrap())
+        .unwrap();
+    let result: Result<Contact, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((Some("delete_contact".to_string()), None::<String>, None::<Principal>, Some(1u64))).unwrap(),
+        )
+        .unwrap();
+    let entries: Vec<AuditLogEntry> = decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    let details = entries[0].details.clone().expect("delete_contact records details");
+    assert!(!details.contains("private.person@example.com"), "Email must be redacted: {}", details);
+}
+
+// ============================================================================
+// Contact User Linkage Tests
+// ============================================================================
+
+#[test]
+fn test_get_contact_by_user_id() {
+    let (pic, canister_id, controller) = setup();
+    let auth_service = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("auth-service".to_string(), auth_service)).unwrap(),
+    )
+    .unwrap();
+
+    let request = CreateContactRequest {
+        user_id: Some("user-123".to_string()),
+        email: "linked@example.com".to_string(),
+        name: None,
+        company: None,
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let contact: Contact = decode_one::<Result<Contact, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let lookup = |caller: Principal, user_id: &str| -> Result<Option<Contact>, String> {
+        let response = pic
+            .query_call(canister_id, caller, "get_contact_by_user_id", encode_one(user_id.to_string()).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let resolved = lookup(auth_service, "user-123").expect("auth-service may resolve users");
+    assert_eq!(resolved.map(|c| c.id), Some(contact.id));
+    assert!(lookup(auth_service, "unknown-user").unwrap().is_none());
+
+    let err = lookup(non_admin_principal(), "user-123").unwrap_err();
+    assert!(err.contains("Unauthorized"), "Unexpected error: {}", err);
+}
+
+// ============================================================================
+// Protected Feature Flag Tests
+// ============================================================================
+
+#[test]
+fn test_protected_flag_only_modifiable_by_controller() {
+    let (pic, canister_id, controller) = setup();
+    let flag_admin = test_principal(50);
+    add_admin_with_permissions(&pic, canister_id, controller, flag_admin, &[AdminPermission::ManageFeatureFlags]);
+
+    let set_flag = |caller: Principal, enabled: bool| -> Result<(), String> {
+        let request = SetFeatureFlagRequest {
+            key: "kill_switch".to_string(),
+            enabled,
+            description: None,
+            percentage: None,
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
+        };
+        let response = pic
+            .update_call(canister_id, caller, "set_feature_flag", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert!(set_flag(controller, false).is_ok());
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_protected_flag_keys",
+            encode_one(vec!["kill_switch".to_string()]).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok());
+
+    let err = set_flag(flag_admin, true).unwrap_err();
+    assert!(err.contains("protected"), "Unexpected error: {}", err);
+
+    let response = pic
+        .update_call(canister_id, flag_admin, "delete_feature_flag", encode_one("kill_switch".to_string()).unwrap())
+        .unwrap();
+    let result: Result<FeatureFlag, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap_err().contains("protected"));
+
+    assert!(set_flag(controller, true).is_ok(), "Controller can still change a protected flag");
+}
+
+// ============================================================================
+// Activity Funnel Tests
+// ============================================================================
+
+#[test]
+fn test_get_activity_funnel_drop_off() {
+    let (pic, canister_id, controller) = setup();
+
+    // alice and bob sign up then capture; carol only signs up; dave captures without signing up
+    let events: Vec<(String, String, Option<String>)> = [
+        ("alice", "signup"),
+        ("bob", "signup"),
+        ("carol", "signup"),
+        ("dave", "first_capture"),
+        ("alice", "first_capture"),
+        ("bob", "first_capture"),
+    ]
+    .into_iter()
+    .map(|(user, action)| (user.to_string(), action.to_string(), None))
+    .collect();
+    for event in events {
+        pic.update_call(canister_id, controller, "log_activity", encode_args(event).unwrap())
+            .unwrap();
+    }
+
+    let steps = vec!["signup".to_string(), "first_capture".to_string()];
+    let response = pic
+        .query_call(canister_id, controller, "get_activity_funnel", encode_args((steps, 3600u64)).unwrap())
+        .unwrap();
+    let funnel: Result<Vec<(String, u64)>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+
+    assert_eq!(
+        funnel.unwrap(),
+        vec![("signup".to_string(), 3), ("first_capture".to_string(), 2)]
+    );
+}
+
+// ============================================================================
+// Owner Assignment Tests
+// ============================================================================
+
+#[test]
+fn test_controller_assigns_contact_owner() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(60);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::ViewOwnContacts]);
+
+    let request = CreateContactRequestV2 {
+        email: "assigned@example.com".to_string(),
+        owner_id: Some(rep),
+        ..Default::default()
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let contact = result.expect("Controller should create an assigned contact");
+    assert_eq!(contact.owner_id, Some(rep));
+
+    let response = pic
+        .query_call(
+            canister_id,
+            rep,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let page: PaginatedContactResponse = decode_one::<Result<PaginatedContactResponse, String>>(
+        &unwrap_wasm_result(response),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(page.items.iter().map(|c| c.id).collect::<Vec<_>>(), vec![contact.id]);
+
+    // The assignee must be an admin
+    let request = CreateContactRequestV2 {
+        email: "unassignable@example.com".to_string(),
+        owner_id: Some(test_principal(61)),
+        ..Default::default()
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap_err().contains("not an admin"));
+}
+
+// ============================================================================
+// Audit Purge Tests
+// ============================================================================
+
+#[test]
+fn test_purge_audit_entries_before() {
+    let (pic, canister_id, controller) = setup();
+
+    create_test_contact(&pic, canister_id, controller, "old@example.com");
+    pic.advance_time(std::time::Duration::from_secs(3600));
+    let recent = create_test_contact(&pic, canister_id, controller, "recent@example.com");
+
+    let audit_log = || -> Vec<AuditLogEntry> {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_audit_log",
+                encode_args((None::<String>, None::<String>, None::<Principal>, None::<u64>)).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    let boundary = audit_log()
+        .iter()
+        .find(|e| e.action == "create_contact" && e.target_id == recent.id.to_string())
+        .map(|e| e.timestamp)
+        .expect("Recent contact should be audited");
+
+    let response = pic
+        .update_call(canister_id, controller, "purge_audit_entries_before", encode_one(boundary).unwrap())
+        .unwrap();
+    let removed: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(removed.unwrap(), 1, "Only the older create_contact entry is purged");
+
+    let remaining = audit_log();
+    assert!(remaining.iter().all(|e| e.timestamp >= boundary));
+    assert!(remaining.iter().any(|e| e.target_id == recent.id.to_string()));
+    assert!(remaining.iter().any(|e| e.action == "purge_audit_entries"), "Purge is itself audited");
+}
+
+// ============================================================================
+// Analytics Service Authorization Tests
+// ============================================================================
+
+#[test]
+fn test_analytics_canister_can_record_metrics() {
+    let (pic, canister_id, controller) = setup();
+    let analytics = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "register_authorized_canister",
+            encode_args(("analytics-service".to_string(), analytics)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "analytics-service should be a known role");
+
+    let snapshot = MetricsSnapshot {
+        total_users: 42,
+        active_users_24h: 5,
+        active_users_7d: 12,
+        active_users_30d: 30,
+        total_captures: 100,
+        total_sprints: 4,
+        total_workspaces: 2,
+        timestamp: 1704067200,
+    };
+    let response = pic
+        .update_call(canister_id, analytics, "record_metrics", encode_one(snapshot).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Analytics canister should record metrics without being an admin");
+
+    let response = pic
+        .query_call(canister_id, controller, "get_latest_metrics", encode_one(()).unwrap())
+        .unwrap();
+    let latest: Result<Option<MetricsSnapshot>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(latest.unwrap().map(|m| m.total_users), Some(42));
+}
+
+// ============================================================================
+// Data Quality Warning Tests
+// ============================================================================
+
+#[test]
+fn test_deal_warnings_flag_missing_close_date() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "warnings@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Undated deal".to_string(),
+        value: Some(10_000),
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let response = pic
+        .query_call(canister_id, controller, "get_deal_warnings", encode_one(deal.id).unwrap())
+        .unwrap();
+    let warnings: Result<Vec<String>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(warnings.unwrap(), vec!["Deal has no expected close date".to_string()]);
+
+    let response = pic
+        .query_call(canister_id, controller, "get_contact_warnings", encode_one(contact.id).unwrap())
+        .unwrap();
+    let warnings: Result<Vec<String>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(warnings.unwrap().contains(&"Contact has no company".to_string()));
+}
+
+// ============================================================================
+// Deals By Owner Report Tests
+// ============================================================================
+
+#[test]
+fn test_get_deals_by_owner_aggregates_per_rep() {
+    let (pic, canister_id, controller) = setup();
+    let rep_a = test_principal(70);
+    let rep_b = test_principal(71);
+    for rep in [rep_a, rep_b] {
+        add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::EditOwnDeals]);
+    }
+
+    let create_deal = |owner: Principal, email: &str, value: u64, stage: Option<DealStage>| {
+        let contact = create_test_contact(&pic, canister_id, owner, email);
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: format!("Deal for {}", email),
+            value: Some(value),
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, owner, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+        if let Some(stage) = stage {
+            let response = pic
+                .update_call(canister_id, owner, "update_deal_stage", encode_args((deal.id, stage)).unwrap())
+                .unwrap();
+            let result: Result<Deal, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+            result.expect("Owner should move own deal");
+        }
+    };
+
+    create_deal(rep_a, "a1@example.com", 1_000, None);
+    create_deal(rep_a, "a2@example.com", 2_000, None);
+    create_deal(rep_a, "a3@example.com", 5_000, Some(DealStage::ClosedWon));
+    create_deal(rep_b, "b1@example.com", 7_000, Some(DealStage::ClosedWon));
+    create_deal(rep_b, "b2@example.com", 9_000, Some(DealStage::ClosedLost));
+
+    let response = pic
+        .query_call(canister_id, controller, "get_deals_by_owner", encode_one(()).unwrap())
+        .unwrap();
+    let board: Result<Vec<OwnerDealSummary>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let board = board.unwrap();
+
+    let row = |owner: Principal| board.iter().find(|r| r.owner == owner).cloned().expect("owner row");
+    assert_eq!(
+        row(rep_a),
+        OwnerDealSummary { owner: rep_a, open_count: 2, won_count: 1, open_value: 3_000, won_value: 5_000 }
+    );
+    assert_eq!(
+        row(rep_b),
+        OwnerDealSummary { owner: rep_b, open_count: 0, won_count: 1, open_value: 0, won_value: 7_000 }
+    );
+
+    // Reps without ViewAllDeals cannot see the board
+    let response = pic
+        .query_call(canister_id, rep_a, "get_deals_by_owner", encode_one(()).unwrap())
+        .unwrap();
+    let result: Result<Vec<OwnerDealSummary>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err());
+}
+
+// ============================================================================
+// Feature Flag Input Validation Tests
+// ============================================================================
+
+#[test]
+fn test_set_feature_flag_rejects_invalid_keys() {
+    let (pic, canister_id, controller) = setup();
+
+    let set_flag = |key: String| -> Result<(), String> {
+        let request = SetFeatureFlagRequest {
+            key,
+            enabled: true,
+            description: None,
+            percentage: None,
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let err = set_flag("k".repeat(101)).unwrap_err();
+    assert!(err.contains("Flag key"), "Unexpected error: {}", err);
+
+    let err = set_flag("new dashboard".to_string()).unwrap_err();
+    assert!(err.contains("Flag key"), "Unexpected error: {}", err);
+
+    assert!(set_flag("new-dashboard_v2".to_string()).is_ok());
+}
+
+// ============================================================================
+// Contact Growth Tests
+// ============================================================================
+
+#[test]
+fn test_get_contact_growth_buckets() {
+    let (pic, canister_id, controller) = setup();
+    let day_ns: Timestamp = 86_400_000_000_000;
+    let start: Timestamp = 1_600_000_000_000_000_000; // Sep 2020
+
+    // Two contacts on day 0, one on day 1 (backdated by the controller)
+    for (email, offset) in [("g1@example.com", 0), ("g2@example.com", 3_600_000_000_000), ("g3@example.com", day_ns)] {
+        let request = CreateContactRequestV2 {
+            email: email.to_string(),
+            created_at_override: Some(start + offset),
+            ..Default::default()
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        result.expect("Controller should create backdated contact");
+    }
+
+    let growth = |bucket_secs: u64| -> Result<Vec<(Timestamp, u64)>, String> {
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_contact_growth",
+                encode_args((start, start + 2 * day_ns - 1, bucket_secs)).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert_eq!(growth(86_400).unwrap(), vec![(start, 2), (start + day_ns, 1)]);
+    assert!(growth(0).is_err(), "Zero-width buckets are rejected");
+}
+
+// ============================================================================
+// Global Search Tests
+// ============================================================================
+
+#[test]
+fn test_global_search_matches_contacts_and_deals() {
+    let (pic, canister_id, controller) = setup();
+
+    let request = CreateContactRequest {
+        user_id: None,
+        email: "buyer@example.com".to_string(),
+        name: None,
+        company: Some("Zephyr Labs".to_string()),
+        job_title: None,
+        interest_area: None,
+        source: None,
+        notes: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+        .unwrap();
+    let zephyr_contact: Contact = decode_one::<Result<Contact, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let other = create_test_contact(&pic, canister_id, controller, "other@example.com");
+    for (contact_id, name) in [(other.id, "Zephyr expansion"), (other.id, "Unrelated renewal")] {
+        let request = CreateDealRequest {
+            contact_id,
+            name: name.to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        pic.update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "global_search",
+            encode_args(("zephyr".to_string(), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let results: Result<SearchResults, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let results = results.unwrap();
+
+    assert_eq!(results.contacts.iter().map(|c| c.id).collect::<Vec<_>>(), vec![zephyr_contact.id]);
+    assert_eq!(results.deals.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(), vec!["Zephyr expansion"]);
+}
+
+// ============================================================================
+// Custom Pipeline Stage Tests
+// ============================================================================
+
+#[test]
+fn test_custom_pipeline_rejects_unknown_stage() {
+    let (pic, canister_id, controller) = setup();
+
+    let stages = vec!["Discovery".to_string(), "Pilot".to_string(), "Contract".to_string()];
+    let response = pic
+        .update_call(canister_id, controller, "set_pipeline_stages", encode_one(stages).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should configure the pipeline");
+
+    let contact = create_test_contact(&pic, canister_id, controller, "pipeline@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Pipeline deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(deal.custom_stage, Some("Discovery".to_string()), "New deals start at the first stage");
+
+    let move_to = |custom_stage: &str| -> Result<DealV2, String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "update_deal_stage",
+                encode_args((deal.id, DealStage::Qualified, Some(custom_stage.to_string()))).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let err = move_to("Negotiation").unwrap_err();
+    assert!(err.contains("Unknown pipeline stage"), "Unexpected error: {}", err);
+
+    let moved = move_to("Pilot").expect("Configured stage should be accepted");
+    assert_eq!(moved.custom_stage, Some("Pilot".to_string()));
+    assert_eq!(moved.stage, DealStage::Qualified);
+
+    // Two-argument callers keep working and keep the current custom stage
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "update_deal_stage",
+            encode_args((deal.id, DealStage::Proposal)).unwrap(),
+        )
+        .unwrap();
+    let moved: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .expect("Omitting the custom stage should be allowed");
+    assert_eq!(moved.custom_stage, Some("Pilot".to_string()));
+
+    // update_deal validates custom stages the same way
+    let update = |custom_stage: &str| -> Result<DealV2, String> {
+        let request = UpdateDealRequest {
+            id: deal.id,
+            name: None,
+            value: None,
+            stage: None,
+            notes: None,
+            expected_close_date: None,
+            custom_stage: Some(custom_stage.to_string()),
+        };
+        let response = pic
+            .update_call(canister_id, controller, "update_deal", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    assert!(update("Negotiation").unwrap_err().contains("Unknown pipeline stage"));
+    assert_eq!(update("Contract").unwrap().custom_stage, Some("Contract".to_string()));
+
+    // Removing a stage clears it from the deals that were in it
+    let stages = vec!["Discovery".to_string(), "Pilot".to_string()];
+    let response = pic
+        .update_call(canister_id, controller, "set_pipeline_stages", encode_one(stages).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+    let response = pic
+        .query_call(canister_id, controller, "get_deal", encode_one(deal.id).unwrap())
+        .unwrap();
+    let reloaded: Option<DealV2> = decode_one::<Result<Option<DealV2>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(reloaded.unwrap().custom_stage, None);
+}
+
+// ============================================================================
+// Email Blocklist Tests
+// ============================================================================
+
+#[test]
+fn test_email_blocklist_rejects_blocked_domain() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_email_blocklist",
+            encode_args((vec!["spam.com".to_string()], Vec::<String>::new())).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should set the blocklist");
+
+    let create = |email: &str| -> Result<ContactV2, String> {
+        let request = CreateContactRequest {
+            user_id: None,
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert_eq!(create("x@spam.com").unwrap_err(), "Email is blocklisted");
+    assert!(create("x@good.com").is_ok(), "Unblocked domain should be accepted");
+}
+
+// ============================================================================
+// Recent Action Rate Tests
+// ============================================================================
+
+#[test]
+fn test_recent_action_rates_surface_delete_spike() {
+    let (pic, canister_id, controller) = setup();
+
+    for i in 0..4 {
+        let contact = create_test_contact(&pic, canister_id, controller, &format!("spike{}@example.com", i));
+        let response = pic
+            .update_call(canister_id, controller, "delete_contact", encode_one(contact.id).unwrap())
+            .unwrap();
+        let result: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        assert!(result.is_ok(), "Should be able to delete contact");
+    }
+    create_test_contact(&pic, canister_id, controller, "keeper@example.com");
+
+    let response = pic
+        .query_call(canister_id, controller, "get_recent_action_rates", encode_one(3600u64).unwrap())
+        .unwrap();
+    let rates: Result<Vec<(Principal, String, u64)>, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    let rates = rates.unwrap();
+
+    assert!(
+        rates.contains(&(controller, "delete_contact".to_string(), 4)),
+        "Deletes should be counted per actor: {:?}",
+        rates
+    );
+    assert!(rates.contains(&(controller, "create_contact".to_string(), 5)));
+    assert!(rates.windows(2).all(|w| w[0].2 >= w[1].2), "Rates should be sorted descending");
+}
+
+// ============================================================================
+// Export Version Header Tests
+// ============================================================================
+
+#[test]
+fn test_import_rejects_newer_export_version() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .query_call(canister_id, controller, "export_state", encode_args(()).unwrap())
+        .unwrap();
+    let exported: Vec<u8> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(&exported[..4], b"DAOX", "Exports should carry the version header");
+
+    let response = pic
+        .update_call(canister_id, controller, "import_state", encode_one(exported.clone()).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Current-version export should import: {:?}", result);
+
+    let mut bumped = exported;
+    let version = u32::from_le_bytes([bumped[4], bumped[5], bumped[6], bumped[7]]);
+    bumped[4..8].copy_from_slice(&(version + 1).to_le_bytes());
+    let response = pic
+        .update_call(canister_id, controller, "import_state", encode_one(bumped).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let err = result.unwrap_err();
+    assert!(err.contains("Incompatible backup"), "Unexpected error: {}", err);
+}
+
+// ============================================================================
+// Marketing Consent Tests
+// ============================================================================
+
+#[test]
+fn test_marketing_consent_updates_timestamp_and_list() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "consent@example.com");
+    assert_eq!(contact.marketing_consent, None);
+
+    let set_consent = |consent: bool| -> ContactV2 {
+        let request = UpdateContactRequest {
+            id: contact.id,
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            notes: None,
+            status: None,
+            clear_fields: None,
+            marketing_consent: Some(consent),
+        };
+        let response = pic
+            .update_call(canister_id, controller, "update_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Update should succeed")
+    };
+    let consented_ids = || -> Vec<ContactId> {
+        let response = pic
+            .query_call(canister_id, controller, "get_consented_contacts", encode_one(None::<PaginationParams>).unwrap())
+            .unwrap();
+        decode_one::<Result<PaginatedContactV2Response, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|c| c.id)
+            .collect()
+    };
+
+    let opted_in = set_consent(true);
+    assert_eq!(opted_in.marketing_consent, Some(true));
+    let opted_in_at = opted_in.consent_updated_at.expect("Consent timestamp should be set");
+    assert_eq!(consented_ids(), vec![contact.id]);
+
+    pic.advance_time(std::time::Duration::from_secs(5));
+    let opted_out = set_consent(false);
+    assert_eq!(opted_out.marketing_consent, Some(false));
+    assert!(opted_out.consent_updated_at.unwrap() > opted_in_at, "Timestamp should move on change");
+    assert!(consented_ids().is_empty());
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((Some("update_marketing_consent".to_string()), None::<String>, None::<Principal>, None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let logs: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(logs.unwrap().len(), 2, "Each consent change should be audited");
+}
+
+// ============================================================================
+// Feature Flag History Tests
+// ============================================================================
+
+#[test]
+fn test_feature_flag_history_returns_changes_newest_first() {
+    let (pic, canister_id, controller) = setup();
+
+    for enabled in [true, false] {
+        let request = SetFeatureFlagRequest {
+            key: "history_flag".to_string(),
+            enabled,
+            description: None,
+            percentage: None,
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+            .unwrap();
+        let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        assert!(result.is_ok(), "Controller should set the flag");
+    }
+    let other = SetFeatureFlagRequest {
+        key: "other_flag".to_string(),
+        enabled: true,
+        description: None,
+        percentage: None,
+        allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    pic.update_call(canister_id, controller, "set_feature_flag", encode_one(other).unwrap())
+        .unwrap();
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_feature_flag_history",
+            encode_args(("history_flag".to_string(), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let history: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let history = history.unwrap();
+
+    assert_eq!(history.len(), 2, "Only this flag's changes should be returned");
+    assert!(history.iter().all(|e| e.target_type == "feature_flag" && e.target_id == "history_flag"));
+    assert!(history[0].id > history[1].id, "History should be newest first");
+    assert!(history[0].details.as_deref().unwrap().contains(r#""new":{"enabled":false"#));
+}
+
+// ============================================================================
+// Batch Contact Lookup Tests
+// ============================================================================
+
+#[test]
+fn test_get_contacts_by_ids_skips_missing_and_hidden() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(80);
+    add_admin_with_permissions(&pic, canister_id, controller, rep, &[AdminPermission::ViewOwnContacts]);
+
+    let others = create_test_contact(&pic, canister_id, controller, "others@example.com");
+    let mine = create_test_contact(&pic, canister_id, rep, "mine@example.com");
+    let ids = vec![others.id, 9_999, mine.id];
+
+    let lookup = |caller: Principal| -> Vec<ContactId> {
+        let response = pic
+            .query_call(canister_id, caller, "get_contacts_by_ids", encode_one(ids.clone()).unwrap())
+            .unwrap();
+        decode_one::<Result<Vec<ContactV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect()
+    };
+
+    assert_eq!(lookup(controller), vec![others.id, mine.id], "Missing ids should be skipped");
+    assert_eq!(lookup(rep), vec![mine.id], "Records the rep cannot view should be omitted");
+
+    let too_many: Vec<ContactId> = (1..=201).collect();
+    let response = pic
+        .query_call(canister_id, controller, "get_contacts_by_ids", encode_one(too_many).unwrap())
+        .unwrap();
+    let result: Result<Vec<ContactV2>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Id count should be capped");
+}
+
+// ============================================================================
+// Deal Reopen Tracking Tests
+// ============================================================================
+
+#[test]
+fn test_deal_reopen_count_tracks_reopens() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "reopen@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Bouncing deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let move_to = |stage: DealStage| -> DealV2 {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "update_deal_stage",
+                encode_args((deal.id, stage, None::<String>)).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Stage change should succeed")
+    };
+
+    move_to(DealStage::ClosedWon);
+    move_to(DealStage::Negotiation);
+    move_to(DealStage::ClosedLost);
+    let reopened = move_to(DealStage::Proposal);
+    assert_eq!(reopened.reopen_count, 2);
+
+    let reopened_ids = |min_reopens: u32| -> Vec<DealId> {
+        let response = pic
+            .query_call(canister_id, controller, "get_frequently_reopened_deals", encode_one(min_reopens).unwrap())
+            .unwrap();
+        decode_one::<Result<Vec<DealV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.id)
+            .collect()
+    };
+    assert_eq!(reopened_ids(1), vec![deal.id]);
+    assert!(reopened_ids(2).is_empty());
+}
+
+// ============================================================================
+// Auto-Churn Tests
+// ============================================================================
+
+#[test]
+fn test_auto_churn_marks_inactive_contact_churned() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "dormant@example.com");
+    assert_eq!(contact.status, ContactStatus::Active);
+
+    let response = pic
+        .update_call(canister_id, controller, "set_auto_churn_after_secs", encode_one(Some(60u64)).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should enable auto-churn");
+
+    pic.advance_time(std::time::Duration::from_secs(180));
+    for _ in 0..5 {
+        pic.tick();
+    }
+
+    let response = pic
+        .query_call(canister_id, controller, "get_contact", encode_one(contact.id).unwrap())
+        .unwrap();
+    let fetched: Result<Option<ContactV2>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(fetched.unwrap().unwrap().status, ContactStatus::Churned);
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((Some("auto_churn_contact".to_string()), None::<String>, None::<Principal>, None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let logs: Result<Vec<AuditLogEntry>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(logs.unwrap().len(), 1);
+}
+
+// ============================================================================
+// Unowned Record Repair Tests
+// ============================================================================
+
+#[test]
+fn test_unowned_contact_repair_endpoints() {
+    let (pic, canister_id, controller) = setup();
+    create_test_contact(&pic, canister_id, controller, "owned@example.com");
+
+    let response = pic
+        .query_call(canister_id, controller, "list_unowned_contacts", encode_args(()).unwrap())
+        .unwrap();
+    let unowned: Result<Vec<ContactId>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(unowned.unwrap(), Vec::<ContactId>::new(), "API-created contacts always have an owner");
+
+    let response = pic
+        .query_call(canister_id, non_admin_principal(), "list_unowned_contacts", encode_args(()).unwrap())
+        .unwrap();
+    let unowned: Result<Vec<ContactId>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(unowned.is_err(), "Only controllers may list unowned contacts");
+
+    let assign = |owner: Principal| -> Result<(u64, u64), String> {
+        let response = pic
+            .update_call(canister_id, controller, "assign_unowned_to", encode_one(Some(owner)).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    let err = assign(test_principal(81)).unwrap_err();
+    assert!(err.contains("is not an admin"), "Unexpected error: {}", err);
+    assert_eq!(assign(controller), Ok((0, 0)));
+}
+
+// ============================================================================
+// Signup Rate Limit Tests
+// ============================================================================
+
+#[test]
+fn test_signup_rate_limit_rejects_flood_and_counts_violations() {
+    let (pic, canister_id, controller) = setup();
+    let user_service_principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service_principal)).unwrap(),
+    )
+    .unwrap();
+    let response = pic
+        .update_call(canister_id, controller, "set_signup_rate_limit", encode_one(5u32).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should set the signup cap");
+
+    let signup = |i: u32| -> Result<ContactV2, String> {
+        let request = CreateContactRequest {
+            user_id: Some(format!("flood-{}", i)),
+            email: format!("flood{}@example.com", i),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: Some(ContactSource::Signup),
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, user_service_principal, "create_contact_from_signup", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    for i in 0..5 {
+        assert!(signup(i).is_ok(), "Signup {} should be within the cap", i);
+    }
+    for i in 5..7 {
+        let err = signup(i).unwrap_err();
+        assert!(err.contains("Rate limit exceeded"), "Unexpected error: {}", err);
+    }
+
+    let response = pic
+        .query_call(canister_id, controller, "get_rate_limit_violations", encode_args(()).unwrap())
+        .unwrap();
+    let violations: Result<Vec<(Principal, String, u64)>, String> =
+        decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(
+        violations.unwrap(),
+        vec![(user_service_principal, "create_contact_from_signup".to_string(), 2)]
+    );
+}
+
+// ============================================================================
+// Manual Contact Default Source Tests
+// ============================================================================
+
+#[test]
+fn test_manual_contact_uses_configured_default_source() {
+    let (pic, canister_id, controller) = setup();
+
+    let contact = create_test_contact(&pic, canister_id, controller, "manual@example.com");
+    assert_eq!(contact.source, ContactSource::Other, "Manual contacts should not default to Signup");
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_manual_contact_default_source",
+            encode_one(ContactSource::Referral).unwrap(),
+        )
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should set the default source");
+
+    let contact = create_test_contact(&pic, canister_id, controller, "referred@example.com");
+    assert_eq!(contact.source, ContactSource::Referral);
+}
+
+// ============================================================================
+// Transaction Reference Prefix Tests
+// ============================================================================
+
+#[test]
+fn test_get_transactions_by_reference_prefix() {
+    let (pic, canister_id, controller) = setup();
+
+    for reference in ["INV-001", "INV-002", "REF-003", "inv-004"] {
+        let request = CreateTransactionRequest {
+            transaction_type: TransactionType::Income,
+            category: TransactionCategory::Other,
+            amount: 1_000,
+            currency: None,
+            description: format!("Payment {}", reference),
+            reference: Some(reference.to_string()),
+            date: None,
+            reverses: None,
+            receipt_hash: None,
+        };
+        pic.update_call(canister_id, controller, "create_transaction", encode_one(request).unwrap())
+            .unwrap();
+    }
+
+    let filter = TransactionFilter {
+        reference_prefix: Some("INV-".to_string()),
+        ..Default::default()
+    };
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_transactions",
+            encode_args((Some(filter), None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let result: Result<PaginatedTransactionResponse, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let references: Vec<Option<String>> = result.unwrap().items.into_iter().map(|t| t.reference).collect();
+
+    assert_eq!(references, vec![Some("INV-001".to_string()), Some("INV-002".to_string())]);
+}
+
+// ============================================================================
+// Access Denial Audit Tests
+// ============================================================================
+
+#[test]
+fn test_denied_add_admin_leaves_access_denial_trail() {
+    let (pic, canister_id, controller) = setup();
+    let intruder = test_principal(90);
+
+    let response = pic
+        .update_call(canister_id, intruder, "add_admin", encode_one(intruder).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err(), "Non-controller must not add admins");
+
+    let response = pic
+        .query_call(canister_id, controller, "get_access_denials", encode_one(None::<u64>).unwrap())
+        .unwrap();
+    let denials: Vec<AccessDenial> = decode_one::<Result<Vec<AccessDenial>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(denials.len(), 1);
+    assert_eq!(denials[0].caller, intruder);
+    assert_eq!(denials[0].action, "add_admin");
+    assert_eq!(denials[0].required, "controller");
+
+    // Denials stay out of the audit log, so they cannot evict its history
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((None::<String>, None::<String>, Some(intruder), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let logs: Vec<AuditLogEntry> = decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert!(logs.is_empty());
+}
+
+// ============================================================================
+// Bulk Feature Flag Tests
+// ============================================================================
+
+#[test]
+fn test_set_feature_flags_applies_batch_all_or_nothing() {
+    let (pic, canister_id, controller) = setup();
+
+    let flag = |key: &str| SetFeatureFlagRequest {
+        key: key.to_string(),
+        enabled: true,
+        description: None,
+        percentage: None,
+        allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    let set_flags = |requests: Vec<SetFeatureFlagRequest>| -> Result<u32, String> {
+        let response = pic
+            .update_call(canister_id, controller, "set_feature_flags", encode_one(requests).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    let flag_exists = |key: &str| -> bool {
+        let response = pic
+            .query_call(canister_id, controller, "get_feature_flag", encode_one(key.to_string()).unwrap())
+            .unwrap();
+        decode_one::<Option<FeatureFlag>>(&unwrap_wasm_result(response)).unwrap().is_some()
+    };
+
+    assert_eq!(set_flags(vec![flag("bulk_a"), flag("bulk_b"), flag("bulk_c")]), Ok(3));
+    assert!(flag_exists("bulk_a") && flag_exists("bulk_b") && flag_exists("bulk_c"));
+
+    // The whole batch is one audit entry listing every key
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_feature_flag_history",
+            encode_args(("batch".to_string(), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let history: Vec<AuditLogEntry> = decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].action, "set_feature_flags");
+    let details = history[0].details.as_deref().unwrap();
+    assert!(["bulk_a", "bulk_b", "bulk_c"].iter().all(|key| details.contains(key)));
+    assert!(details.contains("\"old\":null"));
+
+    let result = set_flags(vec![flag("bulk_d"), flag("bad key!")]);
+    assert!(result.is_err(), "An invalid key should reject the whole batch");
+    assert!(!flag_exists("bulk_d"), "No flag from a rejected batch should be applied");
+}
+
+// ============================================================================
+// Deal Create/Update Validation Wiring Tests
+// ============================================================================
+
+#[test]
+fn test_update_deal_rejects_short_name() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "deal-update@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Valid deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let update = UpdateDealRequest {
+        id: deal.id,
+        name: Some("ab".to_string()),
+        value: None,
+        stage: None,
+        notes: None,
+        expected_close_date: None,
+        custom_stage: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "update_deal", encode_one(update).unwrap())
+        .unwrap();
+    let result: Result<DealV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap_err().contains("Deal name"), "A 2-char deal name should be rejected on update");
+}
+
+#[test]
+fn test_create_deal_rejects_long_notes() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "deal-notes@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Wordy deal".to_string(),
+        value: None,
+        notes: Some("x".repeat(5001)),
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<DealV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.unwrap_err().contains("Notes"), "Over-long notes should be rejected on create");
+}
+
+// ============================================================================
+// Required Contact Name Tests
+// ============================================================================
+
+#[test]
+fn test_require_contact_name_rejects_nameless_contacts() {
+    let (pic, canister_id, controller) = setup();
+
+    let create = |email: &str| -> Result<ContactV2, String> {
+        let request = CreateContactRequest {
+            user_id: None,
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert!(create("nameless-ok@example.com").is_ok(), "Names are optional by default");
+
+    let response = pic
+        .update_call(canister_id, controller, "set_require_contact_name", encode_one(true).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_ok(), "Controller should enable the requirement");
+
+    assert_eq!(create("nameless-rejected@example.com").unwrap_err(), "Name is required");
+}
+
+// ============================================================================
+// State Size Diagnostics Tests
+// ============================================================================
+
+#[test]
+fn test_get_state_sizes_counts_records() {
+    let (pic, canister_id, controller) = setup();
+
+    let contact = create_test_contact(&pic, canister_id, controller, "size-a@example.com");
+    create_test_contact(&pic, canister_id, controller, "size-b@example.com");
+    let deal = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Sized deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    pic.update_call(canister_id, controller, "create_deal", encode_one(deal).unwrap())
+        .unwrap();
+    let transaction = CreateTransactionRequest {
+        transaction_type: TransactionType::Income,
+        category: TransactionCategory::Other,
+        amount: 500,
+        currency: None,
+        description: "Sized payment".to_string(),
+        reference: None,
+        date: None,
+        reverses: None,
+        receipt_hash: None,
+    };
+    pic.update_call(canister_id, controller, "create_transaction", encode_one(transaction).unwrap())
+        .unwrap();
+
+    let response = pic
+        .query_call(canister_id, controller, "get_state_sizes", encode_args(()).unwrap())
+        .unwrap();
+    let sizes: Result<StateSizes, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let sizes = sizes.unwrap();
+
+    assert_eq!(sizes.contacts.count, 2);
+    assert_eq!(sizes.deals.count, 1);
+    assert_eq!(sizes.transactions.count, 1);
+    assert_eq!(sizes.feature_flags.count, 0);
+    assert!(sizes.audit_log.count >= 4, "Each create should be audited");
+    assert!(sizes.contacts.approx_bytes > 0);
+    assert!(sizes.total_approx_bytes >= sizes.contacts.approx_bytes + sizes.deals.approx_bytes);
+
+    let response = pic
+        .query_call(canister_id, test_principal(91), "get_state_sizes", encode_args(()).unwrap())
+        .unwrap();
+    let denied: Result<StateSizes, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(denied.is_err(), "Only controllers may inspect state sizes");
+}
+
+// ============================================================================
+// Contact Tag Tests
+// ============================================================================
+
+#[test]
+fn test_rename_tag_rewrites_all_contacts() {
+    let (pic, canister_id, controller) = setup();
+
+    let mut ids = Vec::new();
+    for (email, tags) in [
+        ("tag-a@example.com", vec!["vips"]),
+        ("tag-b@example.com", vec!["VIPS ", "beta"]),
+        ("tag-c@example.com", vec!["vips", "vip"]),
+        ("tag-d@example.com", vec!["beta"]),
+    ] {
+        let contact = create_test_contact(&pic, canister_id, controller, email);
+        let tags: Vec<String> = tags.into_iter().map(String::from).collect();
+        let response = pic
+            .update_call(canister_id, controller, "set_contact_tags", encode_args((contact.id, tags)).unwrap())
+            .unwrap();
+        let tagged: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+        tagged.expect("Tagging should succeed");
+        ids.push(contact.id);
+    }
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "rename_tag",
+            encode_args(("vips".to_string(), " VIP".to_string())).unwrap(),
+        )
+        .unwrap();
+    let affected: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(affected, Ok(3));
+
+    let tags_of = |id: ContactId| -> Vec<String> {
+        let response = pic
+            .query_call(canister_id, controller, "get_contact", encode_one(id).unwrap())
+            .unwrap();
+        decode_one::<Result<Option<ContactV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .tags
+    };
+    assert_eq!(tags_of(ids[0]), vec!["vip"]);
+    assert_eq!(tags_of(ids[1]), vec!["beta", "vip"]);
+    assert_eq!(tags_of(ids[2]), vec!["vip"], "Existing target tag is merged, not duplicated");
+    assert_eq!(tags_of(ids[3]), vec!["beta"]);
+}
+
+// ============================================================================
+// Deal Integrity Tests
+// ============================================================================
+
+#[test]
+fn test_deal_integrity_endpoints_are_controller_only() {
+    let (pic, canister_id, controller) = setup();
+
+    let contact = create_test_contact(&pic, canister_id, controller, "integrity@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Integrity Deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let _: Result<DealV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+
+    // Deleting the contact cascades to its deals, so a healthy tree has no orphans
+    let response = pic
+        .update_call(canister_id, controller, "delete_contact", encode_one(contact.id).unwrap())
+        .unwrap();
+    let _: Result<ContactV2, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+
+    let response = pic
+        .query_call(canister_id, controller, "validate_deal_integrity", encode_args(()).unwrap())
+        .unwrap();
+    let orphans: Result<Vec<u64>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(orphans, Ok(vec![]));
+
+    let response = pic
+        .update_call(canister_id, controller, "delete_orphaned_deals", encode_args(()).unwrap())
+        .unwrap();
+    let removed: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(removed, Ok(0));
+
+    let outsider = test_principal(92);
+    let response = pic
+        .query_call(canister_id, outsider, "validate_deal_integrity", encode_args(()).unwrap())
+        .unwrap();
+    let denied: Result<Vec<u64>, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(denied.is_err());
+
+    let response = pic
+        .update_call(canister_id, outsider, "delete_orphaned_deals", encode_args(()).unwrap())
+        .unwrap();
+    let denied: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(denied.is_err());
+}
+
+// ============================================================================
+// Default Page Size Tests
+// ============================================================================
+
+#[test]
+fn test_default_page_size_applies_to_unpaginated_get_contacts() {
+    let (pic, canister_id, controller) = setup();
+
+    for i in 0..15 {
+        create_test_contact(&pic, canister_id, controller, &format!("page{}@example.com", i));
+    }
+
+    let response = pic
+        .update_call(canister_id, controller, "set_default_page_size", encode_one(10u64).unwrap())
+        .unwrap();
+    let set: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert_eq!(set, Ok(10));
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts",
+            encode_args((None::<ContactFilter>, None::<PaginationParams>)).unwrap(),
+        )
+        .unwrap();
+    let page: Result<PaginatedContactV2Response, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    let page = page.unwrap();
+    assert_eq!(page.items.len(), 10);
+    assert_eq!(page.limit, 10);
+    assert_eq!(page.total, 15);
+
+    let response = pic
+        .update_call(canister_id, controller, "set_default_page_size", encode_one(0u64).unwrap())
+        .unwrap();
+    let rejected: Result<u64, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(rejected.is_err());
+}
+
+// ============================================================================
+// Stage Velocity Tests
+// ============================================================================
+
+#[test]
+fn test_stage_velocity_averages_time_per_stage() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "velocity@example.com");
+
+    let create = |name: &str| -> DealId {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: name.to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .id
+    };
+    let move_to = |id: DealId, stage: DealStage| {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "update_deal_stage",
+                encode_args((id, stage, None::<String>)).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Stage change should succeed");
+    };
+
+    // Both deals enter Lead together; one spends 100s there, the other 300s
+    let fast = create("Fast deal");
+    let slow = create("Slow deal");
+    pic.advance_time(std::time::Duration::from_secs(100));
+    move_to(fast, DealStage::Qualified);
+    pic.advance_time(std::time::Duration::from_secs(200));
+    move_to(slow, DealStage::Qualified);
+    move_to(fast, DealStage::ClosedWon);
+    pic.advance_time(std::time::Duration::from_secs(60));
+    move_to(slow, DealStage::ClosedLost);
+
+    let response = pic
+        .query_call(canister_id, controller, "get_stage_velocity", encode_args(()).unwrap())
+        .unwrap();
+    let velocity: Vec<(DealStage, u64)> =
+        decode_one::<Result<Vec<(DealStage, u64)>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+
+    // Update calls advance the clock slightly, so allow a little slack
+    let secs = 1_000_000_000u64;
+    assert_eq!(velocity.len(), 2, "Stages never left are omitted: {:?}", velocity);
+    assert_eq!(velocity[0].0, DealStage::Lead);
+    assert!((200 * secs..205 * secs).contains(&velocity[0].1), "Lead average: {}", velocity[0].1);
+    assert_eq!(velocity[1].0, DealStage::Qualified);
+    assert!((130 * secs..135 * secs).contains(&velocity[1].1), "Qualified average: {}", velocity[1].1);
+}
+
+// ============================================================================
+// Conditional Feature Flag Update Tests
+// ============================================================================
+
+#[test]
+fn test_set_feature_flag_rejects_stale_expected_updated_at() {
+    let (pic, canister_id, controller) = setup();
+
+    let write = |caller: Principal, percentage: u8, expected_updated_at: Option<Timestamp>| -> Result<(), String> {
+        let request = SetFeatureFlagRequest {
+            key: "checkout_v2".to_string(),
+            enabled: true,
+            description: None,
+            percentage: Some(percentage),
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at,
+            audit_evaluations: None,
+            permille: None,
+        };
+        let response = pic
+            .update_call(canister_id, caller, "set_feature_flag", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    let read = || -> FeatureFlag {
+        let response = pic
+            .query_call(canister_id, controller, "get_feature_flag", encode_one("checkout_v2".to_string()).unwrap())
+            .unwrap();
+        decode_one::<Option<FeatureFlag>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Flag should exist")
+    };
+
+    write(controller, 10, None).expect("Initial write should succeed");
+    let first_read = read();
+
+    // A second writer updates the flag after the first writer read it
+    let second_writer = test_principal(93);
+    add_admin_with_permissions(&pic, canister_id, controller, second_writer, &[AdminPermission::ManageFeatureFlags]);
+    pic.advance_time(std::time::Duration::from_secs(1));
+    write(second_writer, 50, Some(first_read.updated_at)).expect("Second writer holds the current version");
+
+    let result = write(controller, 20, Some(first_read.updated_at));
+    let err = result.expect_err("Stale conditional update should be rejected");
+    assert!(err.contains("Conflict"), "Unexpected error: {}", err);
+    assert_eq!(read().percentage, Some(50), "Second writer's change must survive");
+
+    // Re-reading gives the current version, which is accepted
+    write(controller, 20, Some(read().updated_at)).expect("Fresh conditional update should succeed");
+    assert_eq!(read().percentage, Some(20));
+}
+
+// ============================================================================
+// Contacts Without Deals Tests
+// ============================================================================
+
+#[test]
+fn test_get_contacts_without_deals_returns_only_dealless_contacts() {
+    let (pic, canister_id, controller) = setup();
+
+    let with_deal = create_test_contact(&pic, canister_id, controller, "has-deal@example.com");
+    let without_deal = create_test_contact(&pic, canister_id, controller, "no-deal@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: with_deal.id,
+        name: "Existing deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let _: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_contacts_without_deals",
+            encode_one(None::<PaginationParams>).unwrap(),
+        )
+        .unwrap();
+    let page: PaginatedContactV2Response =
+        decode_one::<Result<PaginatedContactV2Response, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+
+    let ids: Vec<ContactId> = page.items.iter().map(|c| c.id).collect();
+    assert_eq!(ids, vec![without_deal.id]);
+    assert_eq!(page.total, 1);
+}
+
+// ============================================================================
+// Audited Feature Evaluation Tests
+// ============================================================================
+
+#[test]
+fn test_check_feature_audited_records_true_evaluations() {
+    let (pic, canister_id, controller) = setup();
+    let allowed = test_principal(94);
+    let other = test_principal(95);
+
+    let request = SetFeatureFlagRequest {
+        key: "payouts".to_string(),
+        enabled: true,
+        description: None,
+        percentage: None,
+        allowed_principals: Some(vec![allowed]),
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: Some(true),
+        permille: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    result.unwrap();
+
+    let check = |caller: Principal| -> bool {
+        let response = pic
+            .update_call(canister_id, caller, "check_feature_audited", encode_one("payouts".to_string()).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    assert!(check(allowed));
+    assert!(check(allowed));
+    assert!(!check(other), "Principals outside the allow list evaluate false");
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_flag_evaluations",
+            encode_args(("payouts".to_string(), None::<u64>)).unwrap(),
+        )
+        .unwrap();
+    let evaluations: Vec<FlagEvaluation> =
+        decode_one::<Result<Vec<FlagEvaluation>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+
+    assert_eq!(evaluations.len(), 2, "Only true evaluations are recorded");
+    assert!(evaluations.iter().all(|e| e.caller == allowed && e.key == "payouts"));
+}
+
+// ============================================================================
+// Name Length Limit Tests
+// ============================================================================
+
+#[test]
+fn test_lowered_deal_name_min_allows_short_codes() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "short-codes@example.com");
+
+    let create = |name: &str| -> Result<DealV2, String> {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: name.to_string(),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert!(create("Q").is_err(), "Default minimum is 3 characters");
+
+    let response = pic
+        .query_call(canister_id, controller, "get_name_length_limits", encode_args(()).unwrap())
+        .unwrap();
+    let limits: NameLengthLimits = decode_one::<Result<NameLengthLimits, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(limits.deal_name_min, 3);
+
+    let lowered = NameLengthLimits { deal_name_min: 1, ..limits };
+    let response = pic
+        .update_call(canister_id, controller, "set_name_length_limits", encode_one(lowered).unwrap())
+        .unwrap();
+    let result: Result<(), String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    result.expect("Controller should be able to lower the minimum");
+
+    let deal = create("Q").expect("One-character deal names should now validate");
+    assert_eq!(deal.name, "Q");
+}
+
+// ============================================================================
+// Audit Log Target Id Filter Tests
+// ============================================================================
+
+#[test]
+fn test_get_audit_log_filters_by_target_id_substring() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "audit-ids@example.com");
+
+    for i in 0..12 {
+        let request = CreateDealRequest {
+            contact_id: contact.id,
+            name: format!("Audited deal {}", i),
+            value: None,
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        let _: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+    }
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("create_deal".to_string()),
+                Some("deal".to_string()),
+                None::<Principal>,
+                None::<u64>,
+                Some("1".to_string()),
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+    let entries: Vec<AuditLogEntry> =
+        decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+
+    let mut ids: Vec<&str> = entries.iter().map(|e| e.target_id.as_str()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["1", "10", "11", "12"]);
+}
+
+// ============================================================================
+// Deal Attachment Tests
+// ============================================================================
+
+#[test]
+fn test_deal_attachments_round_trip_with_metadata() {
+    let (pic, canister_id, controller) = setup();
+    let contact = create_test_contact(&pic, canister_id, controller, "attachments@example.com");
+
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Grant proposal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: DealV2 = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let attach = |name: &str, url: &str| -> Result<DealV2, String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                controller,
+                "add_deal_attachment",
+                encode_args((deal.id, name.to_string(), url.to_string())).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    attach("Proposal", "https://docs.example.com/proposal.pdf").expect("https attachment should be accepted");
+    attach("Contract", "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi")
+        .expect("ipfs attachment should be accepted");
+    assert!(attach("Sketchy", "http://docs.example.com/x.pdf").is_err(), "Plain http is rejected");
+
+    let response = pic
+        .query_call(canister_id, controller, "get_deal", encode_one(deal.id).unwrap())
+        .unwrap();
+    let stored = decode_one::<Result<Option<DealV2>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap()
+        .expect("Deal should exist");
+
+    assert_eq!(stored.attachments.len(), 2);
+    assert_eq!(stored.attachments[0].name, "Proposal");
+    assert_eq!(stored.attachments[0].url, "https://docs.example.com/proposal.pdf");
+    assert_eq!(stored.attachments[1].name, "Contract");
+    assert!(stored.attachments.iter().all(|a| a.added_by == controller && a.added_at > 0));
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "remove_deal_attachment",
+            encode_args((deal.id, "https://docs.example.com/proposal.pdf".to_string())).unwrap(),
+        )
+        .unwrap();
+    let updated = decode_one::<Result<DealV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.attachments.len(), 1);
+    assert_eq!(updated.attachments[0].name, "Contract");
+}
+
+// ============================================================================
+// Contact Change Feed Tests
+// ============================================================================
+
+#[test]
+fn test_get_contact_changes_since_reports_create_update_delete() {
+    let (pic, canister_id, controller) = setup();
+
+    let to_update = create_test_contact(&pic, canister_id, controller, "feed-update@example.com");
+    let to_delete = create_test_contact(&pic, canister_id, controller, "feed-delete@example.com");
+    let baseline = to_update.updated_at.max(to_delete.updated_at);
+    pic.advance_time(std::time::Duration::from_secs(1));
+
+    let created = create_test_contact(&pic, canister_id, controller, "feed-create@example.com");
+    pic.advance_time(std::time::Duration::from_secs(1));
+
+    let update = UpdateContactRequest {
+        id: to_update.id,
+        name: Some("Polled Name".to_string()),
+        company: None,
+        job_title: None,
+        interest_area: None,
+        notes: None,
+        status: None,
+        clear_fields: None,
+        marketing_consent: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "update_contact", encode_one(update).unwrap())
+        .unwrap();
+    let _: ContactV2 = decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    pic.advance_time(std::time::Duration::from_secs(1));
+
+    let response = pic
+        .update_call(canister_id, controller, "delete_contact", encode_one(to_delete.id).unwrap())
+        .unwrap();
+    let _: ContactV2 = decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let changes_page = |limit: Option<u64>| -> PaginatedContactChangeResponse {
+        let pagination = limit.map(|limit| PaginationParams { offset: None, limit: Some(limit) });
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_contact_changes_since",
+                encode_args((baseline, pagination)).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<PaginatedContactChangeResponse, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    let changes = changes_page(None).items;
+    assert_eq!(changes.len(), 3, "{:?}", changes);
+    assert!(matches!(&changes[0], ContactChange::Created(c) if c.id == created.id));
+    assert!(matches!(&changes[1], ContactChange::Updated(c) if c.id == to_update.id && c.name.as_deref() == Some("Polled Name")));
+    assert!(matches!(&changes[2], ContactChange::Deleted { id, deleted_at } if *id == to_delete.id && *deleted_at > baseline));
+
+    let first_page = changes_page(Some(2));
+    assert_eq!(first_page.total, 3);
+    assert_eq!(first_page.items.len(), 2);
+}
+
+// ============================================================================
+// Audit Action Taxonomy Tests
+// ============================================================================
+
+#[test]
+fn test_crud_endpoints_record_canonical_audit_actions() {
+    let (pic, canister_id, controller) = setup();
+    let user_service = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    // Every call must succeed, otherwise its action would silently be missing below
+    let call = |caller: Principal, method: &str, args: Vec<u8>| -> Vec<u8> {
+        let response = pic.update_call(canister_id, caller, method, args).unwrap();
+        let bytes = unwrap_wasm_result(response);
+        if let Err(e) = decode_one::<Result<candid::Reserved, String>>(&bytes).unwrap() {
+            panic!("{} failed: {}", method, e);
+        }
+        bytes
+    };
+
+    // Configuration endpoints, set to their current or default values
+    let response = pic
+        .query_call(canister_id, controller, "get_name_length_limits", encode_args(()).unwrap())
+        .unwrap();
+    let limits: NameLengthLimits = decode_one::<Result<NameLengthLimits, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    call(controller, "set_name_length_limits", encode_one(limits).unwrap());
+    call(controller, "set_require_contact_name", encode_one(false).unwrap());
+    call(controller, "set_normalize_plus_addressing", encode_one(false).unwrap());
+    call(controller, "set_manual_contact_default_source", encode_one(ContactSource::Other).unwrap());
+    call(controller, "set_compact_signup_audit", encode_one(false).unwrap());
+    call(controller, "set_signup_deal_template", encode_one(None::<SignupDealTemplate>).unwrap());
+    call(controller, "set_metrics_retention", encode_one(None::<MetricsRetention>).unwrap());
+    call(controller, "set_pipeline_stages", encode_one(Vec::<String>::new()).unwrap());
+    call(controller, "set_signup_rate_limit", encode_one(1000u32).unwrap());
+    call(controller, "set_authorized_canister_rate_limit", encode_one(1000u32).unwrap());
+    call(controller, "set_default_page_size", encode_one(50u64).unwrap());
+    call(controller, "set_max_audit_detail_len", encode_one(2048u32).unwrap());
+    call(controller, "set_low_cycles_threshold", encode_one(0u64).unwrap());
+    call(controller, "set_deal_approval_threshold", encode_one(None::<u64>).unwrap());
+    call(controller, "set_auto_status_from_deals", encode_one(true).unwrap());
+    call(controller, "set_maintenance_mode", encode_one(true).unwrap());
+    call(controller, "set_maintenance_mode", encode_one(false).unwrap());
+    call(controller, "set_redact_pii_in_audit", encode_one(false).unwrap());
+    call(controller, "set_mirror_crm_to_activity_log", encode_one(false).unwrap());
+    call(controller, "set_audit_export_policy", encode_args((8_000u64, false)).unwrap());
+    call(controller, "set_email_blocklist", encode_args((Vec::<String>::new(), Vec::<String>::new())).unwrap());
+    call(controller, "set_protected_flag_keys", encode_one(Vec::<String>::new()).unwrap());
+    call(controller, "add_canister_role", encode_one("billing-service".to_string()).unwrap());
+    call(controller, "set_migration_default_owner", encode_one(Some(controller)).unwrap());
+    call(
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service)).unwrap(),
+    );
+
+    // Contacts
+    let contact = create_test_contact(&pic, canister_id, controller, "taxonomy@example.com");
+    let update = UpdateContactRequest {
+        id: contact.id,
+        name: Some("Taxonomy Contact".to_string()),
+        company: None,
+        job_title: None,
+        interest_area: None,
+        notes: None,
+        status: None,
+        clear_fields: None,
+        marketing_consent: Some(true),
+    };
+    call(controller, "update_contact", encode_one(update).unwrap());
+    call(controller, "set_contact_tags", encode_args((contact.id, vec!["vip".to_string()])).unwrap());
+    call(controller, "rename_tag", encode_args(("vip".to_string(), "priority".to_string())).unwrap());
+
+    // Deals
+    let create_deal = |contact_id: ContactId, name: &str| -> DealV2 {
+        let request = CreateDealRequest {
+            contact_id,
+            name: name.to_string(),
+            value: Some(1_000),
+            notes: None,
+            expected_close_date: None,
+        };
+        let bytes = call(controller, "create_deal", encode_one(request).unwrap());
+        decode_one::<Result<DealV2, String>>(&bytes).unwrap().unwrap()
+    };
+    let deal = create_deal(contact.id, "Taxonomy deal");
+    let update = UpdateDealRequest {
+        id: deal.id,
+        name: None,
+        value: Some(2_000),
+        stage: None,
+        notes: None,
+        expected_close_date: None,
+        custom_stage: None,
+    };
+    call(controller, "update_deal", encode_one(update).unwrap());
+    call(controller, "update_deal_stage", encode_args((deal.id, DealStage::Qualified, None::<String>)).unwrap());
+    call(controller, "add_deal_comment", encode_args((deal.id, "Call booked".to_string())).unwrap());
+    let url = "https://example.com/proposal.pdf".to_string();
+    call(controller, "add_deal_attachment", encode_args((deal.id, "Proposal".to_string(), url.clone())).unwrap());
+    call(controller, "remove_deal_attachment", encode_args((deal.id, url)).unwrap());
+    call(controller, "approve_deal_close", encode_one(deal.id).unwrap());
+    call(controller, "delete_deal", encode_one(deal.id).unwrap());
+
+    // Losing a contact's only deal re-derives its status, and deleting it cascades
+    let lost = create_test_contact(&pic, canister_id, controller, "taxonomy-lost@example.com");
+    let lost_deal = create_deal(lost.id, "Lost deal");
+    call(controller, "update_deal_stage", encode_args((lost_deal.id, DealStage::ClosedLost, None::<String>)).unwrap());
+    call(controller, "delete_contact", encode_one(lost.id).unwrap());
+
+    // Signups: a fresh contact with its auto-deal, and the manual contact enriched
+    let signup = |email: &str, user_id: &str| {
+        let request = CreateContactRequest {
+            user_id: Some(user_id.to_string()),
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: Some(ContactSource::Signup),
+            notes: None,
+        };
+        call(user_service, "create_contact_from_signup", encode_one(request).unwrap());
+    };
+    signup("taxonomy-signup@example.com", "user-taxonomy-1");
+    signup("taxonomy@example.com", "user-taxonomy-2");
+    call(controller, "assign_unowned_to", encode_one(Some(controller)).unwrap());
+
+    // Finance and feature flags
+    let row = CreateTransactionRequest {
+        transaction_type: TransactionType::Income,
+        category: TransactionCategory::Donation,
+        amount: 5_000,
+        currency: None,
+        description: "Statement line".to_string(),
+        reference: None,
+        date: None,
+        reverses: None,
+        receipt_hash: None,
+    };
+    call(controller, "bulk_create_transactions", encode_one(vec![row]).unwrap());
+    let flag = |key: &str| SetFeatureFlagRequest {
+        key: key.to_string(),
+        enabled: true,
+        description: None,
+        percentage: None,
+        allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    call(controller, "set_feature_flag", encode_one(flag("taxonomy_flag")).unwrap());
+    call(controller, "set_feature_flags", encode_one(vec![flag("taxonomy_batch")]).unwrap());
+    call(controller, "delete_feature_flag", encode_one("taxonomy_flag".to_string()).unwrap());
+    call(controller, "seed_default_feature_flags", encode_args(()).unwrap());
+
+    // Audit log housekeeping; purging before time 0 removes nothing
+    call(controller, "acknowledge_audit_export", encode_one(1u64).unwrap());
+    call(controller, "purge_audit_entries_before", encode_one(0u64).unwrap());
+
+    // The heartbeat churns the remaining idle contacts
+    call(controller, "set_auto_churn_after_secs", encode_one(Some(60u64)).unwrap());
+    pic.advance_time(std::time::Duration::from_secs(180));
+    for _ in 0..5 {
+        pic.tick();
+    }
+
+    let response = pic
+        .query_call(canister_id, controller, "get_audit_actions", encode_args(()).unwrap())
+        .unwrap();
+    let catalogue: Vec<String> = decode_one::<Result<Vec<String>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((None::<String>, None::<String>, None::<Principal>, Some(200u64))).unwrap(),
+        )
+        .unwrap();
+    let entries: Vec<AuditLogEntry> =
+        decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+    let recorded: std::collections::BTreeSet<&str> = entries.iter().map(|e| e.action.as_str()).collect();
+
+    for action in &recorded {
+        assert!(catalogue.iter().any(|a| a == action), "'{}' is not a catalogued audit action", action);
+    }
+
+    // Orphaned deals can only come from an imported state, so no endpoint above creates one
+    let unreachable = ["delete_orphaned_deal"];
+    let missing: Vec<&String> = catalogue
+        .iter()
+        .filter(|a| !unreachable.contains(&a.as_str()) && !recorded.contains(a.as_str()))
+        .collect();
+    assert!(missing.is_empty(), "No endpoint call recorded {:?}", missing);
+}
+
+// ============================================================================
+// Duplicate user_id Tests
+// ============================================================================
+
+#[test]
+fn test_create_contact_rejects_duplicate_user_id() {
+    let (pic, canister_id, controller) = setup();
+
+    let create = |email: &str| -> Result<ContactV2, String> {
+        let request = CreateContactRequest {
+            user_id: Some("user-dup-1".to_string()),
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let first = create("first-dup@example.com").expect("First contact should be created");
+    let err = create("second-dup@example.com").expect_err("Second contact with the same user_id should be rejected");
+    assert!(err.contains("user_id"), "Unexpected error: {}", err);
+
+    let response = pic
+        .query_call(canister_id, controller, "get_contact_by_user_id", encode_one("user-dup-1".to_string()).unwrap())
+        .unwrap();
+    let found = decode_one::<Result<Option<ContactV2>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap()
+        .expect("user_id should still resolve");
+    assert_eq!(found.id, first.id, "Index must still point at the first contact");
+}
+
+// ============================================================================
+// Transaction Receipt Hash Tests
+// ============================================================================
+
+#[test]
+fn test_transaction_receipt_hash_is_validated_and_stored() {
+    let (pic, canister_id, controller) = setup();
+
+    let create = |receipt_hash: &str| -> Result<Transaction, String> {
+        let request = CreateTransactionRequest {
+            transaction_type: TransactionType::Expense,
+            category: TransactionCategory::Other,
+            amount: 12_500,
+            currency: None,
+            description: "Audited expense".to_string(),
+            reference: None,
+            date: None,
+            reverses: None,
+            receipt_hash: Some(receipt_hash.to_string()),
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_transaction", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let hash = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+    let transaction = create(hash).expect("Valid receipt hash should be accepted");
+    assert_eq!(transaction.receipt_hash.as_deref(), Some(hash.to_lowercase().as_str()));
+
+    let err = create("not-a-sha256").expect_err("Malformed receipt hash should be rejected");
+    assert!(err.contains("Receipt hash"), "Unexpected error: {}", err);
+}
+
+// ============================================================================
+// Audit Export Watermark Tests
+// ============================================================================
+
+#[test]
+fn test_audit_export_flag_flips_at_high_watermark() {
+    let (pic, canister_id, controller) = setup();
+
+    let health = || -> HealthDetailed {
+        let response = pic
+            .query_call(canister_id, controller, "health_detailed", encode_one(()).unwrap())
+            .unwrap();
+        decode_one::<Result<HealthDetailed, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+    let create = |email: &str| {
+        let request = CreateContactRequest {
+            email: email.to_string(),
+            user_id: None,
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, controller, "create_contact", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Contact should be created");
+    };
+
+    let before = health();
+    assert!(!before.audit_export_needed);
+
+    // The policy change itself is audited, leaving two entries below the watermark
+    let watermark = before.audit_log_len + 3;
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_audit_export_policy",
+            encode_args((watermark, false)).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    create("watermark-1@example.com");
+    assert!(!health().audit_export_needed, "One entry below the watermark");
+
+    create("watermark-2@example.com");
+    let at_watermark = health();
+    assert!(at_watermark.audit_export_needed, "Flag should flip at the watermark");
+    assert_eq!(at_watermark.audit_log_len, watermark);
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "acknowledge_audit_export",
+            encode_one(at_watermark.audit_log_len).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let after = health();
+    assert!(!after.audit_export_needed, "Acknowledged export should clear the flag");
+    assert_eq!(after.audit_exported_through_id, at_watermark.audit_log_len);
+
+    // Only controllers may change the policy
+    let response = pic
+        .update_call(
+            canister_id,
+            non_admin_principal(),
+            "set_audit_export_policy",
+            encode_args((1u64, true)).unwrap(),
+        )
+        .unwrap();
+    assert!(decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().is_err());
+}
+
+#[test]
+fn test_signup_rejected_when_blocked_audit_log_is_full() {
+    let (pic, canister_id, controller) = setup();
+    let user_service = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service)).unwrap(),
+    )
+    .unwrap();
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_audit_export_policy",
+            encode_args((10_000u64, true)).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let health = || -> HealthDetailed {
+        let response = pic
+            .query_call(canister_id, controller, "health_detailed", encode_one(()).unwrap())
+            .unwrap();
+        decode_one::<Result<HealthDetailed, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+    let signup = |email: &str| -> Result<Contact, String> {
+        let request = CreateContactRequest {
+            user_id: None,
+            email: email.to_string(),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: None,
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, user_service, "create_contact_from_signup", encode_one(request).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    // Fill the log to capacity: each batch writes one entry
+    let mut next_key = 0u32;
+    let mut set_flag = || -> bool {
+        let request = SetFeatureFlagRequest {
+            key: format!("fill_{}", next_key),
+            enabled: false,
+            description: None,
+            percentage: None,
+            allowed_principals: None,
+            excluded_principals: None,
+            expected_updated_at: None,
+            audit_evaluations: None,
+            permille: None,
+        };
+        next_key += 1;
+        match pic.update_call(canister_id, controller, "set_feature_flags", encode_one(vec![request]).unwrap()) {
+            Ok(WasmResult::Reply(bytes)) => decode_one::<Result<u32, String>>(&bytes).unwrap().is_ok(),
+            _ => false,
+        }
+    };
+    while set_flag() {}
+
+    let full = health();
+    assert_eq!(full.audit_log_len, 10_000, "Log should stop exactly at capacity");
+
+    let err = signup("over-capacity@example.com").expect_err("Signup should be rejected while the log is full");
+    assert!(err.contains("acknowledge_audit_export"), "Unexpected error: {}", err);
+    assert_eq!(health().audit_log_len, 10_000, "A rejected signup must not grow the log");
+
+    // Acknowledging an export frees the drain and signups resume
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "acknowledge_audit_export",
+            encode_one(5_000u64).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    signup("after-export@example.com").expect("Signup should succeed once the export is acknowledged");
+    assert!(health().audit_log_len <= 10_000);
+}
+
+// ============================================================================
+// CRM Activity Mirroring Tests
+// ============================================================================
+
+#[test]
+fn test_mirror_crm_to_activity_log_records_both_logs() {
+    let (pic, canister_id, controller) = setup();
+
+    let contacts_created = || -> u64 {
+        let steps = vec!["contact_created".to_string()];
+        let response = pic
+            .query_call(canister_id, controller, "get_activity_funnel", encode_args((steps, 3600u64)).unwrap())
+            .unwrap();
+        let funnel: Vec<(String, u64)> = decode_one::<Result<Vec<(String, u64)>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap();
+        funnel[0].1
+    };
+
+    create_test_contact(&pic, canister_id, controller, "unmirrored@example.com");
+    assert_eq!(contacts_created(), 0, "Mirroring is off by default");
+
+    let response = pic
+        .update_call(canister_id, controller, "set_mirror_crm_to_activity_log", encode_one(true).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let contact = create_test_contact(&pic, canister_id, controller, "mirrored@example.com");
+    assert_eq!(contacts_created(), 1, "Create should be mirrored into the activity log");
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("create_contact".to_string()),
+                None::<String>,
+                None::<Principal>,
+                Some(10u64),
+            )).unwrap(),
+        )
+        .unwrap();
+    let logs: Vec<AuditLogEntry> = decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert!(
+        logs.iter().any(|e| e.target_id == contact.id.to_string()),
+        "Create should still be audited"
+    );
+
+    // Non-controllers cannot toggle mirroring
+    let response = pic
+        .update_call(canister_id, non_admin_principal(), "set_mirror_crm_to_activity_log", encode_one(false).unwrap())
+        .unwrap();
+    assert!(decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().is_err());
+}
+
+// ============================================================================
+// Stale Feature Flag Tests
+// ============================================================================
+
+#[test]
+fn test_get_stale_feature_flags_after_threshold() {
+    let (pic, canister_id, controller) = setup();
+
+    let request = SetFeatureFlagRequest {
+        key: "forgotten_experiment".to_string(),
+        enabled: true,
+        description: None,
+        percentage: None,
+        allowed_principals: None,
+        excluded_principals: None,
+        expected_updated_at: None,
+        audit_evaluations: None,
+        permille: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "set_feature_flag", encode_one(request).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let stale_keys = |caller: Principal| -> Result<Vec<String>, String> {
+        let response = pic
+            .query_call(canister_id, caller, "get_stale_feature_flags", encode_one(3600u64).unwrap())
+            .unwrap();
+        decode_one::<Result<Vec<FeatureFlag>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .map(|flags| flags.into_iter().map(|f| f.key).collect())
+    };
+
+    let keys = stale_keys(controller).unwrap();
+    assert!(!keys.contains(&"forgotten_experiment".to_string()), "Fresh flag is not stale");
+
+    pic.advance_time(std::time::Duration::from_secs(3601));
+    pic.tick();
+
+    let keys = stale_keys(controller).unwrap();
+    assert!(keys.contains(&"forgotten_experiment".to_string()), "Flag should be stale past the threshold");
+
+    assert!(stale_keys(non_admin_principal()).is_err());
+}
+
+// ============================================================================
+// Bulk Transaction Import Tests
+// ============================================================================
+
+#[test]
+fn test_bulk_create_transactions_reports_invalid_rows() {
+    let (pic, canister_id, controller) = setup();
+
+    let row = |amount: u64| CreateTransactionRequest {
+        transaction_type: TransactionType::Income,
+        category: TransactionCategory::Donation,
+        amount,
+        currency: None,
+        description: "Statement line".to_string(),
+        reference: None,
+        date: None,
+        reverses: None,
+        receipt_hash: None,
+    };
+    // MAX_TRANSACTION_AMOUNT is $1M in cents
+    let requests = vec![row(5_000), row(100_000_001), row(7_500), row(u64::MAX)];
+
+    let response = pic
+        .update_call(canister_id, controller, "bulk_create_transactions", encode_one(requests).unwrap())
+        .unwrap();
+    let result: BulkImportResult = decode_one::<Result<BulkImportResult, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .expect("Batch should be accepted");
+
+    assert_eq!(result.created.len(), 2, "Valid rows are created despite invalid ones");
+    assert_eq!(result.errors.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![1, 3]);
+    assert!(result.errors[0].1.contains("exceed"), "Unexpected error: {}", result.errors[0].1);
+
+    let response = pic
+        .query_call(
+            canister_id,
+            controller,
+            "get_audit_log",
+            encode_args((
+                Some("bulk_create_transactions".to_string()),
+                None::<String>,
+                None::<Principal>,
+                Some(10u64),
+            )).unwrap(),
+        )
+        .unwrap();
+    let logs: Vec<AuditLogEntry> = decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(logs.len(), 1, "One summary entry per batch");
+
+    // Oversized batches are rejected outright
+    let requests = vec![row(1); 501];
+    let response = pic
+        .update_call(canister_id, controller, "bulk_create_transactions", encode_one(requests).unwrap())
+        .unwrap();
+    let result: Result<BulkImportResult, String> = decode_one(&unwrap_wasm_result(response)).unwrap();
+    assert!(result.is_err());
+}
+
+// ============================================================================
+// Contact Status From Deal Outcome Tests
+// ============================================================================
+
+#[test]
+fn test_auto_status_from_deals_churns_contact_on_lost_deal() {
+    let (pic, canister_id, controller) = setup();
+
+    let response = pic
+        .update_call(canister_id, controller, "set_auto_status_from_deals", encode_one(true).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let contact = create_test_contact(&pic, canister_id, controller, "lost-deal@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Only deal".to_string(),
+        value: None,
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, controller, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let status = || -> ContactStatus {
+        let response = pic
+            .query_call(canister_id, controller, "get_contact", encode_one(contact.id).unwrap())
+            .unwrap();
+        decode_one::<Result<Option<ContactV2>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+            .expect("Contact should exist")
+            .status
+    };
+    assert_eq!(status(), ContactStatus::Active);
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "update_deal_stage",
+            encode_args((deal.id, DealStage::ClosedLost)).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    assert_eq!(status(), ContactStatus::Churned, "Losing the only deal should churn the contact");
+}
+
+// ============================================================================
+// Personal Dashboard Stats Tests
+// ============================================================================
+
+#[test]
+fn test_get_my_stats_counts_callers_records() {
+    let (pic, canister_id, controller) = setup();
+    let rep = test_principal(96);
+    add_admin_with_permissions(
+        &pic,
+        canister_id,
+        controller,
+        rep,
+        &[AdminPermission::ViewOwnContacts, AdminPermission::ViewOwnDeals],
+    );
+
+    let mine = create_test_contact(&pic, canister_id, rep, "my-stats@example.com");
+    create_test_contact(&pic, canister_id, controller, "not-my-stats@example.com");
+    for (name, value) in [("First open deal", 1_000u64), ("Second open deal", 2_500)] {
+        let request = CreateDealRequest {
+            contact_id: mine.id,
+            name: name.to_string(),
+            value: Some(value),
+            notes: None,
+            expected_close_date: None,
+        };
+        let response = pic
+            .update_call(canister_id, rep, "create_deal", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Rep should create a deal");
+    }
+
+    let stats = |caller: Principal| -> Result<MyStats, String> {
+        let response = pic
+            .query_call(canister_id, caller, "get_my_stats", encode_one(()).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert_eq!(
+        stats(rep).unwrap(),
+        MyStats { contacts: 1, deals: 2, open_deals: 2, closed_deals: 0, open_pipeline_value: 3_500 }
+    );
+    assert_eq!(stats(controller).unwrap().contacts, 2, "View All holders count every record");
+    assert!(stats(non_admin_principal()).is_err());
+}
+
+// ============================================================================
+// Anonymous Principal Guard Tests
+// ============================================================================
+
+#[test]
+fn test_anonymous_principal_cannot_be_granted_privileges() {
+    let (pic, canister_id, controller) = setup();
+    let anonymous = Principal::anonymous();
+    let expected = "Cannot grant privileges to the anonymous principal";
+
+    let call = |method: &str, arg: Vec<u8>| -> Result<(), String> {
+        let response = pic.update_call(canister_id, controller, method, arg).unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    assert_eq!(call("add_admin", encode_one(anonymous).unwrap()), Err(expected.to_string()));
+    assert_eq!(
+        call(
+            "register_authorized_canister",
+            encode_args(("user-service".to_string(), anonymous)).unwrap(),
+        ),
+        Err(expected.to_string())
+    );
+    assert_eq!(
+        call(
+            "grant_permission",
+            encode_args((anonymous, AdminPermission::ViewAllContacts)).unwrap(),
+        ),
+        Err(expected.to_string())
+    );
+
+    // init traps rather than installing with the anonymous principal as controller
+    let other = pic.create_canister();
+    pic.add_cycles(other, 2_000_000_000_000);
+    let wasm = std::fs::read(get_wasm_path()).expect("Failed to read WASM");
+    let installed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pic.install_canister(other, wasm, encode_one(Some(vec![anonymous])).unwrap(), None);
+    }));
+    assert!(installed.is_err(), "init must reject the anonymous principal");
+}
+
+// ============================================================================
+// High-Value Deal Approval Tests
+// ============================================================================
+
+#[test]
+fn test_high_value_deal_close_needs_second_admin() {
+    let (pic, canister_id, controller) = setup();
+    let owner = test_principal(97);
+    add_admin_with_permissions(
+        &pic,
+        canister_id,
+        controller,
+        owner,
+        &[
+            AdminPermission::ViewOwnContacts,
+            AdminPermission::ViewOwnDeals,
+            AdminPermission::EditOwnDeals,
+        ],
+    );
+
+    let response = pic
+        .update_call(
+            canister_id,
+            controller,
+            "set_deal_approval_threshold",
+            encode_one(Some(1_000_000u64)).unwrap(),
+        )
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let contact = create_test_contact(&pic, canister_id, owner, "big-deal@example.com");
+    let request = CreateDealRequest {
+        contact_id: contact.id,
+        name: "Enterprise plan".to_string(),
+        value: Some(2_500_000),
+        notes: None,
+        expected_close_date: None,
+    };
+    let response = pic
+        .update_call(canister_id, owner, "create_deal", encode_one(request).unwrap())
+        .unwrap();
+    let deal: Deal = decode_one::<Result<Deal, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+
+    let close_won = || -> Result<Deal, String> {
+        let response = pic
+            .update_call(
+                canister_id,
+                owner,
+                "update_deal_stage",
+                encode_args((deal.id, DealStage::ClosedWon)).unwrap(),
+            )
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+    let approve = |approver: Principal| -> Result<Vec<Principal>, String> {
+        let response = pic
+            .update_call(canister_id, approver, "approve_deal_close", encode_one(deal.id).unwrap())
+            .unwrap();
+        decode_one(&unwrap_wasm_result(response)).unwrap()
+    };
+
+    let err = close_won().expect_err("Owner alone cannot close a high-value deal");
+    assert!(err.contains("approve_deal_close"), "Unexpected error: {}", err);
+
+    approve(owner).expect("Owner may record an approval");
+    assert!(close_won().is_err(), "The owner's own approval does not count");
+
+    assert_eq!(approve(controller).unwrap(), vec![owner, controller]);
+    let closed = close_won().expect("Close should succeed after a second admin approves");
+    assert_eq!(closed.stage, DealStage::ClosedWon);
+}
+
+// ============================================================================
+// Compact Signup Audit Tests
+// ============================================================================
+
+#[test]
+fn test_compact_signup_audit_writes_one_entry_per_signup() {
+    let (pic, canister_id, controller) = setup();
+    let user_service_principal = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+
+    pic.update_call(
+        canister_id,
+        controller,
+        "register_authorized_canister",
+        encode_args(("user-service".to_string(), user_service_principal)).unwrap(),
+    )
+    .unwrap();
+
+    // Audit entries written by the user-service for one new signup
+    let signup_entries = |user_id: &str| -> Vec<AuditLogEntry> {
+        let request = CreateContactRequest {
+            user_id: Some(user_id.to_string()),
+            email: format!("{}@example.com", user_id),
+            name: None,
+            company: None,
+            job_title: None,
+            interest_area: None,
+            source: Some(ContactSource::Signup),
+            notes: None,
+        };
+        let response = pic
+            .update_call(canister_id, user_service_principal, "create_contact_from_signup", encode_one(request).unwrap())
+            .unwrap();
+        decode_one::<Result<ContactV2, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .expect("Signup should succeed");
+
+        let response = pic
+            .query_call(
+                canister_id,
+                controller,
+                "get_audit_log",
+                encode_args((None::<String>, None::<String>, Some(user_service_principal), Some(100u64))).unwrap(),
+            )
+            .unwrap();
+        decode_one::<Result<Vec<AuditLogEntry>, String>>(&unwrap_wasm_result(response))
+            .unwrap()
+            .unwrap()
+    };
+
+    let verbose = signup_entries("verbose-signup");
+    assert_eq!(verbose.len(), 2, "Off by default: contact and deal entries");
+
+    let response = pic
+        .update_call(canister_id, controller, "set_compact_signup_audit", encode_one(true).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+
+    let total = signup_entries("compact-signup");
+    assert_eq!(total.len() - verbose.len(), 1, "Compact mode writes a single entry");
+    let combined = total.iter().max_by_key(|e| e.id).unwrap();
+    assert_eq!(combined.action, "create_contact_from_signup");
+    assert!(combined.details.as_deref().unwrap_or_default().contains("deal_id"));
+
+    // With mirroring on, the combined entry still yields both CRM activities
+    let response = pic
+        .update_call(canister_id, controller, "set_mirror_crm_to_activity_log", encode_one(true).unwrap())
+        .unwrap();
+    decode_one::<Result<(), String>>(&unwrap_wasm_result(response)).unwrap().unwrap();
+    let before = signup_entries("mirrored-signup").len();
+    let steps = vec!["contact_created".to_string(), "deal_created".to_string()];
+    let response = pic
+        .query_call(canister_id, controller, "get_activity_funnel", encode_args((steps, 3600u64)).unwrap())
+        .unwrap();
+    let funnel: Vec<(String, u64)> = decode_one::<Result<Vec<(String, u64)>, String>>(&unwrap_wasm_result(response))
+        .unwrap()
+        .unwrap();
+    assert_eq!(funnel, vec![("contact_created".to_string(), 1), ("deal_created".to_string(), 1)]);
+
+    // Enriching a manually added contact is not a create: no combined entry
+    create_test_contact(&pic, canister_id, controller, "enriched-signup@example.com");
+    let after = signup_entries("enriched-signup");
+    let newest = after.iter().max_by_key(|e| e.id).unwrap();
+    assert_eq!(after.len() - before, 2, "Enrichment and its auto-deal are audited separately");
+    assert_ne!(newest.action, "create_contact_from_signup");
+}